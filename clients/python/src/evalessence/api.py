@@ -109,7 +109,7 @@ class Experiment(BaseModel):
     id: str
     name: str
     pipeline_id: str
-    dataset_version: int # table version in lancedb
+    dataset_version: int # version of the dataset the experiment ran on
     app_snapshot: App
     status: ExperimentStatus
 
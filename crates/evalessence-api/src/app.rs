@@ -1,57 +1,229 @@
+use crate::dataset::{DatasetService, OrderDirection};
 use anyhow;
 use async_trait::async_trait;
+use futures::stream::BoxStream;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
-#[serde(transparent)]
-pub struct AppId(pub String);
+// Ids are slugs: they are used in file names, urls and SQL identifiers, so only the
+// characters safe in all of them are accepted, when an id is created and when it is read
+const MAX_ID_LEN: usize = 128;
+const ID_PATTERN: &str = "^[A-Za-z0-9_-]{1,128}$";
 
-// Diplay impl for AppId to show only the inner string
-impl std::fmt::Display for AppId {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
-    }
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
-#[serde(transparent)]
-pub struct DatasetId(pub String);
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{kind} id '{id}' must be 1 to {MAX_ID_LEN} ASCII letters, digits, '-' or '_'")]
+pub struct InvalidId {
+    pub kind: &'static str,
+    pub id: String,
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
-#[serde(transparent)]
-pub struct EnvId(pub String);
+macro_rules! slug_id {
+    ($name:ident, $kind:literal) => {
+        #[derive(Debug, Clone, Serialize, JsonSchema, PartialEq, Eq, Hash)]
+        #[serde(transparent)]
+        pub struct $name(#[schemars(pattern(ID_PATTERN))] String);
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
-#[serde(transparent)]
-pub struct PipelineId(pub String);
+        impl $name {
+            /// # Errors
+            /// Returns [`InvalidId`] if `id` is not a slug of ASCII letters, digits, '-' or '_'
+            pub fn new(id: impl Into<String>) -> Result<Self, InvalidId> {
+                let id = id.into();
+                if is_valid_id(&id) {
+                    Ok(Self(id))
+                } else {
+                    Err(InvalidId { kind: $kind, id })
+                }
+            }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        // Display shows only the inner string
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = InvalidId;
+            fn from_str(id: &str) -> Result<Self, InvalidId> {
+                Self::new(id)
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = InvalidId;
+            fn try_from(id: String) -> Result<Self, InvalidId> {
+                Self::new(id)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        // ids read from config files are checked like the created ones
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Self::new(String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+slug_id!(AppId, "app");
+slug_id!(DatasetId, "dataset");
+slug_id!(EnvId, "env");
+slug_id!(PipelineId, "pipeline");
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 pub struct Dataset {
     pub id: DatasetId,
     pub name: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 pub struct Env {
     pub id: EnvId,
+    /// base url of the environment, ex: `http://localhost:8000`
+    #[schemars(url)]
     pub url: String,
     pub name: String,
+    /// headers sent with every call to the environment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<EnvHeader>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<EnvAuth>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct EnvHeader {
+    pub name: String,
+    pub value: HeaderValue,
+}
+
+/// A header value, written in the config file, or read from a secret when the env is called
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum HeaderValue {
+    Plain(String),
+    Secret(SecretRef),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EnvAuth {
+    /// `Authorization: Bearer <token>`
+    Bearer { token: SecretRef },
+    /// `<header>: <key>`
+    ApiKey { header: String, key: SecretRef },
+    /// `Authorization: Basic <base64 of username:password>`
+    Basic {
+        username: String,
+        password: SecretRef,
+    },
+}
+
+/// Where a secret is read from when the env is called.
+/// Only the reference is stored in the app config file, never the secret itself
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretRef {
+    /// name of an environment variable of the evalessence process
+    EnvVar(String),
+    /// key of the `.env` file of the config directory
+    SecretsFile(String),
+}
+
+impl std::fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EnvVar(name) => write!(f, "environment variable '{name}'"),
+            Self::SecretsFile(key) => write!(f, "key '{key}' of the secrets file"),
+        }
+    }
+}
+
+/// A resolved secret value, redacted when debugged or serialized
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub const fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    /// the actual value, to be used only to call the env
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+const REDACTED: &str = "<redacted>";
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// The headers to send to an env, with their secrets resolved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResolvedHeader {
+    pub name: String,
+    pub value: Secret,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct Pipeline {
     pub id: PipelineId,
     pub name: String,
+    /// path called on the env url, starting with '/'
+    #[schemars(pattern("^/"))]
     pub route: String,
     pub env_id: EnvId,
     pub dataset_id: DatasetId,
+    /// request body posted to the route to check it is up, instead of a HEAD or OPTIONS request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ping: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct App {
     pub id: AppId,
     pub name: String,
+    /// shared config file of the config directory whose envs, datasets and pipelines are
+    /// included in the app. They are listed with the app ones, which override them by id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub envs: Vec<Env>,
     pub datasets: Vec<Dataset>,
     pub pipelines: Vec<Pipeline>,
@@ -59,14 +231,395 @@ pub struct App {
     pub filename: String,
 }
 
+/// How the routes of the pipelines are probed, see [`AppService::probe_env`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RouteProbe {
+    Head,
+    Options,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeOptions {
+    /// also probe the route of each pipeline using the env, `None` to probe the env url only
+    pub routes: Option<RouteProbe>,
+    /// maximum duration of each request
+    pub timeout: Duration,
+    /// DER encoded certificates trusted with the public roots, ex: of an internal CA
+    pub root_certificates: Vec<Vec<u8>>,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        Self {
+            routes: None,
+            timeout: Duration::from_secs(10),
+            root_certificates: vec![],
+        }
+    }
+}
+
+/// The outcome of one request sent by [`AppService::probe_env`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Probe {
+    pub url: String,
+    /// an HTTP response has been received, whatever its status
+    pub reachable: bool,
+    pub status: Option<u16>,
+    /// time until the response headers are received
+    pub latency: Option<Duration>,
+    /// `None` for plain http urls
+    pub tls: Option<TlsInfo>,
+    /// why the env could not be reached
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsInfo {
+    /// DER encoded certificate presented by the server
+    pub peer_certificate: Option<Vec<u8>>,
+    /// end of the validity period of the certificate
+    pub not_after: Option<SystemTime>,
+    /// negotiated protocol version, ex: `TLSv1_3`
+    pub protocol_version: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvHealth {
+    pub env_id: EnvId,
+    pub env: Probe,
+    /// in the order of the pipelines of the app
+    pub routes: Vec<(PipelineId, Probe)>,
+}
+
+/// A change of an app config file, emitted by [`AppService::watch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppEvent {
+    Created {
+        id: AppId,
+        filename: String,
+        etag: String,
+    },
+    Modified {
+        id: AppId,
+        filename: String,
+        etag: String,
+    },
+    Deleted {
+        id: AppId,
+        filename: String,
+    },
+}
+
+pub type AppEventStream = BoxStream<'static, AppResult<AppEvent>>;
+
+/// A saved version of an app, identified by the etag it had
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppRevision {
+    pub etag: String,
+    /// when this version was written
+    pub saved_at: SystemTime,
+}
+
+/// An app moved to the trash by [`AppService::delete`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashedApp {
+    /// identifies the deleted file in the trash, an app deleted several times has several ids
+    pub trash_id: String,
+    pub id: AppId,
+    /// `None` if the deleted file can't be loaded
+    pub name: Option<String>,
+    /// name of the file before its deletion, and after its restoration
+    pub filename: String,
+    pub deleted_at: SystemTime,
+}
+
+/// An app as listed by [`AppService::list_headers`], without its envs, datasets and pipelines
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppHeader {
+    pub id: AppId,
+    pub name: String,
+    pub filename: String,
+    /// the counts include the entities of the file the app `extends`
+    pub env_count: usize,
+    pub dataset_count: usize,
+    pub pipeline_count: usize,
+    /// last modification of the app config file
+    pub modified: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AppOrder {
+    /// by name, then by id for apps with the same name
+    #[default]
+    Name,
+    Id,
+    Modified,
+}
+
+/// Which app headers [`AppService::list_headers`] returns, and in which order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppListOptions {
+    pub order_by: AppOrder,
+    pub direction: OrderDirection,
+    /// keep only the apps whose name contains this text, ignoring case
+    pub name_filter: Option<String>,
+    /// number of matching apps to skip
+    pub offset: usize,
+    /// maximum number of headers to return, all the remaining ones if `None`
+    pub limit: Option<usize>,
+}
+
+impl Default for AppListOptions {
+    fn default() -> Self {
+        Self {
+            order_by: AppOrder::default(),
+            direction: OrderDirection::Asc,
+            name_filter: None,
+            offset: 0,
+            limit: None,
+        }
+    }
+}
+
+/// A page of app headers
+#[derive(Debug)]
+pub struct AppHeaderPage {
+    pub headers: Vec<AppHeader>,
+    /// number of apps matching the filter, over all the pages
+    pub total_count: usize,
+    /// the app config files that can't be loaded, returned with every page
+    pub errors: Vec<AppError>,
+}
+
+/// A difference between two versions of an app, located by its field path.
+/// Entities are matched by id, ex: `pipelines[chat].route`, `old` is `None` for an added entity
+/// and `new` is `None` for a removed one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppChange {
+    pub path: String,
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+}
+
+/// What changed an app, in an [`AuditEntry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// by `create`, `duplicate`, `import` or `restore`
+    Create,
+    /// by `update`, `revert` or an operation on the entities of the app
+    Update,
+    Delete,
+}
+
+/// A change of an app, as recorded in the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub app_id: AppId,
+    pub action: AuditAction,
+    /// who made the change, as set on the service
+    pub actor: String,
+    pub timestamp: SystemTime,
+    /// `None` for a created app
+    pub old_etag: Option<String>,
+    /// `None` for a deleted app
+    pub new_etag: Option<String>,
+    /// every field is added by a creation and removed by a deletion
+    pub changes: Vec<AppChange>,
+}
+
+/// Which entries [`AppService::audit_log`] returns, `None` filters nothing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditQuery {
+    pub app_id: Option<AppId>,
+    /// entries at or after this time
+    pub since: Option<SystemTime>,
+    /// entries before this time
+    pub until: Option<SystemTime>,
+}
+
+/// A field modified differently by an update and by the version saved since the update was
+/// loaded, located like [`AppChange`]. `None` values are entities absent from a version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldConflict {
+    pub path: String,
+    /// value in the version the update was made from
+    pub base: Option<serde_json::Value>,
+    /// value in the update
+    pub ours: Option<serde_json::Value>,
+    /// value in the current version
+    pub theirs: Option<serde_json::Value>,
+}
+
+fn conflict_paths(conflicts: &[FieldConflict]) -> String {
+    conflicts
+        .iter()
+        .map(|c| c.path.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The kind of entity stored in an app, used to report errors on a specific entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntityKind {
+    Env,
+    Dataset,
+    Pipeline,
+}
+
+impl std::fmt::Display for EntityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Env => f.write_str("Env"),
+            Self::Dataset => f.write_str("Dataset"),
+            Self::Pipeline => f.write_str("Pipeline"),
+        }
+    }
+}
+
 #[async_trait]
 pub trait AppService: Send + Sync {
     /// load all apps in the config directory with the format app-{id}.yaml
     async fn list(&self) -> AppResult<Vec<AppResult<App>>>;
+    /// the headers of the apps in the config directory, filtered, sorted and paginated
+    /// according to `options`
+    async fn list_headers(&self, options: AppListOptions) -> AppResult<AppHeaderPage>;
     async fn create(&self, name: String) -> AppResult<App>;
-    async fn get(&self, filename: String) -> AppResult<App>;
-    async fn delete(&self, filename: String) -> AppResult<()>;
+    /// fails with [`AppError::NotFound`] if no file has this id, or [`AppError::DuplicateId`]
+    /// if several files claim it. The entities of the file the app `extends` are included,
+    /// and its etag covers both files
+    async fn get(&self, id: AppId) -> AppResult<App>;
+    /// move the app file to the trash, from where it can be restored until it is purged
+    async fn delete(&self, id: AppId) -> AppResult<()>;
+    /// save `app`. If the file has been modified since `app.etag`, the changes of both versions
+    /// are merged by entity id, and the update fails with [`AppError::MergeConflict`] if they
+    /// modify the same fields (or [`AppError::Conflict`] if the version of `app.etag` is unknown).
+    /// Only the entities that differ from the ones of the file the app `extends` are written
     async fn update(&self, app: App) -> AppResult<App>;
+    /// copy an app under a new name, with a new id and filename.
+    /// Envs and pipelines keep their ids, datasets get new ones with a copy of their content
+    async fn duplicate(
+        &self,
+        app_id: AppId,
+        name: String,
+        datasets: &dyn DatasetService,
+    ) -> AppResult<App>;
+    /// write an app and the content of its datasets into a single `archive` file
+    async fn export(
+        &self,
+        app_id: AppId,
+        datasets: &dyn DatasetService,
+        archive: PathBuf,
+    ) -> AppResult<()>;
+    /// create an app and its datasets from an `archive` written by [`AppService::export`].
+    /// The app id and dataset ids already in use are replaced by new ones
+    async fn import(&self, archive: PathBuf, datasets: &dyn DatasetService) -> AppResult<App>;
+    /// stream the changes of the app config files until the stream is dropped
+    ///
+    /// # Errors
+    /// Returns [`AppError::Internal`] if the config directory can't be watched
+    fn watch(&self) -> AppResult<AppEventStream>;
+
+    /// the app with the `${VAR}` and `${VAR:-default}` placeholders of its values replaced by
+    /// the variables of the environment or of the `.env` file, to be used to call its envs.
+    /// [`AppService::get`] returns the placeholders as written, to be saved back by `update`.
+    /// Fails with [`AppError::UnresolvedVariable`] if a variable without default is not set
+    async fn resolve_app(&self, app_id: AppId) -> AppResult<App>;
+
+    /// the headers of the env `env_id`, auth included, with their secrets read now.
+    /// Fails with [`AppError::UnresolvedSecret`] if a secret is not set
+    async fn resolve_env_headers(
+        &self,
+        app_id: AppId,
+        env_id: EnvId,
+    ) -> AppResult<Vec<ResolvedHeader>>;
+
+    /// check that the env `env_id` is up before using it, with its headers and secrets.
+    /// Unreachable urls are reported in the result, not as errors
+    async fn probe_env(
+        &self,
+        app_id: AppId,
+        env_id: EnvId,
+        options: ProbeOptions,
+    ) -> AppResult<EnvHealth>;
+
+    // Trash: deleted apps are kept until they are purged, or until the retention period
+    // of the service has elapsed.
+    /// the deleted apps, most recently deleted first
+    async fn list_trash(&self) -> AppResult<Vec<TrashedApp>>;
+    /// move a deleted app back to its file. Fails with [`AppError::TrashNotFound`] if it is not
+    /// in the trash, or [`AppError::AlreadyExists`] if its id or filename is used again
+    async fn restore(&self, trash_id: String) -> AppResult<App>;
+    /// remove a deleted app for good, with its revisions if no other app has its id
+    async fn purge(&self, trash_id: String) -> AppResult<()>;
+
+    // Revision history: every update keeps the version it replaces, identified by its etag.
+    /// every version of the app, newest first: the current one, then the replaced ones
+    async fn list_revisions(&self, app_id: AppId) -> AppResult<Vec<AppRevision>>;
+    /// the app as it was at revision `etag`,
+    /// fails with [`AppError::RevisionNotFound`] if the app never had this etag
+    async fn get_revision(&self, app_id: AppId, etag: String) -> AppResult<App>;
+    /// the changes from revision `from` to revision `to`
+    async fn diff_revisions(
+        &self,
+        app_id: AppId,
+        from: String,
+        to: String,
+    ) -> AppResult<Vec<AppChange>>;
+    /// write back the content of `revision` as a new version.
+    /// `etag` must match the current version of the file, no merge is attempted
+    async fn revert(&self, app_id: AppId, etag: String, revision: String) -> AppResult<App>;
+
+    // Audit log: every change of an app is appended to a log that is never rewritten.
+    /// the entries of the audit log matching `query`, oldest first
+    async fn audit_log(&self, query: AuditQuery) -> AppResult<Vec<AuditEntry>>;
+
+    // Granular operations on the entities of an app.
+    // `etag` must match the current version of the file, like for `update`.
+    async fn add_env(
+        &self,
+        app_id: AppId,
+        etag: String,
+        name: String,
+        url: String,
+    ) -> AppResult<App>;
+    async fn update_env(&self, app_id: AppId, etag: String, env: Env) -> AppResult<App>;
+    /// fails with [`AppError::StillReferenced`] if a pipeline uses the env
+    async fn remove_env(&self, app_id: AppId, etag: String, env_id: EnvId) -> AppResult<App>;
+
+    async fn add_dataset(&self, app_id: AppId, etag: String, name: String) -> AppResult<App>;
+    async fn update_dataset(&self, app_id: AppId, etag: String, dataset: Dataset)
+    -> AppResult<App>;
+    /// fails with [`AppError::StillReferenced`] if a pipeline uses the dataset
+    async fn remove_dataset(
+        &self,
+        app_id: AppId,
+        etag: String,
+        dataset_id: DatasetId,
+    ) -> AppResult<App>;
+
+    /// fails with [`AppError::EntityNotFound`] if `env_id` or `dataset_id` does not exist in the app
+    async fn add_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        name: String,
+        route: String,
+        env_id: EnvId,
+        dataset_id: DatasetId,
+    ) -> AppResult<App>;
+    async fn update_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        pipeline: Pipeline,
+    ) -> AppResult<App>;
+    async fn remove_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        pipeline_id: PipelineId,
+    ) -> AppResult<App>;
 }
 
 #[derive(Error, Debug)]
@@ -78,15 +631,69 @@ pub enum AppError {
         source: anyhow::Error,
     },
 
+    #[error("App '{id}' not found")]
+    NotFound { id: AppId },
+
+    #[error("App '{id}' is defined by several config files: {filenames:?}")]
+    DuplicateId { id: AppId, filenames: Vec<String> },
+
+    #[error("App config file '{filename}' has id '{id}', it should be named 'app-{id}.yaml'")]
+    IdMismatch { filename: String, id: AppId },
+
+    #[error("App '{id}' already exists in config file '{filename}'")]
+    AlreadyExists { id: AppId, filename: String },
+
+    #[error("No deleted app with trash id '{trash_id}'")]
+    TrashNotFound { trash_id: String },
+
+    #[error("App '{id}' has no revision with etag '{etag}'")]
+    RevisionNotFound { id: AppId, etag: String },
+
     #[error("App config file '{filename}' has been modified externally, please reload it")]
     Conflict { filename: String },
 
+    #[error(
+        "App config file '{filename}' has been modified on the same fields: {}",
+        conflict_paths(conflicts)
+    )]
+    MergeConflict {
+        filename: String,
+        conflicts: Vec<FieldConflict>,
+    },
+
+    #[error("App config file '{filename}' uses variable '{variable}' in {path}, which is not set")]
+    UnresolvedVariable {
+        filename: String,
+        path: String,
+        variable: String,
+    },
+
+    #[error("Env '{env_id}' uses {secret}, which is not set")]
+    UnresolvedSecret { env_id: EnvId, secret: SecretRef },
+
     #[error("Internal service error: {source}")]
     Internal {
         #[source]
         source: anyhow::Error,
     },
 
+    #[error("{kind} '{id}' not found in App config file '{filename}'")]
+    EntityNotFound {
+        filename: String,
+        kind: EntityKind,
+        id: String,
+    },
+
+    #[error(
+        "{kind} '{id}' of App config file '{filename}' is still used by pipelines {pipeline_ids:?}"
+    )]
+    StillReferenced {
+        filename: String,
+        kind: EntityKind,
+        id: String,
+        pipeline_ids: Vec<PipelineId>,
+    },
+
     #[error(
         "App config file '{filename}' could not be loaded bacause of an invalid format: {source}"
     )]
@@ -98,3 +705,27 @@ pub enum AppError {
 }
 
 pub type AppResult<T> = Result<T, AppError>;
+
+/// A semantic problem found in an app config, located by its field path (ex: `pipelines[2].env_id`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub path: String,
+    pub message: String,
+}
+
+/// Every problem found while validating an app config,
+/// used as the source of [`AppError::ValidationError`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssues(pub Vec<ValidationIssue>);
+
+impl std::fmt::Display for ValidationIssues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, issue) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: {}", issue.path, issue.message)?;
+        }
+        Ok(())
+    }
+}
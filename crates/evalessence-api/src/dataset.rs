@@ -1,6 +1,9 @@
+use crate::app::DatasetId;
 use anyhow;
 use arrow::array::StringArray;
 use arrow::record_batch::RecordBatchReader;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -13,47 +16,235 @@ pub enum DatasetError {
         #[source]
         source: anyhow::Error,
     },
+
+    #[error("Version {version} of dataset {dataset_id} not found")]
+    VersionNotFound { dataset_id: DatasetId, version: u64 },
     // Add other variants as needed
 }
 
 pub type Result<T> = std::result::Result<T, DatasetError>;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum OrderDirection {
     Asc,
     Desc,
 }
 
+/// A column of a dataset, or a value inside a JSON column
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilterField {
+    pub column: String,
+    /// keys (or array indexes) of the value inside the JSON of `column`, the column itself
+    /// if empty. ex: `["metadata", "tags", "0"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub json_path: Vec<String>,
+}
+
+impl FilterField {
+    #[must_use]
+    pub fn column(column: impl Into<String>) -> Self {
+        Self {
+            column: column.into(),
+            json_path: vec![],
+        }
+    }
+
+    /// the value at `path` inside the JSON of `column`
+    #[must_use]
+    pub fn json<S: Into<String>>(
+        column: impl Into<String>,
+        path: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self {
+            column: column.into(),
+            json_path: path.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// A value compared to the values of a field.
+/// Values inside JSON are converted to the type of the value they are compared to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FilterValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl From<bool> for FilterValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i64> for FilterValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<f64> for FilterValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<&str> for FilterValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for FilterValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Condition on the rows of a dataset, for [`DatasetService::select`] and [`Delete::Where`].
+/// The values are passed to the database as parameters, never written into the query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    Compare {
+        field: FilterField,
+        op: CompareOp,
+        value: FilterValue,
+    },
+    /// the field is equal to one of `values`, never true if there is none
+    In {
+        field: FilterField,
+        values: Vec<FilterValue>,
+    },
+    /// `low <= field <= high`
+    Between {
+        field: FilterField,
+        low: FilterValue,
+        high: FilterValue,
+    },
+    IsNull(FilterField),
+    IsNotNull(FilterField),
+    /// true if there is no filter
+    And(Vec<Filter>),
+    /// false if there is no filter
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+    /// a SQL condition written as is into the query, it must never contain user input.
+    /// It can't be deserialized, so it can't be received from a client
+    #[serde(skip)]
+    RawSql(String),
+}
+
 pub enum Delete {
     ByIds(StringArray),
-    Where(String),
+    Where(Filter),
 }
 
 pub type SendableRecordBatchReader = Box<dyn RecordBatchReader + Send + Sync>;
 
+/// Where a page of [`DatasetService::select_page`] ends, to select the next one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub filter: Option<Filter>,
+    pub order_by: Option<(String, OrderDirection)>,
+    /// value of the `order_by` column in the last row of the page, `None` if it is null
+    pub last_value: Option<FilterValue>,
+    /// id of the last row of the page
+    pub last_id: String,
+}
+
+pub struct SamplePage {
+    pub items: SendableRecordBatchReader,
+    /// to select the next page, `None` if there are no more rows
+    pub cursor: Option<Cursor>,
+    /// number of rows matching the filter, in every page
+    pub total_count: usize,
+}
+
+/// What the update creating a version did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatasetOperation {
+    /// the first upsert of the dataset
+    Create,
+    Update,
+}
+
+/// An immutable version of a dataset, created by each [`DatasetService::update`] changing rows
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatasetVersion {
+    /// 1 for the first version, then incremented by every update
+    pub version: u64,
+    pub created_at: SystemTime,
+    pub operation: DatasetOperation,
+    /// rows inserted or replaced by the update
+    pub upserted_rows: usize,
+    /// rows removed by the update
+    pub deleted_rows: usize,
+    /// rows of the dataset at this version
+    pub row_count: usize,
+}
+
+/// Which versions [`DatasetService::compact`] keeps, the latest one is always kept
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionRetention {
+    /// number of most recent versions kept
+    pub keep_last: usize,
+    /// versions created at or after this time are kept
+    pub keep_since: Option<SystemTime>,
+    /// versions kept whatever their age, ex: the versions experiments ran on
+    pub keep_versions: Vec<u64>,
+}
+
 pub trait DatasetService: Send + Sync {
-    /// Update a dataset with upsert and/or delete operations
+    /// Check if a dataset has been created by a first [`DatasetService::update`]
+    ///
+    /// # Errors
+    /// Returns [`DatasetError::Internal`] if an internal service error occurs.
+    fn exists(&self, dataset_id: DatasetId) -> Result<bool>;
+
+    /// Update a dataset with upsert and/or delete operations.
+    /// The dataset is created by the first upsert, rows are matched on their `id` column.
+    /// The previous versions are kept unchanged, until they are removed by
+    /// [`DatasetService::compact`].
     ///
     /// # Arguments
     /// * `dataset_id` - The identifier of the dataset to update
     /// * `upsert` - Optional record batch reader containing rows to insert or update
-    /// * `delete` - Optional delete specification (by IDs or WHERE clause)
+    /// * `delete` - Optional delete specification (by IDs or filter)
+    ///
+    /// # Returns
+    /// The version created by the update, `None` if it upserted and deleted no row
     ///
     /// # Errors
     /// Returns a [`DatasetError::ArrowError`] if an Arrow operation fails, or
     /// [`DatasetError::Internal`] if an internal service error occurs.
     fn update(
         &self,
-        dataset_id: String,
+        dataset_id: DatasetId,
         upsert: Option<SendableRecordBatchReader>,
         delete: Option<Delete>,
-    ) -> Result<()>;
+    ) -> Result<Option<DatasetVersion>>;
 
-    /// Select data from a dataset
+    /// Select data from the latest version of a dataset
     ///
     /// # Arguments
     /// * `dataset_id` - The identifier of the dataset to query
-    /// * `where_clause` - Optional SQL WHERE clause filter
+    /// * `filter` - Optional condition on the rows to return
     /// * `order_by` - Optional list of (column, direction) pairs for sorting
     /// * `limit` - Optional maximum number of rows to return
     /// * `offset` - Optional number of rows to skip
@@ -66,10 +257,84 @@ pub trait DatasetService: Send + Sync {
     /// [`DatasetError::Internal`] if an internal service error occurs.
     fn select(
         &self,
-        dataset_id: String,
-        where_clause: Option<String>,
+        dataset_id: DatasetId,
+        filter: Option<Filter>,
         order_by: Option<Vec<(String, OrderDirection)>>,
         limit: Option<usize>,
         offset: Option<usize>,
     ) -> Result<SendableRecordBatchReader>;
+
+    /// Select the first page of the rows matching `filter`, sorted by `order_by` then by id.
+    /// Each page starts after the last row of the previous one instead of skipping the rows
+    /// before it, so rows upserted or deleted meanwhile don't shift the next pages.
+    ///
+    /// # Arguments
+    /// * `dataset_id` - The identifier of the dataset to query
+    /// * `filter` - Optional condition on the rows to return
+    /// * `order_by` - Optional column and direction, rows with a null value come last
+    /// * `limit` - Optional maximum number of rows of the page, every row if `None`
+    ///
+    /// # Errors
+    /// Returns a [`DatasetError::ArrowError`] if an Arrow operation fails, or
+    /// [`DatasetError::Internal`] if an internal service error occurs, or if the `order_by`
+    /// column is not a boolean, number or string.
+    fn select_page(
+        &self,
+        dataset_id: DatasetId,
+        filter: Option<Filter>,
+        order_by: Option<(String, OrderDirection)>,
+        limit: Option<usize>,
+    ) -> Result<SamplePage>;
+
+    /// Select the page after `cursor`, returned with the previous page
+    ///
+    /// # Errors
+    /// Same as [`DatasetService::select_page`].
+    fn select_next(
+        &self,
+        dataset_id: DatasetId,
+        cursor: Cursor,
+        limit: Option<usize>,
+    ) -> Result<SamplePage>;
+
+    /// The versions of a dataset, newest first, empty if it has never been created
+    ///
+    /// # Errors
+    /// Returns [`DatasetError::Internal`] if an internal service error occurs.
+    fn list_versions(&self, dataset_id: DatasetId) -> Result<Vec<DatasetVersion>>;
+
+    /// Select data from a dataset as it was at `version`, like [`DatasetService::select`]
+    ///
+    /// # Errors
+    /// Returns [`DatasetError::VersionNotFound`] if the dataset never had this version or if it
+    /// has been removed by [`DatasetService::compact`], or the errors of
+    /// [`DatasetService::select`].
+    fn select_version(
+        &self,
+        dataset_id: DatasetId,
+        version: u64,
+        filter: Option<Filter>,
+        order_by: Option<Vec<(String, OrderDirection)>>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<SendableRecordBatchReader>;
+
+    /// Remove the versions of a dataset not kept by `retention`
+    ///
+    /// # Returns
+    /// The removed versions, newest first
+    ///
+    /// # Errors
+    /// Returns [`DatasetError::Internal`] if an internal service error occurs.
+    fn compact(
+        &self,
+        dataset_id: DatasetId,
+        retention: VersionRetention,
+    ) -> Result<Vec<DatasetVersion>>;
+
+    /// Remove a dataset and all its versions, nothing is done if it doesn't exist
+    ///
+    /// # Errors
+    /// Returns [`DatasetError::Internal`] if an internal service error occurs.
+    fn remove(&self, dataset_id: DatasetId) -> Result<()>;
 }
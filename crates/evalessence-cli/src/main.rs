@@ -0,0 +1,76 @@
+use evalessence_core::app_core::app_config_schema;
+use evalessence_core::app_db::DuckDbAppService;
+use std::fmt::Display;
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: evalessence <command>
+
+commands:
+  app-schema                        print the JSON Schema of app-*.yaml files
+  db-import <config_dir> <database>  copy the apps of a config dir into a database
+  db-export <database> <config_dir>  write the apps of a database as files of a config dir";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["app-schema"] => {
+            // `#` pretty prints the json
+            println!("{:#}", app_config_schema());
+            ExitCode::SUCCESS
+        }
+        ["db-import", config_dir, database] => {
+            let migrated = match DuckDbAppService::new(database) {
+                Ok(db) => db.import_config_dir(Path::new(config_dir)).await,
+                Err(e) => Err(e),
+            };
+            let (apps, trash) = match migrated {
+                Ok(migrated) => (Ok(migrated.apps), Ok(migrated.trash)),
+                Err(e) => (Err(e), Ok(vec![])),
+            };
+            let apps = report(apps, "imported app");
+            let trash = report(trash, "imported deleted app");
+            if apps == ExitCode::SUCCESS {
+                trash
+            } else {
+                apps
+            }
+        }
+        ["db-export", database, config_dir] => {
+            let migrated = match DuckDbAppService::new(database) {
+                Ok(db) => db.export_config_dir(Path::new(config_dir)).await,
+                Err(e) => Err(e),
+            };
+            report(migrated, "exported")
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Print what has been migrated and why the rest hasn't, failing if anything is left behind
+fn report<T: Display, E: Display>(migrated: Result<Vec<Result<T, E>>, E>, done: &str) -> ExitCode {
+    let migrated = match migrated {
+        Ok(migrated) => migrated,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut status = ExitCode::SUCCESS;
+    for result in migrated {
+        match result {
+            Ok(item) => println!("{done} {item}"),
+            Err(e) => {
+                eprintln!("error: {e}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
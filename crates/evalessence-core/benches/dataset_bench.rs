@@ -0,0 +1,107 @@
+// remove lints that do not make sense in benchmarks
+#![allow(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+// Per-call latency of the dataset service on a 1M-row dataset, run with
+// `cargo bench -p evalessence-core --bench dataset_bench`
+
+use arrow::array::{Int64Array, RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatchIterator;
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use evalessence_api::app::DatasetId;
+use evalessence_api::dataset::{
+    DatasetService, OrderDirection, SendableRecordBatchReader, VersionRetention,
+};
+use evalessence_core::datatset_core::DuckDbDatasetService;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::Duration;
+
+const ROWS: i64 = 1_000_000;
+// the arrow scan used by upserts reads at most one DuckDB vector per batch
+const BATCH_ROWS: i64 = 2048;
+
+fn rows(start: i64, end: i64) -> SendableRecordBatchReader {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("input", DataType::Utf8, true),
+        Field::new("score", DataType::Int64, true),
+    ]));
+    let batches: Vec<_> = (start..end)
+        .step_by(usize::try_from(BATCH_ROWS).unwrap())
+        .map(|first| {
+            let numbers: Vec<i64> = (first..end.min(first + BATCH_ROWS)).collect();
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(StringArray::from_iter_values(
+                        numbers.iter().map(|n| format!("sample-{n:07}")),
+                    )),
+                    Arc::new(StringArray::from_iter_values(
+                        numbers
+                            .iter()
+                            .map(|n| format!("the input of the sample {n}")),
+                    )),
+                    Arc::new(Int64Array::from_iter_values(
+                        numbers.iter().map(|n| n % 100),
+                    )),
+                ],
+            )
+        })
+        .collect();
+    Box::new(RecordBatchIterator::new(batches, schema))
+}
+
+fn dataset_calls(c: &mut Criterion) {
+    let td = tempfile::tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let dataset_id = DatasetId::new("large").unwrap();
+    svc.update(dataset_id.clone(), Some(rows(0, ROWS)), None)
+        .unwrap();
+
+    let mut group = c.benchmark_group("1M rows");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(20));
+
+    group.bench_function("select 10 rows", |b| {
+        b.iter(|| {
+            let reader = svc
+                .select(
+                    dataset_id.clone(),
+                    None,
+                    Some(vec![("id".to_string(), OrderDirection::Asc)]),
+                    Some(10),
+                    None,
+                )
+                .unwrap();
+            black_box(reader.count())
+        });
+    });
+    group.bench_function("select page of 100 rows", |b| {
+        b.iter(|| {
+            let page = svc
+                .select_page(dataset_id.clone(), None, None, Some(100))
+                .unwrap();
+            black_box((page.items.count(), page.total_count))
+        });
+    });
+    group.bench_function("upsert 1 row", |b| {
+        // every upsert saves a delta of its row, the previous versions are removed between them
+        // so the deltas don't pile up
+        b.iter_batched(
+            || {
+                svc.compact(dataset_id.clone(), VersionRetention::default())
+                    .unwrap();
+            },
+            |()| {
+                svc.update(dataset_id.clone(), Some(rows(0, 1)), None)
+                    .unwrap()
+            },
+            BatchSize::PerIteration,
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(benches, dataset_calls);
+criterion_main!(benches);
@@ -0,0 +1,272 @@
+use crate::app_core::{AppConfig, FileAppService, internal};
+use arrow::error::ArrowError;
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::{RecordBatch, RecordBatchIterator};
+use evalessence_api::app::{App, AppError, AppId, AppResult, DatasetId};
+use evalessence_api::dataset::{DatasetService, SendableRecordBatchReader};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tokio::task;
+
+// An exported app is a tar archive with:
+// - app.yaml: the app config, in the same format as app-{id}.yaml
+// - datasets/{dataset_id}.arrow: the content of each dataset, as an Arrow IPC stream
+const CONFIG_ENTRY: &str = "app.yaml";
+const DATASETS_DIR: &str = "datasets/";
+const DATASET_EXTENSION: &str = ".arrow";
+
+/// (dataset id, Arrow IPC stream) of a dataset to import
+pub type DatasetContent = (DatasetId, Vec<u8>);
+
+pub struct AppArchive {
+    pub config_yaml: Vec<u8>,
+    /// (dataset id, Arrow IPC stream)
+    pub datasets: Vec<(String, Vec<u8>)>,
+}
+
+pub fn write_archive(path: &Path, archive: &AppArchive) -> io::Result<()> {
+    let mut builder = tar::Builder::new(File::create(path)?);
+
+    append_entry(&mut builder, CONFIG_ENTRY, &archive.config_yaml)?;
+    for (dataset_id, ipc) in &archive.datasets {
+        let entry = format!("{DATASETS_DIR}{dataset_id}{DATASET_EXTENSION}");
+        append_entry(&mut builder, &entry, ipc)?;
+    }
+
+    builder.into_inner()?.sync_all()
+}
+
+fn append_entry(builder: &mut tar::Builder<File>, path: &str, data: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)
+}
+
+pub fn read_archive(path: &Path) -> io::Result<AppArchive> {
+    let mut config_yaml = None;
+    let mut datasets = Vec::new();
+
+    for entry in tar::Archive::new(File::open(path)?).entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+
+        if entry_path == CONFIG_ENTRY {
+            config_yaml = Some(data);
+        } else if let Some(dataset_id) = entry_path
+            .strip_prefix(DATASETS_DIR)
+            .and_then(|name| name.strip_suffix(DATASET_EXTENSION))
+        {
+            datasets.push((dataset_id.to_string(), data));
+        }
+    }
+
+    let config_yaml = config_yaml.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("archive has no {CONFIG_ENTRY} entry"),
+        )
+    })?;
+
+    Ok(AppArchive {
+        config_yaml,
+        datasets,
+    })
+}
+
+pub fn to_ipc(reader: SendableRecordBatchReader) -> Result<Vec<u8>, ArrowError> {
+    let mut writer = StreamWriter::try_new(Vec::new(), &reader.schema())?;
+    for batch in reader {
+        writer.write(&batch?)?;
+    }
+    writer.into_inner()
+}
+
+pub fn from_ipc(ipc: &[u8]) -> Result<SendableRecordBatchReader, ArrowError> {
+    let reader = StreamReader::try_new(ipc, None)?;
+    let schema = reader.schema();
+    let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>()?;
+
+    Ok(Box::new(RecordBatchIterator::new(
+        batches.into_iter().map(Ok),
+        schema,
+    )))
+}
+
+// Write `app` and the content of its datasets into `archive`
+pub async fn export_app(
+    app: App,
+    datasets: &dyn DatasetService,
+    archive: PathBuf,
+) -> AppResult<()> {
+    let mut dataset_contents = Vec::new();
+    for dataset in &app.datasets {
+        let id = dataset.id.clone();
+        // datasets never filled have no content to export
+        if datasets.exists(id.clone()).map_err(internal)? {
+            let reader = datasets
+                .select(id.clone(), None, None, None, None)
+                .map_err(internal)?;
+            dataset_contents.push((id.into(), to_ipc(reader).map_err(internal)?));
+        }
+    }
+
+    // the archive is self-contained: the shared entities are written with the app ones
+    let config = AppConfig {
+        extends: None,
+        ..AppConfig::from(app)
+    };
+    let config_yaml = serde_saphyr::to_string(&config).map_err(internal)?;
+    let content = AppArchive {
+        config_yaml: config_yaml.into_bytes(),
+        datasets: dataset_contents,
+    };
+
+    let archive_name = archive.display().to_string();
+    task::spawn_blocking(move || write_archive(&archive, &content))
+        .await
+        .map_err(internal)?
+        .map_err(|e| AppError::FileIoError {
+            filename: archive_name,
+            source: e.into(),
+        })
+}
+
+// The content of `archive`, with its name to report errors
+pub async fn read_app_archive(archive: PathBuf) -> AppResult<(AppArchive, String)> {
+    let archive_name = archive.display().to_string();
+    let content = task::spawn_blocking(move || read_archive(&archive))
+        .await
+        .map_err(internal)?
+        .map_err(|e| AppError::FileIoError {
+            filename: archive_name.clone(),
+            source: e.into(),
+        })?;
+    Ok((content, archive_name))
+}
+
+// Give an imported app new ids where needed, nothing is written. The app gets a new id if its
+// id is used by one of `apps` or is `taken`, and so do the datasets whose id is used by `apps`
+// or already filled. Returns the content of the datasets of the archive, under their new id
+pub fn import_ids(
+    mut config: AppConfig,
+    archive_datasets: Vec<(String, Vec<u8>)>,
+    datasets: &dyn DatasetService,
+    apps: &[App],
+    taken: impl Fn(&AppId) -> bool,
+) -> AppResult<(AppConfig, Vec<DatasetContent>)> {
+    if taken(&config.id) || apps.iter().any(|a| a.id == config.id) {
+        config.id = FileAppService::generate_id(&config.name)?;
+    }
+
+    let used_dataset_ids: HashSet<&DatasetId> = apps
+        .iter()
+        .flat_map(|a| a.datasets.iter().map(|d| &d.id))
+        .collect();
+    let new_dataset_ids = renew_dataset_ids(&mut config, |id| {
+        Ok(used_dataset_ids.contains(id) || datasets.exists(id.clone()).map_err(internal)?)
+    })?;
+
+    let mut contents = vec![];
+    for (id, ipc) in archive_datasets {
+        // entries of the archive that are not valid dataset ids are not datasets of the app
+        let Ok(id) = DatasetId::new(id) else {
+            continue;
+        };
+        let id = new_dataset_ids.get(&id).cloned().unwrap_or(id);
+        if config.datasets.iter().any(|d| d.id == id) {
+            contents.push((id, ipc));
+        }
+    }
+    Ok((config, contents))
+}
+
+// Give new ids to the datasets of `config` that `renew` accepts, the pipelines follow them.
+// Returns the new id of each renewed dataset
+pub fn renew_dataset_ids(
+    config: &mut AppConfig,
+    renew: impl Fn(&DatasetId) -> AppResult<bool>,
+) -> AppResult<HashMap<DatasetId, DatasetId>> {
+    let mut new_dataset_ids = HashMap::new();
+    for dataset in &mut config.datasets {
+        if renew(&dataset.id)? {
+            let new_id: DatasetId = FileAppService::generate_id(&dataset.name)?;
+            new_dataset_ids.insert(dataset.id.clone(), new_id.clone());
+            dataset.id = new_id;
+        }
+    }
+    for pipeline in &mut config.pipelines {
+        if let Some(new_id) = new_dataset_ids.get(&pipeline.dataset_id) {
+            pipeline.dataset_id = new_id.clone();
+        }
+    }
+    Ok(new_dataset_ids)
+}
+
+// Fill the new datasets of an imported app with the content of the archive
+pub fn write_imported_datasets(
+    contents: Vec<DatasetContent>,
+    datasets: &dyn DatasetService,
+) -> AppResult<()> {
+    write_datasets(
+        contents
+            .into_iter()
+            .map(|(id, ipc)| Ok((id, from_ipc(&ipc).map_err(internal)?))),
+        datasets,
+    )
+}
+
+// Fill the new datasets of a duplicated app with the content of the datasets they copy,
+// the ones never filled stay empty
+pub fn copy_datasets(
+    new_dataset_ids: HashMap<DatasetId, DatasetId>,
+    datasets: &dyn DatasetService,
+) -> AppResult<()> {
+    write_datasets(
+        new_dataset_ids
+            .into_iter()
+            .filter_map(|(id, new_id)| match datasets.exists(id.clone()) {
+                Ok(false) => None,
+                Ok(true) => Some(
+                    datasets
+                        .select(id, None, None, None, None)
+                        .map(|reader| (new_id, reader))
+                        .map_err(internal),
+                ),
+                Err(e) => Some(Err(internal(e))),
+            }),
+        datasets,
+    )
+}
+
+// Write datasets that don't exist yet, their content is read when they are written.
+// On error, the datasets already written are removed
+fn write_datasets(
+    contents: impl Iterator<Item = AppResult<(DatasetId, SendableRecordBatchReader)>>,
+    datasets: &dyn DatasetService,
+) -> AppResult<()> {
+    let mut written = vec![];
+    for content in contents {
+        let result = content.and_then(|(id, reader)| {
+            // a failed update may have written some files
+            written.push(id.clone());
+            datasets
+                .update(id, Some(reader), None)
+                .map(|_| ())
+                .map_err(internal)
+        });
+        if let Err(e) = result {
+            for id in written {
+                let _ = datasets.remove(id);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use crate::app_diff::diff;
+use evalessence_api::app::{AppError, AppId, AppResult, AuditAction, AuditEntry, AuditQuery};
+use serde_json::Value;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::fs;
+use tokio::task;
+
+// Changes of the apps are appended to {config_dir}/.audit.jsonl, one AuditEntry per line, or
+// next to the database for apps stored in one. The file is never rewritten, entries are only
+// added at its end. It is hidden, so list and watch ignore it.
+pub const AUDIT_FILENAME: &str = ".audit.jsonl";
+
+pub struct AppAuditLog {
+    path: PathBuf,
+}
+
+// An app before or after a change, its config converted to json
+pub struct AuditedVersion<'a> {
+    pub etag: &'a str,
+    pub config: Value,
+}
+
+// Who changes the apps when the service is not told: the user running it
+pub fn default_actor() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+// The change of `app_id` from `old` to `new`, one of them is None for a creation or deletion
+pub fn audit_entry(
+    app_id: AppId,
+    actor: &str,
+    old: Option<AuditedVersion<'_>>,
+    new: Option<AuditedVersion<'_>>,
+) -> AuditEntry {
+    let action = match (&old, &new) {
+        (None, _) => AuditAction::Create,
+        (Some(_), Some(_)) => AuditAction::Update,
+        (Some(_), None) => AuditAction::Delete,
+    };
+    let empty = Value::Object(serde_json::Map::new());
+    let changes = diff(
+        old.as_ref().map_or(&empty, |v| &v.config),
+        new.as_ref().map_or(&empty, |v| &v.config),
+    );
+
+    AuditEntry {
+        app_id,
+        action,
+        actor: actor.to_string(),
+        timestamp: SystemTime::now(),
+        old_etag: old.map(|v| v.etag.to_string()),
+        new_etag: new.map(|v| v.etag.to_string()),
+        changes,
+    }
+}
+
+impl AppAuditLog {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    // The change is already saved when its entry is appended, if it fails the entry is missing
+    pub async fn append(&self, entry: &AuditEntry) -> AppResult<()> {
+        let mut line = serde_json::to_vec(entry).map_err(|e| io_error(e.into()))?;
+        line.push(b'\n');
+        let path = self.path.clone();
+
+        task::spawn_blocking(move || {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(path)?;
+            // the lock keeps the lines of other processes from being interleaved with this one
+            file.lock()?;
+            // a line cut by a crash during its write is ended, so it doesn't merge with this one
+            if file.seek(SeekFrom::End(0))? > 0 {
+                file.seek(SeekFrom::End(-1))?;
+                let mut last = [0];
+                file.read_exact(&mut last)?;
+                if last != *b"\n" {
+                    line.insert(0, b'\n');
+                }
+            }
+            file.write_all(&line)
+        })
+        .await
+        .map_err(io::Error::other)
+        .flatten()
+        .map_err(io_error)
+    }
+
+    pub async fn query(&self, query: &AuditQuery) -> AppResult<Vec<AuditEntry>> {
+        let content = match fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            // nothing has been logged yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let mut entries = Vec::new();
+        for line in content.lines() {
+            // lines cut by a crash during their write can't be read, their change is not logged
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(line) else {
+                continue;
+            };
+            if matches(&entry, query) {
+                entries.push(entry);
+            }
+        }
+        // other processes may have appended their entries a bit late
+        entries.sort_by_key(|e| e.timestamp);
+        Ok(entries)
+    }
+}
+
+fn io_error(e: io::Error) -> AppError {
+    AppError::FileIoError {
+        filename: AUDIT_FILENAME.to_string(),
+        source: e.into(),
+    }
+}
+
+fn matches(entry: &AuditEntry, query: &AuditQuery) -> bool {
+    query.app_id.as_ref().is_none_or(|id| *id == entry.app_id)
+        && query.since.is_none_or(|since| entry.timestamp >= since)
+        && query.until.is_none_or(|until| entry.timestamp < until)
+}
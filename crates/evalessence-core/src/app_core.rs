@@ -1,55 +1,343 @@
-use crate::file_utils::atomic_write_async;
+use crate::app_archive::{
+    copy_datasets, export_app, import_ids, read_app_archive, renew_dataset_ids,
+    write_imported_datasets,
+};
+use crate::app_audit::{AUDIT_FILENAME, AppAuditLog, AuditedVersion, audit_entry, default_actor};
+use crate::app_diff::diff;
+use crate::app_history::AppHistory;
+use crate::app_index::{AppIndex, stamp};
+use crate::app_interpolation::{TemplateError, interpolate_value};
+use crate::app_layers::{SharedConfig, check_shared_filename, expand, overrides};
+use crate::app_listing::{HeaderCache, HeaderStamps, page};
+use crate::app_merge::merge;
+use crate::app_migrations::{CURRENT_SCHEMA_VERSION, migrate, schema_version};
+use crate::app_probe::probe_env;
+use crate::app_secrets::SecretResolver;
+use crate::app_trash::{AppTrash, TRASH_DIR, TrashEntry};
+use crate::app_validation::validate;
+use crate::app_watch::watch_config_dir;
+use crate::app_yaml_edit::edit_yaml;
+use crate::file_utils::{atomic_write_async, lock_file_async};
 use async_trait::async_trait;
-use evalessence_api::app::{App, AppError, AppId, AppResult, AppService, Dataset, Env, Pipeline};
+use evalessence_api::app::{
+    App, AppChange, AppError, AppEventStream, AppHeader, AppHeaderPage, AppId, AppListOptions,
+    AppResult, AppRevision, AppService, AuditEntry, AuditQuery, Dataset, DatasetId, EntityKind,
+    Env, EnvHealth, EnvId, InvalidId, Pipeline, PipelineId, ProbeOptions, ResolvedHeader,
+    TrashedApp, ValidationIssue, ValidationIssues,
+};
+use evalessence_api::dataset::DatasetService;
+use futures::StreamExt;
+use futures::stream;
 use nanoid::nanoid;
+use schemars::{JsonSchema, schema_for};
 use serde::{Deserialize, Serialize};
 use serde_saphyr;
 use slug::slugify;
+use std::collections::HashSet;
+use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 use tokio::fs;
 use tokio::fs::DirEntry;
-use tokio_stream::StreamExt;
+use tokio::task;
 
 use tokio_stream::wrappers::ReadDirStream;
 /// The internal format saved to disk (no etag, no filename)
-#[derive(Debug, Serialize, Deserialize)]
-struct AppConfig {
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "evalessence app config")]
+pub(crate) struct AppConfig {
+    /// version of the format of the file, files without it are read as version 0 and upgraded
+    #[schemars(default)]
+    pub schema_version: u32,
     pub id: AppId,
     pub name: String,
+    /// shared config file of the same directory, whose envs, datasets and pipelines are included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub envs: Vec<Env>,
     pub datasets: Vec<Dataset>,
     pub pipelines: Vec<Pipeline>,
 }
 
+impl From<App> for AppConfig {
+    fn from(app: App) -> Self {
+        Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            id: app.id,
+            name: app.name,
+            extends: app.extends,
+            envs: app.envs,
+            datasets: app.datasets,
+            pipelines: app.pipelines,
+        }
+    }
+}
+
+/// JSON Schema of the app-{id}.yaml files, to be used by editors and YAML language servers
+pub fn app_config_schema() -> serde_json::Value {
+    schema_for!(AppConfig).to_value()
+}
+
+pub(crate) fn internal(e: impl Into<anyhow::Error>) -> AppError {
+    AppError::Internal { source: e.into() }
+}
+
+// Lock file of the config dir, held while a file is checked against its etag and rewritten.
+// It is hidden and does not match app-*.yaml, so list and watch ignore it
+const LOCK_FILENAME: &str = ".evalessence.lock";
+
+// Number of app config files loaded at once when the config dir is listed
+const LIST_CONCURRENCY: usize = 16;
+
+// Length of the slug of a name in a generated id, leaving room for the random suffix
+const MAX_SLUG_LEN: usize = 100;
+
+// `config` at version `etag`, as recorded in the audit log
+pub(crate) fn audited<'a>(etag: &'a str, config: &AppConfig) -> AppResult<AuditedVersion<'a>> {
+    Ok(AuditedVersion {
+        etag,
+        config: serde_json::to_value(config).map_err(internal)?,
+    })
+}
+
+// App config files are named app-{id}.yaml
+pub(crate) fn is_app_filename(name: &str) -> bool {
+    name.starts_with("app-")
+        && Path::new(name)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml"))
+}
+
 pub struct FileAppService {
     config_dir: PathBuf,
+    index: AppIndex,
+    headers: HeaderCache,
+    history: AppHistory,
+    trash: AppTrash,
+    // deleted apps older than this are purged, kept until purged explicitly if None
+    trash_retention: Option<Duration>,
+    audit: AppAuditLog,
+    // who the changes are recorded from in the audit log
+    actor: String,
+}
+
+// A version of an app file about to be replaced
+struct Replaced<'a> {
+    etag: &'a str,
+    bytes: &'a [u8],
+    config: &'a AppConfig,
 }
 
 impl FileAppService {
     pub fn new(config_dir: impl AsRef<Path>) -> Self {
         Self {
             config_dir: config_dir.as_ref().to_path_buf(),
+            index: AppIndex::default(),
+            headers: HeaderCache::default(),
+            history: AppHistory::new(config_dir.as_ref()),
+            trash: AppTrash::new(config_dir.as_ref()),
+            trash_retention: None,
+            audit: AppAuditLog::new(&config_dir.as_ref().join(AUDIT_FILENAME)),
+            actor: default_actor(),
         }
     }
 
-    // Helper to clean the name and add a random suffix
-    fn generate_id(name: &str) -> AppId {
-        AppId(format!("{}-{}", slugify(name), nanoid!(4)))
+    /// Record the changes made through this service as made by `actor` in the audit log,
+    /// instead of the user running it
+    #[must_use]
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    /// Purge the deleted apps once they have been in the trash for `retention`
+    #[must_use]
+    pub const fn with_trash_retention(mut self, retention: Duration) -> Self {
+        self.trash_retention = Some(retention);
+        self
+    }
+
+    // Helper to clean the name and add a random suffix.
+    // The slug is shortened so that long names still give valid ids
+    pub(crate) fn generate_id<T: FromStr<Err = InvalidId>>(name: &str) -> AppResult<T> {
+        let mut slug = slugify(name);
+        slug.truncate(MAX_SLUG_LEN);
+        format!("{slug}-{}", nanoid!(4)).parse().map_err(internal)
+    }
+
+    pub(crate) fn app_filename(id: &AppId) -> String {
+        format!("app-{id}.yaml")
     }
 
     // Helper to calculate ETag from raw bytes
-    fn calculate_etag(bytes: &[u8]) -> String {
+    pub(crate) fn calculate_etag(bytes: &[u8]) -> String {
         blake3::hash(bytes).to_string()
     }
 
-    fn get_path(&self, filename: &str) -> PathBuf {
+    // ETag of a file extending the shared file `extends`, which covers both files
+    pub(crate) fn calculate_extended_etag(
+        yaml_bytes: &[u8],
+        extends: &str,
+        shared_bytes: &[u8],
+    ) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for part in [yaml_bytes, extends.as_bytes(), shared_bytes] {
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        hasher.finalize().to_string()
+    }
+
+    pub(crate) fn get_path(&self, filename: &str) -> PathBuf {
         self.config_dir.join(filename)
     }
 
-    async fn upsert_config(&self, config: &AppConfig, filename: String) -> AppResult<App> {
-        // 1. Serialize to an in-memory string (Sync)
-        let yaml_data = serde_saphyr::to_string(&config)
-            .map_err(|e| AppError::Internal { source: e.into() })?;
+    // Semantic validation, run on every load and save
+    pub(crate) fn validate_config(config: &AppConfig, filename: &str) -> AppResult<()> {
+        validate(&config.envs, &config.datasets, &config.pipelines).map_err(|issues| {
+            AppError::ValidationError {
+                filename: filename.to_string(),
+                source: issues.into(),
+            }
+        })
+    }
+
+    // Deserialize a config file, migrating it in memory if it has an old schema version.
+    // It is validated once the entities it extends are included, see `resolve_config`
+    pub(crate) fn parse_config(yaml_bytes: &[u8], filename: &str) -> AppResult<AppConfig> {
+        let validation_error = |e: anyhow::Error| AppError::ValidationError {
+            filename: filename.to_string(),
+            source: e,
+        };
+
+        let version = schema_version(yaml_bytes).map_err(validation_error)?;
+        let config: AppConfig = if version == CURRENT_SCHEMA_VERSION {
+            serde_saphyr::from_slice(yaml_bytes).map_err(|e| validation_error(e.into()))?
+        } else {
+            let migrated = migrate(yaml_bytes, version).map_err(validation_error)?;
+            serde_json::from_value(migrated).map_err(|e| validation_error(e.into()))?
+        };
+        Ok(config)
+    }
+
+    // The shared config `extends` of the config file `filename`, with its raw content
+    async fn read_shared(
+        &self,
+        extends: &str,
+        filename: &str,
+    ) -> AppResult<(SharedConfig, Vec<u8>)> {
+        check_shared_filename(extends).map_err(|e| AppError::ValidationError {
+            filename: filename.to_string(),
+            source: e,
+        })?;
+        let bytes = self.read_current(extends).await?;
+        Self::parse_shared(extends, bytes)
+    }
+
+    fn parse_shared(extends: &str, bytes: Vec<u8>) -> AppResult<(SharedConfig, Vec<u8>)> {
+        let shared = serde_saphyr::from_slice(&bytes).map_err(|e| AppError::ValidationError {
+            filename: extends.to_string(),
+            source: e.into(),
+        })?;
+        Ok((shared, bytes))
+    }
+
+    // Parse a config file and include the entities it extends, returning the config as seen
+    // by the callers and its etag. The etag of an extending file also covers the shared file
+    async fn resolve_config(
+        &self,
+        yaml_bytes: &[u8],
+        filename: &str,
+    ) -> AppResult<(AppConfig, String)> {
+        self.resolve_config_with(yaml_bytes, None, filename).await
+    }
+
+    // Like `resolve_config`, extending the config with `shared_bytes` instead of the current
+    // shared file when they are given
+    pub(crate) async fn resolve_config_with(
+        &self,
+        yaml_bytes: &[u8],
+        shared_bytes: Option<Vec<u8>>,
+        filename: &str,
+    ) -> AppResult<(AppConfig, String)> {
+        // parsed on the blocking pool, so that the files of a listing are parsed in parallel
+        let mut config = task::spawn_blocking({
+            let yaml_bytes = yaml_bytes.to_vec();
+            let filename = filename.to_string();
+            move || Self::parse_config(&yaml_bytes, &filename)
+        })
+        .await
+        .map_err(internal)??;
+        let etag = match config.extends.clone() {
+            None => Self::calculate_etag(yaml_bytes),
+            Some(extends) => {
+                let (shared, shared_bytes) = match shared_bytes {
+                    Some(bytes) => Self::parse_shared(&extends, bytes)?,
+                    None => self.read_shared(&extends, filename).await?,
+                };
+                config.include(&shared);
+                Self::calculate_extended_etag(yaml_bytes, &extends, &shared_bytes)
+            }
+        };
+
+        Self::validate_config(&config, filename)?;
+        Ok((config, etag))
+    }
+
+    // The content of the config file: the entities that differ from the shared ones, if any
+    async fn to_saved_config(&self, config: &AppConfig, filename: &str) -> AppResult<AppConfig> {
+        let Some(extends) = &config.extends else {
+            return Ok(config.clone());
+        };
+        let (shared, _) = self.read_shared(extends, filename).await?;
+        let validation_error = |e: anyhow::Error| AppError::ValidationError {
+            filename: filename.to_string(),
+            source: e,
+        };
+        Ok(AppConfig {
+            envs: overrides(extends, &shared.envs, &config.envs).map_err(validation_error)?,
+            datasets: overrides(extends, &shared.datasets, &config.datasets)
+                .map_err(validation_error)?,
+            pipelines: overrides(extends, &shared.pipelines, &config.pipelines)
+                .map_err(validation_error)?,
+            ..config.clone()
+        })
+    }
+
+    // Write `config` to `filename`. When the file replaces a `previous` version, only the
+    // parts that changed are rewritten, keeping the comments and the layout of the file
+    async fn upsert_config(
+        &self,
+        config: &AppConfig,
+        filename: String,
+        previous: Option<Replaced<'_>>,
+    ) -> AppResult<App> {
+        let app = self
+            .write_config(config, filename, previous.as_ref().map(|p| p.bytes))
+            .await?;
+        let old = match previous {
+            Some(previous) => Some(audited(previous.etag, previous.config)?),
+            None => None,
+        };
+        self.record_created_or_updated(&app, config, old).await?;
+        Ok(app)
+    }
+
+    // Write the file of `config` without recording it in the audit log
+    async fn write_config(
+        &self,
+        config: &AppConfig,
+        filename: String,
+        previous_bytes: Option<&[u8]>,
+    ) -> AppResult<App> {
+        Self::validate_config(config, &filename)?;
+        let saved = self.to_saved_config(config, &filename).await?;
+        let previous_bytes = previous_bytes.map(<[u8]>::to_vec);
+        let yaml_data =
+            task::spawn_blocking(move || Self::to_yaml(&saved, previous_bytes.as_deref()))
+                .await
+                .map_err(internal)??;
 
         atomic_write_async(self.get_path(&filename), yaml_data)
             .await
@@ -57,116 +345,1036 @@ impl FileAppService {
                 filename: filename.clone(),
                 source: e.into(),
             })?;
+        self.index
+            .insert(&self.config_dir, &filename, config.id.clone())
+            .await;
 
-        self.get(filename).await
+        self.load(filename).await
     }
-}
 
-#[async_trait]
-impl AppService for FileAppService {
-    async fn list(&self) -> AppResult<Vec<AppResult<App>>> {
+    async fn record_created_or_updated(
+        &self,
+        app: &App,
+        config: &AppConfig,
+        old: Option<AuditedVersion<'_>>,
+    ) -> AppResult<()> {
+        let new = audited(&app.etag, config)?;
+        self.audit
+            .append(&audit_entry(app.id.clone(), &self.actor, old, Some(new)))
+            .await
+    }
+
+    // Remove the file of an app written by an operation which failed after: the app never
+    // existed for the users, it is neither trashed nor recorded in the audit log
+    async fn discard(&self, app: &App) -> AppResult<()> {
+        fs::remove_file(self.get_path(&app.filename))
+            .await
+            .map_err(|e| AppError::FileIoError {
+                filename: app.filename.clone(),
+                source: e.into(),
+            })?;
+        self.index.remove(&app.filename);
+        Ok(())
+    }
+
+    // The YAML content of `config`. When it replaces a `previous` version, only the parts that
+    // changed are rewritten, keeping the comments and the layout of the file
+    pub(crate) fn to_yaml(config: &AppConfig, previous: Option<&[u8]>) -> AppResult<String> {
+        let edited = match previous.and_then(|bytes| std::str::from_utf8(bytes).ok()) {
+            Some(previous) => edit_yaml(previous, &serde_json::to_value(config).map_err(internal)?),
+            None => None,
+        };
+        match edited {
+            Some(yaml_data) => Ok(yaml_data),
+            None => serde_saphyr::to_string(config).map_err(internal),
+        }
+    }
+
+    async fn resolve(&self, id: &AppId) -> AppResult<String> {
+        self.index.resolve(&self.config_dir, id).await
+    }
+
+    // Read an app from its config file, which must be named after the app id
+    async fn load(&self, filename: String) -> AppResult<App> {
+        let path = self.get_path(&filename);
+
+        let yaml_bytes = fs::read(&path).await.map_err(|e| AppError::FileIoError {
+            filename: filename.clone(),
+            source: e.into(),
+        })?;
+
+        let (config, etag) = self.resolve_config(&yaml_bytes, &filename).await?;
+        if filename != Self::app_filename(&config.id) {
+            return Err(AppError::IdMismatch {
+                filename,
+                id: config.id,
+            });
+        }
+
+        Ok(Self::to_app(config, etag, filename))
+    }
+
+    // The app config files of the config dir
+    async fn app_filenames(&self) -> AppResult<Vec<String>> {
         let entries = fs::read_dir(&self.config_dir)
             .await
             .map_err(|e| AppError::Internal { source: e.into() })?;
 
-        let apps = ReadDirStream::new(entries)
-            .filter_map(|res: Result<DirEntry, std::io::Error>| {
-                // 1. If the OS fails to even give us an entry, we have no filename.
+        let filenames = ReadDirStream::new(entries)
+            .filter_map(|res: Result<DirEntry, std::io::Error>| async move {
+                // If the OS fails to even give us an entry, we have no filename.
                 // Since we can't check if it's an "app-*.yaml" file, we must skip it.
                 let entry = res.ok()?;
 
                 let name = entry.file_name().to_string_lossy().into_owned();
 
-                // 2. Only proceed if it matches your pattern
-                (name.starts_with("app-")
-                    && Path::new(&name)
-                        .extension()
-                        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml")))
-                .then_some(name)
+                // Only proceed if it matches the app-{id}.yaml pattern
+                is_app_filename(&name).then_some(name)
+            })
+            .collect()
+            .await;
+        Ok(filenames)
+    }
+
+    // The header of an app, only loaded again when its file or the file it extends changed
+    async fn load_header(&self, filename: String) -> AppResult<AppHeader> {
+        if let Some(header) = self.headers.get(&self.config_dir, &filename).await {
+            return Ok(header);
+        }
+
+        // stamped before the load, so that a change made during the load is seen next time
+        let app_stamp =
+            stamp(&self.config_dir, &filename)
+                .await
+                .map_err(|e| AppError::FileIoError {
+                    filename: filename.clone(),
+                    source: e.into(),
+                })?;
+        let modified = app_stamp.modified;
+        let app = self.load(filename).await?;
+        let stamps =
+            HeaderStamps::with_app(app_stamp, &self.config_dir, app.extends.as_deref()).await;
+        let header = AppHeader {
+            id: app.id,
+            name: app.name,
+            filename: app.filename,
+            env_count: app.envs.len(),
+            dataset_count: app.datasets.len(),
+            pipeline_count: app.pipelines.len(),
+            modified,
+        };
+        if let Some(stamps) = stamps {
+            self.headers
+                .insert(header.filename.clone(), stamps, header.clone());
+        }
+        Ok(header)
+    }
+
+    pub(crate) fn find_env<'a>(app: &'a App, env_id: &EnvId) -> AppResult<&'a Env> {
+        app.envs
+            .iter()
+            .find(|e| &e.id == env_id)
+            .ok_or_else(|| AppConfig::not_found(&app.filename, EntityKind::Env, env_id.as_str()))
+    }
+
+    pub(crate) fn to_app(config: AppConfig, etag: String, filename: String) -> App {
+        App {
+            id: config.id,
+            name: config.name,
+            extends: config.extends,
+            envs: config.envs,
+            datasets: config.datasets,
+            pipelines: config.pipelines,
+            etag,
+            filename,
+        }
+    }
+
+    // Serialize the modifications of existing files, across tasks and processes
+    async fn lock(&self) -> AppResult<File> {
+        lock_file_async(self.get_path(LOCK_FILENAME))
+            .await
+            .map_err(|e| AppError::FileIoError {
+                filename: LOCK_FILENAME.to_string(),
+                source: e.into(),
             })
-            .then(|filename| async move {
-                // 3. Now we only call 'get' for valid filenames.
-                // Any error here will be preserved in your Vec<AppResult<App>>.
-                self.get(filename).await
+    }
+
+    async fn read_current(&self, filename: &str) -> AppResult<Vec<u8>> {
+        fs::read(self.get_path(filename))
+            .await
+            .map_err(|e| AppError::FileIoError {
+                filename: filename.to_string(),
+                source: e.into(),
+            })
+    }
+
+    // Read the file and fail with a Conflict if it has been modified since `etag`.
+    // Must be called under `lock` so the file can't change before it is written
+    async fn read_checked(&self, filename: &str, etag: &str) -> AppResult<(Vec<u8>, AppConfig)> {
+        let current_bytes = self.read_current(filename).await?;
+        let (config, current_etag) = self.resolve_config(&current_bytes, filename).await?;
+        if current_etag != etag {
+            return Err(AppError::Conflict {
+                filename: filename.to_string(),
+            });
+        }
+        Ok((current_bytes, config))
+    }
+
+    // Save `app` over the version of its file identified by `app.etag`
+    async fn replace(&self, app: App) -> AppResult<App> {
+        let filename = self.resolve(&app.id).await?;
+        let _lock = self.lock().await?;
+        let (current_bytes, current) = self.read_checked(&filename, &app.etag).await?;
+        self.record_revision(
+            &app.id,
+            &filename,
+            &app.etag,
+            &current_bytes,
+            current.extends.as_deref(),
+        )
+        .await?;
+
+        let etag = app.etag.clone();
+        let previous = Replaced {
+            etag: &etag,
+            bytes: &current_bytes,
+            config: &current,
+        };
+        self.upsert_config(&AppConfig::from(app), filename, Some(previous))
+            .await
+    }
+
+    // Merge `app` with the current version of its file, both modified from the version of
+    // `app.etag`, which must be in the history
+    async fn merge_stale(
+        &self,
+        app: App,
+        current: AppConfig,
+        filename: &str,
+    ) -> AppResult<AppConfig> {
+        let base_bytes = self
+            .history
+            .read(&app.id, &app.etag)
+            .await
+            .map_err(|e| AppError::FileIoError {
+                filename: filename.to_string(),
+                source: e.into(),
+            })?
+            .ok_or_else(|| AppError::Conflict {
+                filename: filename.to_string(),
+            })?;
+
+        let base_shared = self
+            .history
+            .read_shared(&app.id, &app.etag)
+            .await
+            .map_err(|e| AppError::FileIoError {
+                filename: filename.to_string(),
+                source: e.into(),
+            })?;
+
+        let to_value = |config: AppConfig| serde_json::to_value(config).map_err(internal);
+        let (base, _) = self
+            .resolve_config_with(&base_bytes, base_shared, filename)
+            .await?;
+        let base = to_value(base)?;
+        let theirs = to_value(current)?;
+        let ours = to_value(AppConfig::from(app))?;
+
+        let merged = merge(&base, &ours, &theirs).map_err(|conflicts| AppError::MergeConflict {
+            filename: filename.to_string(),
+            conflicts,
+        })?;
+        serde_json::from_value(merged).map_err(internal)
+    }
+
+    // Keep the version of the file about to be replaced in the history of the app,
+    // under the etag it has been loaded with, and the shared file it extends
+    async fn record_revision(
+        &self,
+        id: &AppId,
+        filename: &str,
+        etag: &str,
+        bytes: &[u8],
+        extends: Option<&str>,
+    ) -> AppResult<()> {
+        let io_error = |e: std::io::Error| AppError::FileIoError {
+            filename: filename.to_string(),
+            source: e.into(),
+        };
+        let saved_at = fs::metadata(self.get_path(filename))
+            .await
+            .and_then(|metadata| metadata.modified())
+            .map_err(io_error)?;
+        // the etag has been checked under the lock, it covers the current shared file
+        let shared = match extends {
+            Some(extends) => Some(self.read_current(extends).await?),
+            None => None,
+        };
+
+        self.history
+            .record(id, etag, bytes.to_vec(), shared, saved_at)
+            .await
+            .map_err(io_error)
+    }
+
+    fn trash_io_error(trash_id: &str, e: std::io::Error) -> AppError {
+        AppError::FileIoError {
+            filename: trash_id.to_string(),
+            source: e.into(),
+        }
+    }
+
+    async fn find_trashed(&self, trash_id: &str) -> AppResult<TrashEntry> {
+        self.trash
+            .find(trash_id)
+            .await
+            .map_err(|e| Self::trash_io_error(trash_id, e))?
+            .ok_or_else(|| AppError::TrashNotFound {
+                trash_id: trash_id.to_string(),
+            })
+    }
+
+    // Remove a trashed file, and the history of its app unless the id is used by another app.
+    // Must be called under `lock`
+    async fn purge_entry(&self, entry: &TrashEntry) -> AppResult<()> {
+        match self.trash.remove(&entry.trash_id).await {
+            // purged concurrently by another process
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            result => result.map_err(|e| Self::trash_io_error(&entry.trash_id, e))?,
+        }
+        self.purge_history(entry).await
+    }
+
+    // The history is kept while the id is used by an app, or by another deleted app
+    async fn purge_history(&self, entry: &TrashEntry) -> AppResult<()> {
+        let trash = self.trash.list().await.map_err(Self::trash_dir_error)?;
+        let id_in_use = match self.resolve(&entry.id).await {
+            Err(AppError::NotFound { .. }) => trash.iter().any(|e| e.id == entry.id),
+            _ => true,
+        };
+        if id_in_use {
+            return Ok(());
+        }
+        self.history
+            .remove(&entry.id)
+            .await
+            .map_err(|e| Self::trash_io_error(&entry.trash_id, e))
+    }
+
+    fn trash_dir_error(e: std::io::Error) -> AppError {
+        AppError::FileIoError {
+            filename: TRASH_DIR.to_string(),
+            source: e.into(),
+        }
+    }
+
+    // Purge the trashed apps older than the retention period, if any.
+    // Must be called under `lock`
+    async fn purge_expired(&self) -> AppResult<()> {
+        let Some(retention) = self.trash_retention else {
+            return Ok(());
+        };
+        let expired = self
+            .trash
+            .purge_expired(retention)
+            .await
+            .map_err(Self::trash_dir_error)?;
+        for entry in &expired {
+            self.purge_history(entry).await?;
+        }
+        Ok(())
+    }
+
+    // Replace the placeholders of `app` by the variables of the process, or of the secrets file.
+    // The resolved values are validated like the ones of a config file
+    pub(crate) async fn interpolate(app: App, secrets: &mut SecretResolver) -> AppResult<App> {
+        let file_variables = secrets.file_secrets().await?;
+        let lookup = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .or_else(|| file_variables.get(name).cloned())
+        };
+
+        let (etag, filename) = (app.etag.clone(), app.filename.clone());
+        let mut value = serde_json::to_value(AppConfig::from(app)).map_err(internal)?;
+        interpolate_value(&mut value, "", &lookup).map_err(|(path, e)| match e {
+            TemplateError::Unset(variable) => AppError::UnresolvedVariable {
+                filename: filename.clone(),
+                path,
+                variable,
+            },
+            TemplateError::Malformed(message) => AppError::ValidationError {
+                filename: filename.clone(),
+                source: ValidationIssues(vec![ValidationIssue { path, message }]).into(),
+            },
+        })?;
+
+        let config = serde_json::from_value(value).map_err(internal)?;
+        Self::validate_config(&config, &filename)?;
+        Ok(Self::to_app(config, etag, filename))
+    }
+
+    // Apply `edit` on the current config of the app, then save it
+    async fn edit_config(
+        &self,
+        app_id: &AppId,
+        etag: &str,
+        edit: impl FnOnce(&mut AppConfig, &str) -> AppResult<()> + Send,
+    ) -> AppResult<App> {
+        let filename = self.resolve(app_id).await?;
+        let _lock = self.lock().await?;
+        let (current_bytes, current) = self.read_checked(&filename, etag).await?;
+
+        let mut config = current.clone();
+        edit(&mut config, &filename)?;
+
+        self.record_revision(
+            app_id,
+            &filename,
+            etag,
+            &current_bytes,
+            current.extends.as_deref(),
+        )
+        .await?;
+        let previous = Replaced {
+            etag,
+            bytes: &current_bytes,
+            config: &current,
+        };
+        self.upsert_config(&config, filename, Some(previous)).await
+    }
+}
+
+impl AppConfig {
+    // Add the shared entities, the ones of the app with the same ids override them
+    fn include(&mut self, shared: &SharedConfig) {
+        self.envs = expand(&shared.envs, std::mem::take(&mut self.envs));
+        self.datasets = expand(&shared.datasets, std::mem::take(&mut self.datasets));
+        self.pipelines = expand(&shared.pipelines, std::mem::take(&mut self.pipelines));
+    }
+
+    fn not_found(filename: &str, kind: EntityKind, id: &str) -> AppError {
+        AppError::EntityNotFound {
+            filename: filename.to_string(),
+            kind,
+            id: id.to_string(),
+        }
+    }
+
+    fn check_pipeline_refs(
+        &self,
+        filename: &str,
+        env_id: &EnvId,
+        dataset_id: &DatasetId,
+    ) -> AppResult<()> {
+        if !self.envs.iter().any(|e| &e.id == env_id) {
+            return Err(Self::not_found(filename, EntityKind::Env, env_id.as_str()));
+        }
+        if !self.datasets.iter().any(|d| &d.id == dataset_id) {
+            return Err(Self::not_found(
+                filename,
+                EntityKind::Dataset,
+                dataset_id.as_str(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Fail if any pipeline matches `uses`, listing the pipelines involved
+    fn check_unreferenced(
+        &self,
+        filename: &str,
+        kind: EntityKind,
+        id: &str,
+        uses: impl Fn(&Pipeline) -> bool,
+    ) -> AppResult<()> {
+        let pipeline_ids: Vec<PipelineId> = self
+            .pipelines
+            .iter()
+            .filter(|p| uses(p))
+            .map(|p| p.id.clone())
+            .collect();
+        if pipeline_ids.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::StillReferenced {
+                filename: filename.to_string(),
+                kind,
+                id: id.to_string(),
+                pipeline_ids,
             })
+        }
+    }
+
+    pub(crate) fn update_env(&mut self, filename: &str, env: Env) -> AppResult<()> {
+        let current = self
+            .envs
+            .iter_mut()
+            .find(|e| e.id == env.id)
+            .ok_or_else(|| Self::not_found(filename, EntityKind::Env, env.id.as_str()))?;
+        *current = env;
+        Ok(())
+    }
+
+    pub(crate) fn remove_env(&mut self, filename: &str, env_id: &EnvId) -> AppResult<()> {
+        let index = self
+            .envs
+            .iter()
+            .position(|e| &e.id == env_id)
+            .ok_or_else(|| Self::not_found(filename, EntityKind::Env, env_id.as_str()))?;
+        self.check_unreferenced(filename, EntityKind::Env, env_id.as_str(), |p| {
+            &p.env_id == env_id
+        })?;
+        self.envs.remove(index);
+        Ok(())
+    }
+
+    pub(crate) fn update_dataset(&mut self, filename: &str, dataset: Dataset) -> AppResult<()> {
+        let current = self
+            .datasets
+            .iter_mut()
+            .find(|d| d.id == dataset.id)
+            .ok_or_else(|| Self::not_found(filename, EntityKind::Dataset, dataset.id.as_str()))?;
+        *current = dataset;
+        Ok(())
+    }
+
+    pub(crate) fn remove_dataset(
+        &mut self,
+        filename: &str,
+        dataset_id: &DatasetId,
+    ) -> AppResult<()> {
+        let index = self
+            .datasets
+            .iter()
+            .position(|d| &d.id == dataset_id)
+            .ok_or_else(|| Self::not_found(filename, EntityKind::Dataset, dataset_id.as_str()))?;
+        self.check_unreferenced(filename, EntityKind::Dataset, dataset_id.as_str(), |p| {
+            &p.dataset_id == dataset_id
+        })?;
+        self.datasets.remove(index);
+        Ok(())
+    }
+
+    pub(crate) fn add_pipeline(&mut self, filename: &str, pipeline: Pipeline) -> AppResult<()> {
+        self.check_pipeline_refs(filename, &pipeline.env_id, &pipeline.dataset_id)?;
+        self.pipelines.push(pipeline);
+        Ok(())
+    }
+
+    pub(crate) fn update_pipeline(&mut self, filename: &str, pipeline: Pipeline) -> AppResult<()> {
+        self.check_pipeline_refs(filename, &pipeline.env_id, &pipeline.dataset_id)?;
+        let current = self
+            .pipelines
+            .iter_mut()
+            .find(|p| p.id == pipeline.id)
+            .ok_or_else(|| Self::not_found(filename, EntityKind::Pipeline, pipeline.id.as_str()))?;
+        *current = pipeline;
+        Ok(())
+    }
+
+    pub(crate) fn remove_pipeline(
+        &mut self,
+        filename: &str,
+        pipeline_id: &PipelineId,
+    ) -> AppResult<()> {
+        let index = self
+            .pipelines
+            .iter()
+            .position(|p| &p.id == pipeline_id)
+            .ok_or_else(|| Self::not_found(filename, EntityKind::Pipeline, pipeline_id.as_str()))?;
+        self.pipelines.remove(index);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AppService for FileAppService {
+    async fn list(&self) -> AppResult<Vec<AppResult<App>>> {
+        let apps = stream::iter(self.app_filenames().await?)
+            .map(|filename| self.load(filename))
+            .buffered(LIST_CONCURRENCY)
             .collect()
             .await;
 
         Ok(apps)
     }
 
+    async fn list_headers(&self, options: AppListOptions) -> AppResult<AppHeaderPage> {
+        let mut headers = vec![];
+        let mut errors = vec![];
+        let filenames = self.app_filenames().await?;
+        self.headers.retain(&filenames);
+        let mut loaded = stream::iter(filenames)
+            .map(|filename| self.load_header(filename))
+            .buffer_unordered(LIST_CONCURRENCY);
+        while let Some(header) = loaded.next().await {
+            match header {
+                Ok(header) => headers.push(header),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let (headers, total_count) = page(headers, &options);
+        Ok(AppHeaderPage {
+            headers,
+            total_count,
+            errors,
+        })
+    }
+
     async fn create(&self, name: String) -> AppResult<App> {
-        let id = Self::generate_id(&name);
-        let filename = format!("app-{id}.yaml");
+        let id = Self::generate_id(&name)?;
+        let filename = Self::app_filename(&id);
 
         // create and save the AppConfig with empty envs/datasets/pipelines
         let config = AppConfig {
+            schema_version: CURRENT_SCHEMA_VERSION,
             id: id.clone(),
             name: name.clone(),
+            extends: None,
             envs: vec![],
             datasets: vec![],
             pipelines: vec![],
         };
 
-        self.upsert_config(&config, filename).await
+        self.upsert_config(&config, filename, None).await
     }
 
-    async fn get(&self, filename: String) -> AppResult<App> {
-        let path = self.get_path(&filename);
-
-        let yaml_bytes = fs::read(&path).await.map_err(|e| AppError::FileIoError {
-            filename: filename.clone(),
-            source: e.into(),
-        })?;
+    async fn get(&self, id: AppId) -> AppResult<App> {
+        let filename = self.resolve(&id).await?;
+        self.load(filename).await
+    }
 
-        let config: AppConfig =
-            serde_saphyr::from_slice(&yaml_bytes).map_err(|e| AppError::ValidationError {
+    async fn delete(&self, id: AppId) -> AppResult<()> {
+        let filename = self.resolve(&id).await?;
+        let _lock = self.lock().await?;
+        // files that can't be loaded can be deleted, their content is not in the audit log
+        let current = self.load(filename.clone()).await.ok();
+        self.trash
+            .put(&self.get_path(&filename), &filename)
+            .await
+            .map_err(|e| AppError::FileIoError {
                 filename: filename.clone(),
                 source: e.into(),
             })?;
+        self.index.remove(&filename);
 
-        Ok(App {
-            id: config.id,
-            name: config.name,
-            envs: config.envs,
-            datasets: config.datasets,
-            pipelines: config.pipelines,
-            etag: Self::calculate_etag(&yaml_bytes),
+        let old = match &current {
+            Some(app) => Some(audited(&app.etag, &AppConfig::from(app.clone()))?),
+            None => None,
+        };
+        self.audit
+            .append(&audit_entry(id, &self.actor, old, None))
+            .await?;
+        self.purge_expired().await
+    }
+
+    async fn update(&self, app: App) -> AppResult<App> {
+        let filename = self.resolve(&app.id).await?;
+        let _lock = self.lock().await?;
+        let current_bytes = self.read_current(&filename).await?;
+        let (current, current_etag) = self.resolve_config(&current_bytes, &filename).await?;
+
+        let id = app.id.clone();
+        let current_extends = current.extends.clone();
+        let mut config = if current_etag == app.etag {
+            AppConfig::from(app)
+        } else {
+            self.merge_stale(app, current.clone(), &filename).await?
+        };
+        // an app starting to extend a shared file gets its entities, none of them is removed
+        if let Some(extends) = &config.extends
+            && config.extends != current_extends
+        {
+            let (shared, _) = self.read_shared(extends, &filename).await?;
+            config.include(&shared);
+        }
+
+        self.record_revision(
+            &id,
+            &filename,
+            &current_etag,
+            &current_bytes,
+            current_extends.as_deref(),
+        )
+        .await?;
+        let previous = Replaced {
+            etag: &current_etag,
+            bytes: &current_bytes,
+            config: &current,
+        };
+        self.upsert_config(&config, filename, Some(previous)).await
+    }
+
+    async fn duplicate(
+        &self,
+        app_id: AppId,
+        name: String,
+        datasets: &dyn DatasetService,
+    ) -> AppResult<App> {
+        let app = self.get(app_id).await?;
+
+        let mut config = AppConfig::from(app);
+        config.id = Self::generate_id(&name)?;
+        config.name = name;
+        let new_dataset_ids = renew_dataset_ids(&mut config, |_| Ok(true))?;
+
+        // the app is written before its datasets, and discarded if they can't be. It is only
+        // audited once it is complete
+        let filename = Self::app_filename(&config.id);
+        let app = self.write_config(&config, filename, None).await?;
+        if let Err(e) = copy_datasets(new_dataset_ids, datasets) {
+            self.discard(&app).await?;
+            return Err(e);
+        }
+        self.record_created_or_updated(&app, &config, None).await?;
+        Ok(app)
+    }
+
+    async fn export(
+        &self,
+        app_id: AppId,
+        datasets: &dyn DatasetService,
+        archive: PathBuf,
+    ) -> AppResult<()> {
+        let app = self.get(app_id).await?;
+        export_app(app, datasets, archive).await
+    }
+
+    async fn import(&self, archive: PathBuf, datasets: &dyn DatasetService) -> AppResult<App> {
+        let (content, archive_name) = read_app_archive(archive).await?;
+        let (config, _) = self
+            .resolve_config(&content.config_yaml, &archive_name)
+            .await?;
+
+        // app ids must stay unique in this config dir, and match a single file
+        let apps: Vec<App> = self.list().await?.into_iter().flatten().collect();
+        let filenames: HashSet<String> = self.app_filenames().await?.into_iter().collect();
+        let (config, contents) = import_ids(config, content.datasets, datasets, &apps, |id| {
+            filenames.contains(&Self::app_filename(id))
+        })?;
+
+        // the app is written before its datasets, and discarded if they can't be. It is only
+        // audited once it is complete
+        let filename = Self::app_filename(&config.id);
+        let app = self.write_config(&config, filename, None).await?;
+        if let Err(e) = write_imported_datasets(contents, datasets) {
+            self.discard(&app).await?;
+            return Err(e);
+        }
+        self.record_created_or_updated(&app, &config, None).await?;
+        Ok(app)
+    }
+
+    fn watch(&self) -> AppResult<AppEventStream> {
+        watch_config_dir(&self.config_dir)
+    }
+
+    async fn resolve_app(&self, app_id: AppId) -> AppResult<App> {
+        let app = self.get(app_id).await?;
+        Self::interpolate(app, &mut SecretResolver::new(&self.config_dir)).await
+    }
+
+    async fn resolve_env_headers(
+        &self,
+        app_id: AppId,
+        env_id: EnvId,
+    ) -> AppResult<Vec<ResolvedHeader>> {
+        let mut secrets = SecretResolver::new(&self.config_dir);
+        let app = Self::interpolate(self.get(app_id).await?, &mut secrets).await?;
+        let env = Self::find_env(&app, &env_id)?;
+
+        secrets.resolve_headers(env).await
+    }
+
+    async fn probe_env(
+        &self,
+        app_id: AppId,
+        env_id: EnvId,
+        options: ProbeOptions,
+    ) -> AppResult<EnvHealth> {
+        let mut secrets = SecretResolver::new(&self.config_dir);
+        let app = Self::interpolate(self.get(app_id).await?, &mut secrets).await?;
+        let env = Self::find_env(&app, &env_id)?;
+        let headers = secrets.resolve_headers(env).await?;
+
+        probe_env(&app, env, &headers, &options).await
+    }
+
+    async fn list_trash(&self) -> AppResult<Vec<TrashedApp>> {
+        let entries = {
+            let _lock = self.lock().await?;
+            self.purge_expired().await?;
+            self.trash.list().await.map_err(Self::trash_dir_error)?
+        };
+
+        let mut trashed = Vec::new();
+        for entry in entries {
+            let name = fs::read(self.trash.path(&entry.trash_id))
+                .await
+                .ok()
+                .and_then(|bytes| Self::parse_config(&bytes, &entry.filename).ok())
+                .map(|config| config.name);
+            trashed.push(TrashedApp {
+                id: entry.id,
+                name,
+                trash_id: entry.trash_id,
+                filename: entry.filename,
+                deleted_at: entry.deleted_at,
+            });
+        }
+        trashed.sort_by_key(|t| std::cmp::Reverse(t.deleted_at));
+        Ok(trashed)
+    }
+
+    async fn restore(&self, trash_id: String) -> AppResult<App> {
+        let _lock = self.lock().await?;
+        let entry = self.find_trashed(&trash_id).await?;
+        let id = entry.id.clone();
+
+        // the restored file must not shadow an app created since, or be shadowed by it
+        let already_exists = |filename: String| AppError::AlreadyExists {
+            id: id.clone(),
             filename,
-        })
+        };
+        match self.resolve(&id).await {
+            Err(AppError::NotFound { .. }) => {}
+            Ok(filename) => return Err(already_exists(filename)),
+            Err(AppError::DuplicateId { filenames, .. }) => {
+                return Err(already_exists(filenames.join(", ")));
+            }
+            Err(e) => return Err(e),
+        }
+        let path = self.get_path(&entry.filename);
+        if fs::try_exists(&path).await.map_err(internal)? {
+            return Err(already_exists(entry.filename));
+        }
+
+        fs::rename(self.trash.path(&trash_id), &path)
+            .await
+            .map_err(|e| Self::trash_io_error(&trash_id, e))?;
+        self.index
+            .insert(&self.config_dir, &entry.filename, id)
+            .await;
+
+        let app = self.load(entry.filename).await?;
+        let new = audited(&app.etag, &AppConfig::from(app.clone()))?;
+        self.audit
+            .append(&audit_entry(app.id.clone(), &self.actor, None, Some(new)))
+            .await?;
+        Ok(app)
+    }
+
+    async fn purge(&self, trash_id: String) -> AppResult<()> {
+        let _lock = self.lock().await?;
+        let entry = self.find_trashed(&trash_id).await?;
+        self.purge_entry(&entry).await
     }
 
-    async fn delete(&self, filename: String) -> AppResult<()> {
-        fs::remove_file(self.get_path(&filename))
+    async fn list_revisions(&self, app_id: AppId) -> AppResult<Vec<AppRevision>> {
+        let current = self.get(app_id.clone()).await?;
+        let io_error = |e: std::io::Error| AppError::FileIoError {
+            filename: current.filename.clone(),
+            source: e.into(),
+        };
+        let saved_at = fs::metadata(self.get_path(&current.filename))
+            .await
+            .and_then(|metadata| metadata.modified())
+            .map_err(io_error)?;
+
+        let mut revisions = self.history.list(&app_id).await.map_err(io_error)?;
+        // a revert can bring back the content of a recorded version
+        revisions.retain(|r| r.etag != current.etag);
+        revisions.sort_by_key(|r| std::cmp::Reverse(r.saved_at));
+        revisions.insert(
+            0,
+            AppRevision {
+                etag: current.etag,
+                saved_at,
+            },
+        );
+        Ok(revisions)
+    }
+
+    async fn get_revision(&self, app_id: AppId, etag: String) -> AppResult<App> {
+        let current = self.get(app_id.clone()).await?;
+        if current.etag == etag {
+            return Ok(current);
+        }
+
+        let bytes = self
+            .history
+            .read(&app_id, &etag)
             .await
             .map_err(|e| AppError::FileIoError {
-                filename,
+                filename: current.filename.clone(),
                 source: e.into(),
-            })
+            })?
+            .ok_or_else(|| AppError::RevisionNotFound {
+                id: app_id.clone(),
+                etag: etag.clone(),
+            })?;
+        let shared = self
+            .history
+            .read_shared(&app_id, &etag)
+            .await
+            .map_err(|e| AppError::FileIoError {
+                filename: current.filename.clone(),
+                source: e.into(),
+            })?;
+        // old versions may have an older schema version, they are migrated like current files
+        // and extended with the shared file recorded with them, or the current one for the
+        // versions recorded before shared files were kept
+        let (config, _) = self
+            .resolve_config_with(&bytes, shared, &current.filename)
+            .await?;
+        Ok(Self::to_app(config, etag, current.filename))
     }
 
-    async fn update(&self, app: App) -> AppResult<App> {
-        let path = self.get_path(&app.filename);
+    async fn diff_revisions(
+        &self,
+        app_id: AppId,
+        from: String,
+        to: String,
+    ) -> AppResult<Vec<AppChange>> {
+        let from = self.get_revision(app_id.clone(), from).await?;
+        let to = self.get_revision(app_id, to).await?;
 
-        let current_bytes = fs::read(&path).await.map_err(|e| AppError::FileIoError {
-            filename: app.filename.clone(),
-            source: e.into(),
-        })?;
-        let current_etag = Self::calculate_etag(&current_bytes);
-        if current_etag != app.etag {
-            return Err(AppError::Conflict {
-                filename: app.filename.clone(),
-            });
-        }
+        let from = serde_json::to_value(AppConfig::from(from)).map_err(internal)?;
+        let to = serde_json::to_value(AppConfig::from(to)).map_err(internal)?;
+        Ok(diff(&from, &to))
+    }
 
-        let config = AppConfig {
-            id: app.id,
-            name: app.name,
-            envs: app.envs,
-            datasets: app.datasets,
-            pipelines: app.pipelines,
+    async fn revert(&self, app_id: AppId, etag: String, revision: String) -> AppResult<App> {
+        let mut app = self.get_revision(app_id, revision).await?;
+        // written over the current version, a stale etag must not merge the old content away
+        app.etag = etag;
+        self.replace(app).await
+    }
+
+    async fn audit_log(&self, query: AuditQuery) -> AppResult<Vec<AuditEntry>> {
+        self.audit.query(&query).await
+    }
+
+    async fn add_env(
+        &self,
+        app_id: AppId,
+        etag: String,
+        name: String,
+        url: String,
+    ) -> AppResult<App> {
+        let env = Env {
+            id: Self::generate_id(&name)?,
+            url,
+            name,
+            headers: vec![],
+            auth: None,
         };
+        self.edit_config(&app_id, &etag, |config, _| {
+            config.envs.push(env);
+            Ok(())
+        })
+        .await
+    }
 
-        self.upsert_config(&config, app.filename).await
+    async fn update_env(&self, app_id: AppId, etag: String, env: Env) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.update_env(filename, env)
+        })
+        .await
+    }
+
+    async fn remove_env(&self, app_id: AppId, etag: String, env_id: EnvId) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.remove_env(filename, &env_id)
+        })
+        .await
+    }
+
+    async fn add_dataset(&self, app_id: AppId, etag: String, name: String) -> AppResult<App> {
+        let dataset = Dataset {
+            id: Self::generate_id(&name)?,
+            name,
+        };
+        self.edit_config(&app_id, &etag, |config, _| {
+            config.datasets.push(dataset);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_dataset(
+        &self,
+        app_id: AppId,
+        etag: String,
+        dataset: Dataset,
+    ) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.update_dataset(filename, dataset)
+        })
+        .await
+    }
+
+    async fn remove_dataset(
+        &self,
+        app_id: AppId,
+        etag: String,
+        dataset_id: DatasetId,
+    ) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.remove_dataset(filename, &dataset_id)
+        })
+        .await
+    }
+
+    async fn add_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        name: String,
+        route: String,
+        env_id: EnvId,
+        dataset_id: DatasetId,
+    ) -> AppResult<App> {
+        let pipeline = Pipeline {
+            id: Self::generate_id(&name)?,
+            name,
+            route,
+            env_id,
+            dataset_id,
+            ping: None,
+        };
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.add_pipeline(filename, pipeline)
+        })
+        .await
+    }
+
+    async fn update_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        pipeline: Pipeline,
+    ) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.update_pipeline(filename, pipeline)
+        })
+        .await
+    }
+
+    async fn remove_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        pipeline_id: PipelineId,
+    ) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.remove_pipeline(filename, &pipeline_id)
+        })
+        .await
     }
 }
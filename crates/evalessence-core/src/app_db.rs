@@ -0,0 +1,1145 @@
+use crate::app_archive::{
+    copy_datasets, export_app, import_ids, read_app_archive, renew_dataset_ids,
+    write_imported_datasets,
+};
+use crate::app_audit::{AUDIT_FILENAME, AppAuditLog, audit_entry, default_actor};
+use crate::app_core::{AppConfig, FileAppService, audited, internal};
+use crate::app_diff::diff;
+use crate::app_history::AppHistory;
+use crate::app_listing::page;
+use crate::app_merge::merge;
+use crate::app_migrations::CURRENT_SCHEMA_VERSION;
+use crate::app_probe::probe_env;
+use crate::app_secrets::SecretResolver;
+use crate::app_trash::{AppTrash, TrashEntry, new_trash_id};
+use crate::file_utils::atomic_write_async;
+use async_trait::async_trait;
+use duckdb::{Connection, OptionalExt, params};
+use evalessence_api::app::{
+    App, AppChange, AppError, AppEvent, AppEventStream, AppHeader, AppHeaderPage, AppId,
+    AppListOptions, AppResult, AppRevision, AppService, AuditEntry, AuditQuery, Dataset, DatasetId,
+    Env, EnvHealth, EnvId, Pipeline, PipelineId, ProbeOptions, ResolvedHeader, TrashedApp,
+};
+use evalessence_api::dataset::DatasetService;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::sync::{Mutex, mpsc};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+// Apps stored in a DuckDB database instead of a config dir, for servers shared by a team.
+// Each app is a row holding the YAML content of the app-{id}.yaml file it would be, so etags,
+// conflicts and merges work like for the files, and apps can be moved from one service to the
+// other (see `import_config_dir` and `export_config_dir`). Errors name the app by this file.
+// Revisions and deleted apps are kept in their own tables, and secrets are read from the .env
+// file next to the database, and the audit log is written there too.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS apps (
+    id VARCHAR PRIMARY KEY,
+    content VARCHAR NOT NULL,
+    etag VARCHAR NOT NULL,
+    modified_at BIGINT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS app_revisions (
+    app_id VARCHAR NOT NULL,
+    etag VARCHAR NOT NULL,
+    content VARCHAR NOT NULL,
+    saved_at BIGINT NOT NULL,
+    PRIMARY KEY (app_id, etag)
+);
+CREATE TABLE IF NOT EXISTS app_trash (
+    trash_id VARCHAR PRIMARY KEY,
+    app_id VARCHAR NOT NULL,
+    content VARCHAR NOT NULL,
+    deleted_at BIGINT NOT NULL
+);
+";
+
+pub struct DuckDbAppService {
+    // the etag check and the write of an app are done under the same lock
+    conn: Mutex<Connection>,
+    secrets_dir: PathBuf,
+    watchers: std::sync::Mutex<Vec<mpsc::UnboundedSender<AppResult<AppEvent>>>>,
+    // deleted apps older than this are purged, kept until purged explicitly if None
+    trash_retention: Option<Duration>,
+    audit: AppAuditLog,
+    // who the changes are recorded from in the audit log
+    actor: String,
+}
+
+/// What [`DuckDbAppService::import_config_dir`] copied into the database
+pub struct ImportedConfigDir {
+    /// the id of each copied app, or why it could not be copied
+    pub apps: Vec<AppResult<AppId>>,
+    /// the trash id of each copied deleted app, or why it could not be copied
+    pub trash: Vec<AppResult<String>>,
+}
+
+// An app as stored in the database
+struct AppRow {
+    content: String,
+    etag: String,
+    modified_at: SystemTime,
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    i64::try_from(millis).unwrap_or(i64::MAX)
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(u64::try_from(millis).unwrap_or_default())
+}
+
+impl DuckDbAppService {
+    /// Open the database at `database`, creating it if needed.
+    ///
+    /// # Errors
+    /// Returns [`AppError::Internal`] if the database can't be opened or its tables created.
+    pub fn new(database: impl AsRef<Path>) -> AppResult<Self> {
+        let database = database.as_ref();
+        let conn = Connection::open(database).map_err(internal)?;
+        conn.execute_batch(SCHEMA).map_err(internal)?;
+        let dir = database.parent().unwrap_or(Path::new("."));
+        Ok(Self {
+            conn: Mutex::new(conn),
+            secrets_dir: dir.to_path_buf(),
+            watchers: std::sync::Mutex::default(),
+            trash_retention: None,
+            audit: AppAuditLog::new(&dir.join(AUDIT_FILENAME)),
+            actor: default_actor(),
+        })
+    }
+
+    /// Record the changes made through this service as made by `actor` in the audit log,
+    /// instead of the user running it
+    #[must_use]
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    /// Purge the deleted apps once they have been in the trash for `retention`
+    #[must_use]
+    pub const fn with_trash_retention(mut self, retention: Duration) -> Self {
+        self.trash_retention = Some(retention);
+        self
+    }
+
+    /// Copy the apps of the YAML `config_dir` into the database, with their revisions, and the
+    /// deleted apps of its trash. Apps extending a shared file are copied with the shared
+    /// entities included, the others keep the content of their file. The files that can't be
+    /// loaded and the apps already in the database are reported in the result, the other apps
+    /// are copied.
+    ///
+    /// # Errors
+    /// Returns [`AppError::Internal`] if `config_dir` can't be read.
+    pub async fn import_config_dir(&self, config_dir: &Path) -> AppResult<ImportedConfigDir> {
+        let files = FileAppService::new(config_dir);
+        let history = AppHistory::new(config_dir);
+        let mut apps = Vec::new();
+        for app in files.list().await? {
+            apps.push(match app {
+                Ok(app) => self.import_file(&files, &history, app).await,
+                Err(e) => Err(e),
+            });
+        }
+
+        let trash = AppTrash::new(config_dir);
+        let mut trashed = Vec::new();
+        for entry in trash.list().await.map_err(internal)? {
+            trashed.push(self.import_trashed(&files, &history, &trash, entry).await);
+        }
+        Ok(ImportedConfigDir {
+            apps,
+            trash: trashed,
+        })
+    }
+
+    async fn import_file(
+        &self,
+        files: &FileAppService,
+        history: &AppHistory,
+        app: App,
+    ) -> AppResult<AppId> {
+        let content = if app.extends.is_none() {
+            fs::read_to_string(files.get_path(&app.filename))
+                .await
+                .map_err(|e| AppError::FileIoError {
+                    filename: app.filename.clone(),
+                    source: e.into(),
+                })?
+        } else {
+            let config = AppConfig {
+                extends: None,
+                ..AppConfig::from(app.clone())
+            };
+            FileAppService::to_yaml(&config, None)?
+        };
+        let etag = FileAppService::calculate_etag(content.as_bytes());
+        let revisions = Self::read_history(files, history, &app.id, &app.filename).await?;
+
+        let mut conn = self.conn.lock().await;
+        if Self::read_row(&conn, &app.id)?.is_some() {
+            return Err(AppError::AlreadyExists {
+                id: app.id,
+                filename: app.filename,
+            });
+        }
+        let tx = conn.transaction().map_err(internal)?;
+        tx.execute(
+            "INSERT INTO apps VALUES (?, ?, ?, ?)",
+            params![app.id.as_str(), content, etag, to_millis(SystemTime::now())],
+        )
+        .map_err(internal)?;
+        Self::insert_revisions(&tx, &app.id, &etag, &revisions)?;
+        tx.commit().map_err(internal)?;
+        self.notify(&AppEvent::Created {
+            id: app.id.clone(),
+            filename: app.filename.clone(),
+            etag: etag.clone(),
+        });
+
+        let new = audited(&etag, &Self::parse_row(app.id.as_str(), &content)?)?;
+        self.audit
+            .append(&audit_entry(app.id.clone(), &self.actor, None, Some(new)))
+            .await?;
+        Ok(app.id)
+    }
+
+    // Copy a deleted app of the trash, with its revisions, under the same trash id
+    async fn import_trashed(
+        &self,
+        files: &FileAppService,
+        history: &AppHistory,
+        trash: &AppTrash,
+        entry: TrashEntry,
+    ) -> AppResult<String> {
+        let bytes =
+            fs::read(trash.path(&entry.trash_id))
+                .await
+                .map_err(|e| AppError::FileIoError {
+                    filename: entry.trash_id.clone(),
+                    source: e.into(),
+                })?;
+        let content = Self::stored_content(files, bytes, None, &entry.filename).await?;
+        let revisions = Self::read_history(files, history, &entry.id, &entry.filename).await?;
+
+        let mut conn = self.conn.lock().await;
+        let tx = conn.transaction().map_err(internal)?;
+        let inserted = tx
+            .execute(
+                "INSERT INTO app_trash VALUES (?, ?, ?, ?) ON CONFLICT DO NOTHING",
+                params![
+                    entry.trash_id,
+                    entry.id.as_str(),
+                    content,
+                    to_millis(entry.deleted_at)
+                ],
+            )
+            .map_err(internal)?;
+        if inserted == 0 {
+            return Err(AppError::AlreadyExists {
+                id: entry.id,
+                filename: entry.trash_id,
+            });
+        }
+        let etag = FileAppService::calculate_etag(content.as_bytes());
+        Self::insert_revisions(&tx, &entry.id, &etag, &revisions)?;
+        tx.commit().map_err(internal)?;
+        Ok(entry.trash_id)
+    }
+
+    // The revisions of the app in the history of a config dir, as (etag, content, saved_at)
+    // rows of app_revisions
+    async fn read_history(
+        files: &FileAppService,
+        history: &AppHistory,
+        id: &AppId,
+        filename: &str,
+    ) -> AppResult<Vec<(String, String, SystemTime)>> {
+        let io_error = |e: std::io::Error| AppError::FileIoError {
+            filename: filename.to_string(),
+            source: e.into(),
+        };
+        let mut revisions = Vec::new();
+        for revision in history.list(id).await.map_err(io_error)? {
+            let Some(bytes) = history.read(id, &revision.etag).await.map_err(io_error)? else {
+                continue;
+            };
+            let shared = history
+                .read_shared(id, &revision.etag)
+                .await
+                .map_err(io_error)?;
+            let content = Self::stored_content(files, bytes, shared, filename).await?;
+            let etag = FileAppService::calculate_etag(content.as_bytes());
+            revisions.push((etag, content, revision.saved_at));
+        }
+        Ok(revisions)
+    }
+
+    // The content of a config file as stored in the database: with the entities of the shared
+    // file it extends included, `shared` or the current shared file
+    async fn stored_content(
+        files: &FileAppService,
+        bytes: Vec<u8>,
+        shared: Option<Vec<u8>>,
+        filename: &str,
+    ) -> AppResult<String> {
+        if FileAppService::parse_config(&bytes, filename)?
+            .extends
+            .is_none()
+        {
+            return String::from_utf8(bytes).map_err(internal);
+        }
+        let (config, _) = files.resolve_config_with(&bytes, shared, filename).await?;
+        let config = AppConfig {
+            extends: None,
+            ..config
+        };
+        FileAppService::to_yaml(&config, None)
+    }
+
+    // Add imported revisions of the app, but the one with the content of the app `etag`
+    fn insert_revisions(
+        tx: &duckdb::Transaction<'_>,
+        id: &AppId,
+        etag: &str,
+        revisions: &[(String, String, SystemTime)],
+    ) -> AppResult<()> {
+        for (revision_etag, content, saved_at) in revisions {
+            if revision_etag != etag {
+                tx.execute(
+                    "INSERT OR REPLACE INTO app_revisions VALUES (?, ?, ?, ?)",
+                    params![id.as_str(), revision_etag, content, to_millis(*saved_at)],
+                )
+                .map_err(internal)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write every app of the database as an app-{id}.yaml file of `config_dir`. Files that
+    /// already exist are not overwritten and reported as [`AppError::AlreadyExists`] in the
+    /// result, with the apps that can't be written. Returns the names of the written files.
+    ///
+    /// # Errors
+    /// Returns [`AppError::Internal`] if the database can't be read.
+    pub async fn export_config_dir(&self, config_dir: &Path) -> AppResult<Vec<AppResult<String>>> {
+        let rows = {
+            let conn = self.conn.lock().await;
+            Self::read_rows(&conn)?
+        };
+        fs::create_dir_all(config_dir).await.map_err(internal)?;
+
+        let mut exported = Vec::new();
+        for (id, row) in rows {
+            let filename = format!("app-{id}.yaml");
+            let path = config_dir.join(&filename);
+            let written =
+                match fs::try_exists(&path).await {
+                    Ok(true) => Err(AppError::AlreadyExists {
+                        id: Self::load_row(&id, &row)?.id,
+                        filename: filename.clone(),
+                    }),
+                    Ok(false) => atomic_write_async(path, row.content).await.map_err(|e| {
+                        AppError::FileIoError {
+                            filename: filename.clone(),
+                            source: e.into(),
+                        }
+                    }),
+                    Err(e) => Err(internal(e)),
+                };
+            exported.push(written.map(|()| filename));
+        }
+        Ok(exported)
+    }
+
+    fn filename(id: &str) -> String {
+        format!("app-{id}.yaml")
+    }
+
+    fn notify(&self, event: &AppEvent) {
+        if let Ok(mut watchers) = self.watchers.lock() {
+            // the receivers are gone when their streams are dropped
+            watchers.retain(|tx| tx.send(Ok(event.clone())).is_ok());
+        }
+    }
+
+    fn read_row(conn: &Connection, id: &AppId) -> AppResult<Option<AppRow>> {
+        conn.query_row(
+            "SELECT content, etag, modified_at FROM apps WHERE id = ?",
+            [id.as_str()],
+            |row| {
+                Ok(AppRow {
+                    content: row.get(0)?,
+                    etag: row.get(1)?,
+                    modified_at: from_millis(row.get(2)?),
+                })
+            },
+        )
+        .optional()
+        .map_err(internal)
+    }
+
+    // Every app row, by id
+    fn read_rows(conn: &Connection) -> AppResult<Vec<(String, AppRow)>> {
+        let mut stmt = conn
+            .prepare("SELECT id, content, etag, modified_at FROM apps ORDER BY id")
+            .map_err(internal)?;
+        stmt.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                AppRow {
+                    content: row.get(1)?,
+                    etag: row.get(2)?,
+                    modified_at: from_millis(row.get(3)?),
+                },
+            ))
+        })
+        .map_err(internal)?
+        .collect::<Result<_, _>>()
+        .map_err(internal)
+    }
+
+    // Parse the content of a row like the content of a file, validating it
+    fn parse_row(id: &str, content: &str) -> AppResult<AppConfig> {
+        let filename = Self::filename(id);
+        let config = FileAppService::parse_config(content.as_bytes(), &filename)?;
+        FileAppService::validate_config(&config, &filename)?;
+        Ok(config)
+    }
+
+    fn load_row(id: &str, row: &AppRow) -> AppResult<App> {
+        let config = Self::parse_row(id, &row.content)?;
+        let filename = Self::filename(id);
+        if config.id.as_str() != id {
+            return Err(AppError::IdMismatch {
+                filename,
+                id: config.id,
+            });
+        }
+        Ok(FileAppService::to_app(config, row.etag.clone(), filename))
+    }
+
+    fn load(conn: &Connection, id: &AppId) -> AppResult<App> {
+        let row = Self::read_row(conn, id)?.ok_or_else(|| AppError::NotFound { id: id.clone() })?;
+        Self::load_row(id.as_str(), &row)
+    }
+
+    // The current row of the app, failing with a Conflict if it has been modified since `etag`
+    fn read_checked(conn: &Connection, id: &AppId, etag: &str) -> AppResult<(AppRow, AppConfig)> {
+        let row = Self::read_row(conn, id)?.ok_or_else(|| AppError::NotFound { id: id.clone() })?;
+        if row.etag != etag {
+            return Err(AppError::Conflict {
+                filename: Self::filename(id.as_str()),
+            });
+        }
+        let config = Self::parse_row(id.as_str(), &row.content)?;
+        Ok((row, config))
+    }
+
+    // The content of the app at revision `etag`, if it has been recorded
+    fn read_revision(conn: &Connection, id: &AppId, etag: &str) -> AppResult<Option<String>> {
+        conn.query_row(
+            "SELECT content FROM app_revisions WHERE app_id = ? AND etag = ?",
+            [id.as_str(), etag],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(internal)
+    }
+
+    // Write `config`, over the `previous` row of the app if any, which is kept as a revision
+    async fn save(
+        &self,
+        conn: &mut Connection,
+        config: &AppConfig,
+        previous: Option<&AppRow>,
+    ) -> AppResult<App> {
+        let etag = Self::write(conn, config, previous)?;
+        self.record_saved(config, previous, etag).await
+    }
+
+    // Write the row of `config` without notifying it, returning its etag
+    fn write(
+        conn: &mut Connection,
+        config: &AppConfig,
+        previous: Option<&AppRow>,
+    ) -> AppResult<String> {
+        let filename = Self::filename(config.id.as_str());
+        FileAppService::validate_config(config, &filename)?;
+        if config.extends.is_some() {
+            return Err(AppError::ValidationError {
+                filename,
+                source: anyhow::anyhow!(
+                    "apps stored in a database can't extend a shared config file"
+                ),
+            });
+        }
+
+        let content = FileAppService::to_yaml(config, previous.map(|p| p.content.as_bytes()))?;
+        let etag = FileAppService::calculate_etag(content.as_bytes());
+        Self::write_row(conn, &config.id, &content, &etag, previous)?;
+        Ok(etag)
+    }
+
+    // Notify the watchers and record in the audit log the row written for `config`
+    async fn record_saved(
+        &self,
+        config: &AppConfig,
+        previous: Option<&AppRow>,
+        etag: String,
+    ) -> AppResult<App> {
+        let filename = Self::filename(config.id.as_str());
+        let old = match previous {
+            Some(previous) => Some(audited(
+                &previous.etag,
+                &Self::parse_row(config.id.as_str(), &previous.content)?,
+            )?),
+            None => None,
+        };
+
+        self.notify(&match previous {
+            Some(_) => AppEvent::Modified {
+                id: config.id.clone(),
+                filename: filename.clone(),
+                etag: etag.clone(),
+            },
+            None => AppEvent::Created {
+                id: config.id.clone(),
+                filename: filename.clone(),
+                etag: etag.clone(),
+            },
+        });
+        let new = audited(&etag, config)?;
+        self.audit
+            .append(&audit_entry(config.id.clone(), &self.actor, old, Some(new)))
+            .await?;
+        Ok(FileAppService::to_app(config.clone(), etag, filename))
+    }
+
+    // Remove the row of an app written by an operation which failed after: the app never
+    // existed for the users, it is neither trashed nor recorded in the audit log
+    async fn discard(&self, id: &AppId) -> AppResult<()> {
+        let conn = self.conn.lock().await;
+        conn.execute("DELETE FROM apps WHERE id = ?", [id.as_str()])
+            .map_err(internal)?;
+        Ok(())
+    }
+
+    // Write the row of the app, keeping the `previous` one as a revision
+    fn write_row(
+        conn: &mut Connection,
+        id: &AppId,
+        content: &str,
+        etag: &str,
+        previous: Option<&AppRow>,
+    ) -> AppResult<()> {
+        let now = to_millis(SystemTime::now());
+        let tx = conn.transaction().map_err(internal)?;
+        match previous {
+            Some(previous) => {
+                tx.execute(
+                    "INSERT OR REPLACE INTO app_revisions VALUES (?, ?, ?, ?)",
+                    params![
+                        id.as_str(),
+                        previous.etag,
+                        previous.content,
+                        to_millis(previous.modified_at)
+                    ],
+                )
+                .map_err(internal)?;
+                tx.execute(
+                    "UPDATE apps SET content = ?, etag = ?, modified_at = ? WHERE id = ?",
+                    params![content, etag, now, id.as_str()],
+                )
+                .map_err(internal)?;
+            }
+            None => {
+                tx.execute(
+                    "INSERT INTO apps VALUES (?, ?, ?, ?)",
+                    params![id.as_str(), content, etag, now],
+                )
+                .map_err(internal)?;
+            }
+        }
+        tx.commit().map_err(internal)
+    }
+
+    // Move the row of the app to the trash
+    fn trash_row(conn: &mut Connection, id: &AppId, row: &AppRow) -> AppResult<()> {
+        let now = to_millis(SystemTime::now());
+        let trash_id = new_trash_id(now, &Self::filename(id.as_str()));
+        let tx = conn.transaction().map_err(internal)?;
+        tx.execute(
+            "INSERT INTO app_trash VALUES (?, ?, ?, ?)",
+            params![trash_id, id.as_str(), row.content, now],
+        )
+        .map_err(internal)?;
+        tx.execute("DELETE FROM apps WHERE id = ?", [id.as_str()])
+            .map_err(internal)?;
+        tx.commit().map_err(internal)
+    }
+
+    // Move the deleted app `trash_id` back to the apps
+    fn restore_row(
+        conn: &mut Connection,
+        trash_id: &str,
+        id: &AppId,
+        content: &str,
+        etag: &str,
+    ) -> AppResult<()> {
+        let tx = conn.transaction().map_err(internal)?;
+        tx.execute(
+            "INSERT INTO apps VALUES (?, ?, ?, ?)",
+            params![id.as_str(), content, etag, to_millis(SystemTime::now())],
+        )
+        .map_err(internal)?;
+        tx.execute("DELETE FROM app_trash WHERE trash_id = ?", [trash_id])
+            .map_err(internal)?;
+        tx.commit().map_err(internal)
+    }
+
+    // Apply `edit` on the current config of the app, then save it
+    async fn edit_config(
+        &self,
+        app_id: &AppId,
+        etag: &str,
+        edit: impl FnOnce(&mut AppConfig, &str) -> AppResult<()> + Send,
+    ) -> AppResult<App> {
+        let mut conn = self.conn.lock().await;
+        let (current, mut config) = Self::read_checked(&conn, app_id, etag)?;
+        edit(&mut config, &Self::filename(app_id.as_str()))?;
+        self.save(&mut conn, &config, Some(&current)).await
+    }
+
+    // Remove the revisions of `id` unless the id is used by an app, or by another deleted app
+    fn purge_revisions(conn: &Connection, id: &str) -> AppResult<()> {
+        conn.execute(
+            "DELETE FROM app_revisions WHERE app_id = ?1
+             AND NOT EXISTS (SELECT 1 FROM apps WHERE id = ?1)
+             AND NOT EXISTS (SELECT 1 FROM app_trash WHERE app_id = ?1)",
+            [id],
+        )
+        .map_err(internal)?;
+        Ok(())
+    }
+
+    // Purge the deleted apps older than the retention period, if any
+    fn purge_expired(&self, conn: &Connection) -> AppResult<()> {
+        let Some(retention) = self.trash_retention else {
+            return Ok(());
+        };
+        let oldest = to_millis(SystemTime::now()) - to_millis(UNIX_EPOCH + retention);
+        let mut stmt = conn
+            .prepare("DELETE FROM app_trash WHERE deleted_at < ? RETURNING app_id")
+            .map_err(internal)?;
+        let ids: Vec<String> = stmt
+            .query_map([oldest], |row| row.get(0))
+            .map_err(internal)?
+            .collect::<Result<_, _>>()
+            .map_err(internal)?;
+        for id in ids {
+            Self::purge_revisions(conn, &id)?;
+        }
+        Ok(())
+    }
+
+    fn resolver(&self) -> SecretResolver {
+        SecretResolver::new(&self.secrets_dir)
+    }
+}
+
+#[async_trait]
+impl AppService for DuckDbAppService {
+    async fn list(&self) -> AppResult<Vec<AppResult<App>>> {
+        let conn = self.conn.lock().await;
+        Ok(Self::read_rows(&conn)?
+            .iter()
+            .map(|(id, row)| Self::load_row(id, row))
+            .collect())
+    }
+
+    async fn list_headers(&self, options: AppListOptions) -> AppResult<AppHeaderPage> {
+        let rows = {
+            let conn = self.conn.lock().await;
+            Self::read_rows(&conn)?
+        };
+        let mut headers = vec![];
+        let mut errors = vec![];
+        for (id, row) in &rows {
+            match Self::load_row(id, row) {
+                Ok(app) => headers.push(AppHeader {
+                    id: app.id,
+                    name: app.name,
+                    filename: app.filename,
+                    env_count: app.envs.len(),
+                    dataset_count: app.datasets.len(),
+                    pipeline_count: app.pipelines.len(),
+                    modified: row.modified_at,
+                }),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let (headers, total_count) = page(headers, &options);
+        Ok(AppHeaderPage {
+            headers,
+            total_count,
+            errors,
+        })
+    }
+
+    async fn create(&self, name: String) -> AppResult<App> {
+        let config = AppConfig {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            id: FileAppService::generate_id(&name)?,
+            name,
+            extends: None,
+            envs: vec![],
+            datasets: vec![],
+            pipelines: vec![],
+        };
+        let mut conn = self.conn.lock().await;
+        self.save(&mut conn, &config, None).await
+    }
+
+    async fn get(&self, id: AppId) -> AppResult<App> {
+        let conn = self.conn.lock().await;
+        Self::load(&conn, &id)
+    }
+
+    async fn delete(&self, id: AppId) -> AppResult<()> {
+        let mut conn = self.conn.lock().await;
+        let row =
+            Self::read_row(&conn, &id)?.ok_or_else(|| AppError::NotFound { id: id.clone() })?;
+        Self::trash_row(&mut conn, &id, &row)?;
+        self.notify(&AppEvent::Deleted {
+            id: id.clone(),
+            filename: Self::filename(id.as_str()),
+        });
+
+        // rows that can't be loaded can be deleted, their content is not in the audit log
+        let old = match Self::parse_row(id.as_str(), &row.content) {
+            Ok(config) => Some(audited(&row.etag, &config)?),
+            Err(_) => None,
+        };
+        self.audit
+            .append(&audit_entry(id, &self.actor, old, None))
+            .await?;
+        self.purge_expired(&conn)
+    }
+
+    async fn update(&self, app: App) -> AppResult<App> {
+        let mut conn = self.conn.lock().await;
+        let current = Self::read_row(&conn, &app.id)?
+            .ok_or_else(|| AppError::NotFound { id: app.id.clone() })?;
+        let filename = Self::filename(app.id.as_str());
+
+        let config = if current.etag == app.etag {
+            AppConfig::from(app)
+        } else {
+            // merged like a stale update of a file, from the version of `app.etag`
+            let base = Self::read_revision(&conn, &app.id, &app.etag)?.ok_or_else(|| {
+                AppError::Conflict {
+                    filename: filename.clone(),
+                }
+            })?;
+            let to_value = |config: AppConfig| serde_json::to_value(config).map_err(internal);
+            let base = to_value(Self::parse_row(app.id.as_str(), &base)?)?;
+            let theirs = to_value(Self::parse_row(app.id.as_str(), &current.content)?)?;
+            let ours = to_value(AppConfig::from(app))?;
+            let merged =
+                merge(&base, &ours, &theirs).map_err(|conflicts| AppError::MergeConflict {
+                    filename: filename.clone(),
+                    conflicts,
+                })?;
+            serde_json::from_value(merged).map_err(internal)?
+        };
+        self.save(&mut conn, &config, Some(&current)).await
+    }
+
+    async fn duplicate(
+        &self,
+        app_id: AppId,
+        name: String,
+        datasets: &dyn DatasetService,
+    ) -> AppResult<App> {
+        let mut conn = self.conn.lock().await;
+        let mut config = AppConfig::from(Self::load(&conn, &app_id)?);
+        config.id = FileAppService::generate_id(&name)?;
+        config.name = name;
+        let new_dataset_ids = renew_dataset_ids(&mut config, |_| Ok(true))?;
+
+        // the app is written before its datasets, and discarded if they can't be. It is only
+        // notified and audited once it is complete
+        let etag = Self::write(&mut conn, &config, None)?;
+        drop(conn);
+        if let Err(e) = copy_datasets(new_dataset_ids, datasets) {
+            self.discard(&config.id).await?;
+            return Err(e);
+        }
+        self.record_saved(&config, None, etag).await
+    }
+
+    async fn export(
+        &self,
+        app_id: AppId,
+        datasets: &dyn DatasetService,
+        archive: PathBuf,
+    ) -> AppResult<()> {
+        let app = self.get(app_id).await?;
+        export_app(app, datasets, archive).await
+    }
+
+    async fn import(&self, archive: PathBuf, datasets: &dyn DatasetService) -> AppResult<App> {
+        let (content, archive_name) = read_app_archive(archive).await?;
+        let config = FileAppService::parse_config(&content.config_yaml, &archive_name)?;
+        FileAppService::validate_config(&config, &archive_name)?;
+
+        let mut conn = self.conn.lock().await;
+        let rows = Self::read_rows(&conn)?;
+        let apps: Vec<App> = rows
+            .iter()
+            .filter_map(|(id, row)| Self::load_row(id, row).ok())
+            .collect();
+        let ids: HashSet<&str> = rows.iter().map(|(id, _)| id.as_str()).collect();
+        let (config, contents) = import_ids(config, content.datasets, datasets, &apps, |id| {
+            ids.contains(id.as_str())
+        })?;
+
+        // the app is written before its datasets, and discarded if they can't be. It is only
+        // notified and audited once it is complete
+        let etag = Self::write(&mut conn, &config, None)?;
+        drop(conn);
+        if let Err(e) = write_imported_datasets(contents, datasets) {
+            self.discard(&config.id).await?;
+            return Err(e);
+        }
+        self.record_saved(&config, None, etag).await
+    }
+
+    fn watch(&self) -> AppResult<AppEventStream> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.watchers
+            .lock()
+            .map_err(|e| internal(anyhow::anyhow!("{e}")))?
+            .push(tx);
+        Ok(Box::pin(UnboundedReceiverStream::new(rx)))
+    }
+
+    async fn resolve_app(&self, app_id: AppId) -> AppResult<App> {
+        let app = self.get(app_id).await?;
+        FileAppService::interpolate(app, &mut self.resolver()).await
+    }
+
+    async fn resolve_env_headers(
+        &self,
+        app_id: AppId,
+        env_id: EnvId,
+    ) -> AppResult<Vec<ResolvedHeader>> {
+        let mut secrets = self.resolver();
+        let app = FileAppService::interpolate(self.get(app_id).await?, &mut secrets).await?;
+        let env = FileAppService::find_env(&app, &env_id)?;
+
+        secrets.resolve_headers(env).await
+    }
+
+    async fn probe_env(
+        &self,
+        app_id: AppId,
+        env_id: EnvId,
+        options: ProbeOptions,
+    ) -> AppResult<EnvHealth> {
+        let mut secrets = self.resolver();
+        let app = FileAppService::interpolate(self.get(app_id).await?, &mut secrets).await?;
+        let env = FileAppService::find_env(&app, &env_id)?;
+        let headers = secrets.resolve_headers(env).await?;
+
+        probe_env(&app, env, &headers, &options).await
+    }
+
+    async fn list_trash(&self) -> AppResult<Vec<TrashedApp>> {
+        let conn = self.conn.lock().await;
+        self.purge_expired(&conn)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT trash_id, app_id, content, deleted_at FROM app_trash
+                 ORDER BY deleted_at DESC, trash_id DESC",
+            )
+            .map_err(internal)?;
+        let rows: Vec<(String, String, String, i64)> = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .map_err(internal)?
+            .collect::<Result<_, _>>()
+            .map_err(internal)?;
+
+        rows.into_iter()
+            .map(|(trash_id, id, content, deleted_at)| {
+                Ok(TrashedApp {
+                    trash_id,
+                    name: Self::parse_row(&id, &content)
+                        .ok()
+                        .map(|config| config.name),
+                    filename: Self::filename(&id),
+                    id: AppId::new(id).map_err(internal)?,
+                    deleted_at: from_millis(deleted_at),
+                })
+            })
+            .collect()
+    }
+
+    async fn restore(&self, trash_id: String) -> AppResult<App> {
+        let mut conn = self.conn.lock().await;
+        let (id, content): (String, String) = conn
+            .query_row(
+                "SELECT app_id, content FROM app_trash WHERE trash_id = ?",
+                [&trash_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(internal)?
+            .ok_or_else(|| AppError::TrashNotFound {
+                trash_id: trash_id.clone(),
+            })?;
+        let id = AppId::new(id).map_err(internal)?;
+
+        // the restored app must not replace an app created since
+        if Self::read_row(&conn, &id)?.is_some() {
+            return Err(AppError::AlreadyExists {
+                filename: Self::filename(id.as_str()),
+                id,
+            });
+        }
+
+        let etag = FileAppService::calculate_etag(content.as_bytes());
+        Self::restore_row(&mut conn, &trash_id, &id, &content, &etag)?;
+
+        self.notify(&AppEvent::Created {
+            id: id.clone(),
+            filename: Self::filename(id.as_str()),
+            etag,
+        });
+
+        let app = Self::load(&conn, &id)?;
+        let new = audited(&app.etag, &AppConfig::from(app.clone()))?;
+        self.audit
+            .append(&audit_entry(id, &self.actor, None, Some(new)))
+            .await?;
+        Ok(app)
+    }
+
+    async fn purge(&self, trash_id: String) -> AppResult<()> {
+        let conn = self.conn.lock().await;
+        let id: String = conn
+            .query_row(
+                "DELETE FROM app_trash WHERE trash_id = ? RETURNING app_id",
+                [&trash_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(internal)?
+            .ok_or(AppError::TrashNotFound { trash_id })?;
+        Self::purge_revisions(&conn, &id)
+    }
+
+    async fn list_revisions(&self, app_id: AppId) -> AppResult<Vec<AppRevision>> {
+        let conn = self.conn.lock().await;
+        let current = Self::read_row(&conn, &app_id)?
+            .ok_or_else(|| AppError::NotFound { id: app_id.clone() })?;
+
+        // a revert can bring back the content of a recorded version
+        let mut stmt = conn
+            .prepare(
+                "SELECT etag, saved_at FROM app_revisions WHERE app_id = ? AND etag <> ?
+                 ORDER BY saved_at DESC",
+            )
+            .map_err(internal)?;
+        let mut revisions = vec![AppRevision {
+            etag: current.etag.clone(),
+            saved_at: current.modified_at,
+        }];
+        let recorded = stmt
+            .query_map([app_id.as_str(), &current.etag], |row| {
+                Ok(AppRevision {
+                    etag: row.get(0)?,
+                    saved_at: from_millis(row.get(1)?),
+                })
+            })
+            .map_err(internal)?;
+        for revision in recorded {
+            revisions.push(revision.map_err(internal)?);
+        }
+        Ok(revisions)
+    }
+
+    async fn get_revision(&self, app_id: AppId, etag: String) -> AppResult<App> {
+        let conn = self.conn.lock().await;
+        let current = Self::load(&conn, &app_id)?;
+        if current.etag == etag {
+            return Ok(current);
+        }
+
+        let content = Self::read_revision(&conn, &app_id, &etag)?.ok_or_else(|| {
+            AppError::RevisionNotFound {
+                id: app_id.clone(),
+                etag: etag.clone(),
+            }
+        })?;
+        let config = Self::parse_row(app_id.as_str(), &content)?;
+        Ok(FileAppService::to_app(config, etag, current.filename))
+    }
+
+    async fn diff_revisions(
+        &self,
+        app_id: AppId,
+        from: String,
+        to: String,
+    ) -> AppResult<Vec<AppChange>> {
+        let from = self.get_revision(app_id.clone(), from).await?;
+        let to = self.get_revision(app_id, to).await?;
+
+        let from = serde_json::to_value(AppConfig::from(from)).map_err(internal)?;
+        let to = serde_json::to_value(AppConfig::from(to)).map_err(internal)?;
+        Ok(diff(&from, &to))
+    }
+
+    async fn revert(&self, app_id: AppId, etag: String, revision: String) -> AppResult<App> {
+        let app = self.get_revision(app_id.clone(), revision).await?;
+        // written over the current version, a stale etag must not merge the old content away
+        self.edit_config(&app_id, &etag, |config, _| {
+            *config = AppConfig::from(app);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn audit_log(&self, query: AuditQuery) -> AppResult<Vec<AuditEntry>> {
+        self.audit.query(&query).await
+    }
+
+    async fn add_env(
+        &self,
+        app_id: AppId,
+        etag: String,
+        name: String,
+        url: String,
+    ) -> AppResult<App> {
+        let env = Env {
+            id: FileAppService::generate_id(&name)?,
+            url,
+            name,
+            headers: vec![],
+            auth: None,
+        };
+        self.edit_config(&app_id, &etag, |config, _| {
+            config.envs.push(env);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_env(&self, app_id: AppId, etag: String, env: Env) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.update_env(filename, env)
+        })
+        .await
+    }
+
+    async fn remove_env(&self, app_id: AppId, etag: String, env_id: EnvId) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.remove_env(filename, &env_id)
+        })
+        .await
+    }
+
+    async fn add_dataset(&self, app_id: AppId, etag: String, name: String) -> AppResult<App> {
+        let dataset = Dataset {
+            id: FileAppService::generate_id(&name)?,
+            name,
+        };
+        self.edit_config(&app_id, &etag, |config, _| {
+            config.datasets.push(dataset);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_dataset(
+        &self,
+        app_id: AppId,
+        etag: String,
+        dataset: Dataset,
+    ) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.update_dataset(filename, dataset)
+        })
+        .await
+    }
+
+    async fn remove_dataset(
+        &self,
+        app_id: AppId,
+        etag: String,
+        dataset_id: DatasetId,
+    ) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.remove_dataset(filename, &dataset_id)
+        })
+        .await
+    }
+
+    async fn add_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        name: String,
+        route: String,
+        env_id: EnvId,
+        dataset_id: DatasetId,
+    ) -> AppResult<App> {
+        let pipeline = Pipeline {
+            id: FileAppService::generate_id(&name)?,
+            name,
+            route,
+            env_id,
+            dataset_id,
+            ping: None,
+        };
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.add_pipeline(filename, pipeline)
+        })
+        .await
+    }
+
+    async fn update_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        pipeline: Pipeline,
+    ) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.update_pipeline(filename, pipeline)
+        })
+        .await
+    }
+
+    async fn remove_pipeline(
+        &self,
+        app_id: AppId,
+        etag: String,
+        pipeline_id: PipelineId,
+    ) -> AppResult<App> {
+        self.edit_config(&app_id, &etag, |config, filename| {
+            config.remove_pipeline(filename, &pipeline_id)
+        })
+        .await
+    }
+}
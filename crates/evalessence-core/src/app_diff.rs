@@ -0,0 +1,65 @@
+use evalessence_api::app::AppChange;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+// Structural diff of two app configs converted to json values.
+// Objects are compared key by key, and arrays of objects with an `id` (envs, datasets,
+// pipelines) are compared entity by entity, so reordering entities is not a change.
+pub fn diff(old: &Value, new: &Value) -> Vec<AppChange> {
+    let mut changes = Vec::new();
+    diff_value("", Some(old), Some(new), &mut changes);
+    changes
+}
+
+fn diff_value(path: &str, old: Option<&Value>, new: Option<&Value>, out: &mut Vec<AppChange>) {
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+            for key in keys {
+                diff_value(&field_path(path, key), old.get(key), new.get(key), out);
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) if is_entity_list(old, new) => {
+            for (id, old_entity) in entities(old) {
+                let new_entity = entities(new).find(|(new_id, _)| *new_id == id);
+                diff_value(
+                    &format!("{path}[{id}]"),
+                    Some(old_entity),
+                    new_entity.map(|(_, e)| e),
+                    out,
+                );
+            }
+            for (id, new_entity) in entities(new) {
+                if !entities(old).any(|(old_id, _)| old_id == id) {
+                    diff_value(&format!("{path}[{id}]"), None, Some(new_entity), out);
+                }
+            }
+        }
+        (old, new) if old != new => out.push(AppChange {
+            path: path.to_string(),
+            old: old.cloned(),
+            new: new.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+pub fn field_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+pub fn entity_id(value: &Value) -> Option<&str> {
+    value.get("id")?.as_str()
+}
+
+pub fn is_entity_list(old: &[Value], new: &[Value]) -> bool {
+    old.iter().chain(new).all(|v| entity_id(v).is_some())
+}
+
+pub fn entities(values: &[Value]) -> impl Iterator<Item = (&str, &Value)> {
+    values.iter().filter_map(|v| Some((entity_id(v)?, v)))
+}
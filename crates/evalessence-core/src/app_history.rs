@@ -0,0 +1,129 @@
+use crate::file_utils::atomic_write_async;
+use evalessence_api::app::{AppId, AppRevision};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::fs;
+use tokio::task;
+
+// Replaced versions of the app files are kept in {config_dir}/.history/{app_id}/{etag}.yaml,
+// with the modification time of the version (the time it was saved, not replaced).
+// Versions of an app extending a shared file keep the shared file they were saved with in
+// {etag}.shared.yaml, they are resolved against it instead of the current one.
+// The directory is hidden, so list and watch ignore it.
+const HISTORY_DIR: &str = ".history";
+const REVISION_EXTENSION: &str = ".yaml";
+const SHARED_EXTENSION: &str = ".shared.yaml";
+
+pub struct AppHistory {
+    dir: PathBuf,
+}
+
+impl AppHistory {
+    pub fn new(config_dir: &Path) -> Self {
+        Self {
+            dir: config_dir.join(HISTORY_DIR),
+        }
+    }
+
+    fn app_dir(&self, id: &AppId) -> PathBuf {
+        self.dir.join(id.as_str())
+    }
+
+    // Keep a version of the app, saved at `saved_at`, with the content of the shared file it
+    // extends, if any
+    pub async fn record(
+        &self,
+        id: &AppId,
+        etag: &str,
+        bytes: Vec<u8>,
+        shared: Option<Vec<u8>>,
+        saved_at: SystemTime,
+    ) -> io::Result<()> {
+        let app_dir = self.app_dir(id);
+        fs::create_dir_all(&app_dir).await?;
+
+        // written first, a listed version always has its shared file
+        if let Some(shared) = shared {
+            let path = app_dir.join(format!("{etag}{SHARED_EXTENSION}"));
+            atomic_write_async(path, shared).await?;
+        }
+        let path = app_dir.join(format!("{etag}{REVISION_EXTENSION}"));
+        atomic_write_async(path.clone(), bytes).await?;
+        task::spawn_blocking(move || {
+            File::options()
+                .write(true)
+                .open(path)?
+                .set_modified(saved_at)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    // The recorded versions of the app, in no particular order
+    pub async fn list(&self, id: &AppId) -> io::Result<Vec<AppRevision>> {
+        let mut entries = match fs::read_dir(self.app_dir(id)).await {
+            Ok(entries) => entries,
+            // nothing has been recorded yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        let mut revisions = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(etag) = name.strip_suffix(REVISION_EXTENSION)
+                && is_etag(etag)
+            {
+                revisions.push(AppRevision {
+                    etag: etag.to_string(),
+                    saved_at: entry.metadata().await?.modified()?,
+                });
+            }
+        }
+        Ok(revisions)
+    }
+
+    // Content of the version `etag`, None if it has not been recorded
+    pub async fn read(&self, id: &AppId, etag: &str) -> io::Result<Option<Vec<u8>>> {
+        self.read_file(id, etag, REVISION_EXTENSION).await
+    }
+
+    // Content of the shared file extended by the version `etag`, None if it extends none or
+    // has been recorded before shared files were kept
+    pub async fn read_shared(&self, id: &AppId, etag: &str) -> io::Result<Option<Vec<u8>>> {
+        self.read_file(id, etag, SHARED_EXTENSION).await
+    }
+
+    async fn read_file(
+        &self,
+        id: &AppId,
+        etag: &str,
+        extension: &str,
+    ) -> io::Result<Option<Vec<u8>>> {
+        // the etag comes from the caller, it must not be able to point outside of the app dir
+        if !is_etag(etag) {
+            return Ok(None);
+        }
+        let path = self.app_dir(id).join(format!("{etag}{extension}"));
+        match fs::read(path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Forget every version of the app
+    pub async fn remove(&self, id: &AppId) -> io::Result<()> {
+        match fs::remove_dir_all(self.app_dir(id)).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+// etags are blake3 hashes in hex
+fn is_etag(etag: &str) -> bool {
+    etag.len() == 64 && etag.bytes().all(|b| b.is_ascii_hexdigit())
+}
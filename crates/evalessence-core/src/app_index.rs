@@ -0,0 +1,127 @@
+use crate::app_core::is_app_filename;
+use evalessence_api::app::{AppError, AppId, AppResult};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+use tokio::fs;
+
+// Only the id is read, other fields are ignored
+#[derive(Deserialize)]
+struct IdProbe {
+    id: AppId,
+}
+
+// The state of a file when its id was read: the id is read again when the file changes
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileStamp {
+    pub modified: SystemTime,
+    len: u64,
+}
+
+type IndexEntry = (FileStamp, Option<AppId>);
+
+// AppId -> config file index of a config dir.
+// Files can be edited, renamed or copied outside of the service, so every lookup lists the
+// directory, but a file is only parsed again when its modification time or size changes.
+// Files whose id can't be read are left out, loading them reports the actual error.
+#[derive(Default)]
+pub struct AppIndex {
+    files: Mutex<HashMap<String, IndexEntry>>,
+}
+
+impl AppIndex {
+    // Find the file of the app `id`, failing if no file or several files have this id
+    pub async fn resolve(&self, config_dir: &Path, id: &AppId) -> AppResult<String> {
+        let mut filenames = self.scan(config_dir).await?.remove(id).unwrap_or_default();
+        match filenames.len() {
+            0 => Err(AppError::NotFound { id: id.clone() }),
+            1 => Ok(filenames.remove(0)),
+            _ => {
+                filenames.sort();
+                Err(AppError::DuplicateId {
+                    id: id.clone(),
+                    filenames,
+                })
+            }
+        }
+    }
+
+    // Record a file just written by the service, so the next lookup doesn't parse it again
+    pub async fn insert(&self, config_dir: &Path, filename: &str, id: AppId) {
+        if let Ok(stamp) = stamp(config_dir, filename).await {
+            self.lock().insert(filename.to_string(), (stamp, Some(id)));
+        }
+    }
+
+    pub fn remove(&self, filename: &str) {
+        self.lock().remove(filename);
+    }
+
+    // Filenames of each app id found in the config dir
+    async fn scan(&self, config_dir: &Path) -> AppResult<HashMap<AppId, Vec<String>>> {
+        let mut entries = fs::read_dir(config_dir)
+            .await
+            .map_err(|e| AppError::Internal { source: e.into() })?;
+
+        let mut found = HashMap::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| AppError::Internal { source: e.into() })?
+        {
+            let filename = entry.file_name().to_string_lossy().into_owned();
+            if !is_app_filename(&filename) {
+                continue;
+            }
+            // the file may have been removed since the directory was listed
+            let Ok(stamp) = stamp(config_dir, &filename).await else {
+                continue;
+            };
+
+            let cached = self.lock().get(&filename).cloned();
+            let id = match cached {
+                Some((cached_stamp, id)) if cached_stamp == stamp => id,
+                _ => {
+                    let id = read_id(&config_dir.join(&filename)).await;
+                    self.lock().insert(filename.clone(), (stamp, id.clone()));
+                    id
+                }
+            };
+            found.insert(filename, id);
+        }
+
+        // forget the files that no longer exist
+        self.lock()
+            .retain(|filename, _| found.contains_key(filename));
+
+        let mut ids: HashMap<AppId, Vec<String>> = HashMap::new();
+        for (filename, id) in found {
+            if let Some(id) = id {
+                ids.entry(id).or_default().push(filename);
+            }
+        }
+        Ok(ids)
+    }
+
+    // the lock is never held across an await, a panic while holding it can't leave the map
+    // in an inconsistent state
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, IndexEntry>> {
+        self.files.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub async fn stamp(config_dir: &Path, filename: &str) -> std::io::Result<FileStamp> {
+    let metadata = fs::metadata(config_dir.join(filename)).await?;
+    Ok(FileStamp {
+        modified: metadata.modified()?,
+        len: metadata.len(),
+    })
+}
+
+async fn read_id(path: &Path) -> Option<AppId> {
+    let bytes = fs::read(path).await.ok()?;
+    let probe: IdProbe = serde_saphyr::from_slice(&bytes).ok()?;
+    Some(probe.id)
+}
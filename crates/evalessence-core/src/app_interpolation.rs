@@ -0,0 +1,114 @@
+use serde_json::Value;
+
+// `${VAR}` and `${VAR:-default}` placeholders in the string values of an app config. They are
+// kept as written in the config file, and replaced only when the app is used to call its envs.
+// `$${` is written as a literal `${`.
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    Malformed(String),
+    Unset(String),
+}
+
+pub fn has_placeholders(template: &str) -> bool {
+    template.contains("${")
+}
+
+// Check the syntax of the placeholders of `template`, without resolving them
+pub fn check_template(template: &str) -> Result<(), String> {
+    match interpolate(template, &|_| Some(String::new())) {
+        Err(TemplateError::Malformed(message)) => Err(message),
+        _ => Ok(()),
+    }
+}
+
+pub fn interpolate(
+    template: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, TemplateError> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start..];
+        if let Some(escaped) = after.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+        } else if let Some(placeholder) = after.strip_prefix("${") {
+            let end = placeholder.find('}').ok_or_else(|| {
+                TemplateError::Malformed(format!("placeholder '{after}' is not closed by '}}'"))
+            })?;
+            let (name, default) = match placeholder[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&placeholder[..end], None),
+            };
+            if !is_variable_name(name) {
+                return Err(TemplateError::Malformed(format!(
+                    "'{name}' is not a valid variable name in '${{{}}}'",
+                    &placeholder[..end]
+                )));
+            }
+            let value = lookup(name).filter(|value| default.is_none() || !value.is_empty());
+            match (value, default) {
+                (Some(value), _) => result.push_str(&value),
+                (None, Some(default)) => result.push_str(default),
+                (None, None) => return Err(TemplateError::Unset(name.to_string())),
+            }
+            rest = &placeholder[end + 1..];
+        } else {
+            result.push('$');
+            rest = &after[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Fields holding ids and file names are never interpolated
+const RAW_FIELDS: &[&str] = &["schema_version", "id", "env_id", "dataset_id", "extends"];
+
+// Interpolate every string of an app config value, failing with the path of the first string
+// that can't be resolved, located like the changes of an app (`envs[local].url`)
+pub fn interpolate_value(
+    value: &mut Value,
+    path: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<(), (String, TemplateError)> {
+    match value {
+        Value::String(template) if has_placeholders(template) => {
+            *template = interpolate(template, lookup).map_err(|e| (path.to_string(), e))?;
+        }
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                if RAW_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let field_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                interpolate_value(field, &field_path, lookup)?;
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                let item_path = match item.get("id").and_then(Value::as_str) {
+                    Some(id) => format!("{path}[{id}]"),
+                    None => format!("{path}[{i}]"),
+                };
+                interpolate_value(item, &item_path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::app_core::is_app_filename;
+use anyhow::bail;
+use evalessence_api::app::{Dataset, EntityKind, Env, Pipeline};
+use serde::{Deserialize, Serialize};
+
+// Entities shared by several apps, written in a file of the config dir named by the `extends`
+// field of their config files. Apps see the shared entities as their own, and an entity of the
+// app with the id of a shared one overrides it. Only the entities that differ from the shared
+// ones are written in the app config file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SharedConfig {
+    #[serde(default)]
+    pub envs: Vec<Env>,
+    #[serde(default)]
+    pub datasets: Vec<Dataset>,
+    #[serde(default)]
+    pub pipelines: Vec<Pipeline>,
+}
+
+// The shared file must be in the config dir, next to the app files, and not be one of them
+pub fn check_shared_filename(extends: &str) -> anyhow::Result<()> {
+    if extends.is_empty()
+        || extends.starts_with('.')
+        || extends.contains(['/', '\\'])
+        || is_app_filename(extends)
+    {
+        bail!("extends '{extends}' must be the name of a shared config file of the config dir");
+    }
+    Ok(())
+}
+
+pub trait Entity: Clone + PartialEq {
+    const KIND: EntityKind;
+    fn entity_id(&self) -> &str;
+}
+
+impl Entity for Env {
+    const KIND: EntityKind = EntityKind::Env;
+    fn entity_id(&self) -> &str {
+        self.id.as_str()
+    }
+}
+
+impl Entity for Dataset {
+    const KIND: EntityKind = EntityKind::Dataset;
+    fn entity_id(&self) -> &str {
+        self.id.as_str()
+    }
+}
+
+impl Entity for Pipeline {
+    const KIND: EntityKind = EntityKind::Pipeline;
+    fn entity_id(&self) -> &str {
+        self.id.as_str()
+    }
+}
+
+// The shared entities, overridden by the app ones with the same id, then the other app ones
+pub fn expand<T: Entity>(shared: &[T], own: Vec<T>) -> Vec<T> {
+    let mut own: Vec<Option<T>> = own.into_iter().map(Some).collect();
+    let mut entities: Vec<T> = shared
+        .iter()
+        .map(|s| {
+            own.iter_mut()
+                .find(|o| o.as_ref().is_some_and(|o| o.entity_id() == s.entity_id()))
+                .and_then(Option::take)
+                .unwrap_or_else(|| s.clone())
+        })
+        .collect();
+    entities.extend(own.into_iter().flatten());
+    entities
+}
+
+// The entities of the app that differ from the shared ones.
+// A shared entity can be overridden but not removed, it would come back on the next load
+pub fn overrides<T: Entity>(extends: &str, shared: &[T], entities: &[T]) -> anyhow::Result<Vec<T>> {
+    if let Some(removed) = shared
+        .iter()
+        .find(|s| !entities.iter().any(|e| e.entity_id() == s.entity_id()))
+    {
+        bail!(
+            "{} '{}' is shared by '{extends}', it can't be removed from the app",
+            T::KIND,
+            removed.entity_id()
+        );
+    }
+    Ok(entities
+        .iter()
+        .filter(|e| !shared.contains(e))
+        .cloned()
+        .collect())
+}
@@ -0,0 +1,100 @@
+use crate::app_index::{FileStamp, stamp};
+use evalessence_api::app::{AppHeader, AppListOptions, AppOrder};
+use evalessence_api::dataset::OrderDirection;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+// The files a header was read from: the app file, and the file it extends if any
+#[derive(Clone, PartialEq, Eq)]
+pub struct HeaderStamps {
+    app: FileStamp,
+    shared: Option<(String, FileStamp)>,
+}
+
+impl HeaderStamps {
+    // The current state of the files, None if one of them can't be read
+    pub async fn read(config_dir: &Path, filename: &str, extends: Option<&str>) -> Option<Self> {
+        let app = stamp(config_dir, filename).await.ok()?;
+        Self::with_app(app, config_dir, extends).await
+    }
+
+    // The stamps of an app file read before, with the current state of the file it extends
+    pub async fn with_app(
+        app: FileStamp,
+        config_dir: &Path,
+        extends: Option<&str>,
+    ) -> Option<Self> {
+        let shared = match extends {
+            Some(extends) => Some((extends.to_string(), stamp(config_dir, extends).await.ok()?)),
+            None => None,
+        };
+        Some(Self { app, shared })
+    }
+}
+
+// Headers of the apps of a config dir, by filename.
+// Listing is done often, a file is only loaded again when it or the file it extends changes,
+// like in `AppIndex`. Headers are only cached once loaded, so errors are always reported.
+#[derive(Default)]
+pub struct HeaderCache {
+    headers: Mutex<HashMap<String, (HeaderStamps, AppHeader)>>,
+}
+
+impl HeaderCache {
+    // The cached header of `filename`, if the files it was read from haven't changed
+    pub async fn get(&self, config_dir: &Path, filename: &str) -> Option<AppHeader> {
+        let (stamps, header) = self.lock().get(filename).cloned()?;
+        let extends = stamps.shared.as_ref().map(|(extends, _)| extends.as_str());
+        let current = HeaderStamps::read(config_dir, filename, extends).await?;
+        (current == stamps).then_some(header)
+    }
+
+    pub fn insert(&self, filename: String, stamps: HeaderStamps, header: AppHeader) {
+        self.lock().insert(filename, (stamps, header));
+    }
+
+    // Forget the files that are not in `filenames`
+    pub fn retain(&self, filenames: &[String]) {
+        let filenames: HashSet<&String> = filenames.iter().collect();
+        self.lock()
+            .retain(|filename, _| filenames.contains(filename));
+    }
+
+    // the lock is never held across an await
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, (HeaderStamps, AppHeader)>> {
+        self.headers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Filter, sort and paginate the headers of the loaded apps, returning the requested page
+// and the number of headers matching the filter
+pub fn page(mut headers: Vec<AppHeader>, options: &AppListOptions) -> (Vec<AppHeader>, usize) {
+    if let Some(filter) = &options.name_filter {
+        let filter = filter.to_lowercase();
+        headers.retain(|h| h.name.to_lowercase().contains(&filter));
+    }
+    let total_count = headers.len();
+
+    // ties are broken by id, so that pages don't overlap when names or times are equal
+    headers.sort_by(|a, b| {
+        let ordering = match options.order_by {
+            AppOrder::Name => a.name.cmp(&b.name),
+            AppOrder::Id => Ordering::Equal,
+            AppOrder::Modified => a.modified.cmp(&b.modified),
+        }
+        .then_with(|| a.id.as_str().cmp(b.id.as_str()));
+        match options.direction {
+            OrderDirection::Asc => ordering,
+            OrderDirection::Desc => ordering.reverse(),
+        }
+    });
+
+    let headers = headers
+        .into_iter()
+        .skip(options.offset)
+        .take(options.limit.unwrap_or(usize::MAX))
+        .collect();
+    (headers, total_count)
+}
@@ -0,0 +1,107 @@
+use crate::app_diff::{entities, entity_id, field_path, is_entity_list};
+use evalessence_api::app::FieldConflict;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+
+// Three-way merge of app configs converted to json values: `ours` and `theirs` were both
+// modified from `base`. Objects are merged key by key and entity lists by id, like the diff.
+// A value modified on one side only takes this side, a value modified differently on both
+// sides is a conflict.
+pub fn merge(base: &Value, ours: &Value, theirs: &Value) -> Result<Value, Vec<FieldConflict>> {
+    let mut conflicts = Vec::new();
+    let merged = merge_value("", Some(base), Some(ours), Some(theirs), &mut conflicts);
+    match merged {
+        Some(merged) if conflicts.is_empty() => Ok(merged),
+        _ => Err(conflicts),
+    }
+}
+
+fn merge_value(
+    path: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+    conflicts: &mut Vec<FieldConflict>,
+) -> Option<Value> {
+    if ours == theirs || ours == base {
+        return theirs.cloned();
+    }
+    if theirs == base {
+        return ours.cloned();
+    }
+
+    match (base, ours, theirs) {
+        (Some(Value::Object(base)), Some(Value::Object(ours)), Some(Value::Object(theirs))) => {
+            let keys: BTreeSet<&String> = base
+                .keys()
+                .chain(ours.keys())
+                .chain(theirs.keys())
+                .collect();
+            let mut merged = Map::new();
+            for key in keys {
+                let value = merge_value(
+                    &field_path(path, key),
+                    base.get(key),
+                    ours.get(key),
+                    theirs.get(key),
+                    conflicts,
+                );
+                if let Some(value) = value {
+                    merged.insert(key.clone(), value);
+                }
+            }
+            Some(Value::Object(merged))
+        }
+        (Some(Value::Array(base)), Some(Value::Array(ours)), Some(Value::Array(theirs)))
+            if is_entity_list(base, ours) && is_entity_list(ours, theirs) =>
+        {
+            Some(Value::Array(merge_entities(
+                path, base, ours, theirs, conflicts,
+            )))
+        }
+        _ => {
+            conflicts.push(FieldConflict {
+                path: path.to_string(),
+                base: base.cloned(),
+                ours: ours.cloned(),
+                theirs: theirs.cloned(),
+            });
+            theirs.cloned()
+        }
+    }
+}
+
+// Entities keep the order of `theirs`, entities added by `ours` come last
+fn merge_entities(
+    path: &str,
+    base: &[Value],
+    ours: &[Value],
+    theirs: &[Value],
+    conflicts: &mut Vec<FieldConflict>,
+) -> Vec<Value> {
+    let ids: Vec<&str> = entities(theirs)
+        .map(|(id, _)| id)
+        // added by `ours`, or removed by `theirs` (a conflict if `ours` modified them)
+        .chain(
+            entities(ours)
+                .map(|(id, _)| id)
+                .filter(|id| find(theirs, id).is_none()),
+        )
+        .collect();
+
+    ids.into_iter()
+        .filter_map(|id| {
+            merge_value(
+                &format!("{path}[{id}]"),
+                find(base, id),
+                find(ours, id),
+                find(theirs, id),
+                conflicts,
+            )
+        })
+        .collect()
+}
+
+fn find<'a>(values: &'a [Value], id: &str) -> Option<&'a Value> {
+    values.iter().find(|v| entity_id(v) == Some(id))
+}
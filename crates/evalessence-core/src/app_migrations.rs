@@ -0,0 +1,63 @@
+use anyhow::{Context, anyhow, bail};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Version of the app config format written by this version of evalessence
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+type Migration = fn(&mut Map<String, Value>) -> anyhow::Result<()>;
+
+// MIGRATIONS[n] upgrades a config from schema version n to n + 1.
+// To change the format: bump CURRENT_SCHEMA_VERSION, append a migration, and add a test
+// loading a file of the previous format.
+const MIGRATIONS: &[Migration] = &[v0_to_v1];
+
+const _: () = assert!(MIGRATIONS.len() == CURRENT_SCHEMA_VERSION as usize);
+
+// v0: files written before `schema_version` existed, with the same fields as v1
+#[allow(clippy::unnecessary_wraps)]
+const fn v0_to_v1(_config: &mut Map<String, Value>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+// Only the version is read, other fields are ignored
+#[derive(Deserialize)]
+struct VersionProbe {
+    #[serde(default)]
+    schema_version: u32,
+}
+
+/// Read the schema version of a raw config file (0 if the key is missing)
+pub fn schema_version(yaml_bytes: &[u8]) -> anyhow::Result<u32> {
+    let probe: VersionProbe = serde_saphyr::from_slice(yaml_bytes)?;
+    if probe.schema_version > CURRENT_SCHEMA_VERSION {
+        bail!(
+            "schema_version {} is newer than the supported version {CURRENT_SCHEMA_VERSION}, please upgrade evalessence",
+            probe.schema_version
+        );
+    }
+    Ok(probe.schema_version)
+}
+
+/// Upgrade a raw config file of an old schema version to the current format
+pub fn migrate(yaml_bytes: &[u8], from_version: u32) -> anyhow::Result<Value> {
+    let mut value: Value = serde_saphyr::from_slice(yaml_bytes)?;
+    let config = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("app config must be a mapping"))?;
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from_version as usize) {
+        migration(config).with_context(|| {
+            format!(
+                "failed to migrate schema_version {version} to {}",
+                version + 1
+            )
+        })?;
+    }
+    config.insert(
+        "schema_version".to_string(),
+        Value::from(CURRENT_SCHEMA_VERSION),
+    );
+
+    Ok(value)
+}
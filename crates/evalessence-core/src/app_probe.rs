@@ -0,0 +1,179 @@
+use evalessence_api::app::{
+    App, AppError, AppResult, Env, EnvHealth, Probe, ProbeOptions, ResolvedHeader, RouteProbe,
+    TlsInfo,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, Method, RequestBuilder};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use url::Url;
+
+// Probe the env url with a GET, then the route of each pipeline using the env.
+// Any HTTP response means the url is reachable, even an error status:
+// the status is reported for the caller to decide.
+pub async fn probe_env(
+    app: &App,
+    env: &Env,
+    headers: &[ResolvedHeader],
+    options: &ProbeOptions,
+) -> AppResult<EnvHealth> {
+    let mut builder = Client::builder()
+        .timeout(options.timeout)
+        .default_headers(header_map(app, env, headers)?);
+    for der in &options.root_certificates {
+        let certificate =
+            Certificate::from_der(der).map_err(|e| AppError::Internal { source: e.into() })?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    let client = builder
+        .build()
+        .map_err(|e| AppError::Internal { source: e.into() })?;
+
+    // the routes are on the server of the env url, it negotiates the same session for them
+    let tls = tls_info(&env.url, options).await;
+    let env_probe = probe(client.get(&env.url), &env.url, tls.as_ref()).await;
+
+    let mut routes = Vec::new();
+    if let Some(route_probe) = options.routes {
+        for pipeline in app.pipelines.iter().filter(|p| p.env_id == env.id) {
+            let url = format!("{}{}", env.url.trim_end_matches('/'), pipeline.route);
+            let request = match (&pipeline.ping, route_probe) {
+                (Some(sample), _) => client.post(&url).json(sample),
+                (None, RouteProbe::Head) => client.head(&url),
+                (None, RouteProbe::Options) => client.request(Method::OPTIONS, &url),
+            };
+            routes.push((
+                pipeline.id.clone(),
+                probe(request, &url, tls.as_ref()).await,
+            ));
+        }
+    }
+
+    Ok(EnvHealth {
+        env_id: env.id.clone(),
+        env: env_probe,
+        routes,
+    })
+}
+
+fn header_map(app: &App, env: &Env, headers: &[ResolvedHeader]) -> AppResult<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for header in headers {
+        // the value may be a secret, it is not part of the error
+        let invalid = || AppError::ValidationError {
+            filename: app.filename.clone(),
+            source: anyhow::anyhow!(
+                "header '{}' of env '{}' has an invalid name or value",
+                header.name,
+                env.id
+            ),
+        };
+        let name = HeaderName::from_bytes(header.name.as_bytes()).map_err(|_| invalid())?;
+        let mut value = HeaderValue::from_str(header.value.expose()).map_err(|_| invalid())?;
+        value.set_sensitive(true);
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+async fn probe(request: RequestBuilder, url: &str, tls: Option<&TlsInfo>) -> Probe {
+    let start = Instant::now();
+    match request.send().await {
+        Ok(response) => Probe {
+            url: url.to_string(),
+            reachable: true,
+            status: Some(response.status().as_u16()),
+            latency: Some(start.elapsed()),
+            tls: tls.cloned(),
+            error: None,
+        },
+        Err(e) => Probe {
+            url: url.to_string(),
+            reachable: false,
+            status: None,
+            latency: None,
+            tls: None,
+            error: Some(error_chain(&e)),
+        },
+    }
+}
+
+// reqwest errors only say which request failed, the cause is in their sources
+fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+// The TLS session negotiated by the server of `url`, None for plain http urls or if the
+// handshake fails: the request reports why. reqwest only exposes the certificate of its
+// sessions, so the session is negotiated again to know its protocol version
+async fn tls_info(url: &str, options: &ProbeOptions) -> Option<TlsInfo> {
+    let url = Url::parse(url).ok()?;
+    if url.scheme() != "https" {
+        return None;
+    }
+    // ipv6 hosts are in brackets
+    let host = url
+        .host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default()?;
+
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    for der in &options.root_certificates {
+        roots.add(CertificateDer::from(der.clone())).ok()?;
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .ok()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(host.to_string()).ok()?;
+
+    let handshake = async {
+        let stream = TcpStream::connect((host, port)).await.ok()?;
+        TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await
+            .ok()
+    };
+    let mut stream = timeout(options.timeout, handshake).await.ok()??;
+    let (_, session) = stream.get_ref();
+    let peer_certificate = session
+        .peer_certificates()
+        .and_then(<[CertificateDer<'_>]>::first)
+        .map(|certificate| certificate.to_vec());
+    let info = TlsInfo {
+        not_after: peer_certificate.as_deref().and_then(not_after),
+        peer_certificate,
+        protocol_version: session
+            .protocol_version()
+            .and_then(|version| version.as_str())
+            .map(str::to_string),
+    };
+    // tell the server the connection is closed on purpose, the session is all we needed
+    let _ = timeout(options.timeout, stream.shutdown()).await;
+    Some(info)
+}
+
+// The end of the validity period of a DER encoded certificate
+fn not_after(der: &[u8]) -> Option<SystemTime> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+    let seconds = u64::try_from(certificate.validity().not_after.timestamp()).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
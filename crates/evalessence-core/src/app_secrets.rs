@@ -0,0 +1,128 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use evalessence_api::app::{
+    AppError, AppResult, Env, EnvAuth, HeaderValue, ResolvedHeader, Secret, SecretRef,
+};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+// Secrets referenced by the envs are read each time an env is called, from the environment
+// variables of the process or from the .env file of the config dir. The file is hidden,
+// so list and watch ignore it.
+const SECRETS_FILENAME: &str = ".env";
+
+pub struct SecretResolver {
+    secrets_file: PathBuf,
+    // the secrets file, read once per resolution and only if a secret refers to it
+    file_secrets: Option<HashMap<String, String>>,
+}
+
+impl SecretResolver {
+    pub fn new(config_dir: &Path) -> Self {
+        Self {
+            secrets_file: config_dir.join(SECRETS_FILENAME),
+            file_secrets: None,
+        }
+    }
+
+    // Every header of `env`, the auth one last
+    pub async fn resolve_headers(&mut self, env: &Env) -> AppResult<Vec<ResolvedHeader>> {
+        let mut headers = Vec::new();
+        for header in &env.headers {
+            let value = match &header.value {
+                HeaderValue::Plain(value) => Secret::new(value.clone()),
+                HeaderValue::Secret(secret) => self.resolve(env, secret).await?,
+            };
+            headers.push(ResolvedHeader {
+                name: header.name.clone(),
+                value,
+            });
+        }
+
+        let auth = match &env.auth {
+            None => None,
+            Some(EnvAuth::Bearer { token }) => {
+                let token = self.resolve(env, token).await?;
+                Some((
+                    "Authorization".to_string(),
+                    format!("Bearer {}", token.expose()),
+                ))
+            }
+            Some(EnvAuth::ApiKey { header, key }) => {
+                let key = self.resolve(env, key).await?;
+                Some((header.clone(), key.expose().to_string()))
+            }
+            Some(EnvAuth::Basic { username, password }) => {
+                let password = self.resolve(env, password).await?;
+                let credentials = STANDARD.encode(format!("{username}:{}", password.expose()));
+                Some(("Authorization".to_string(), format!("Basic {credentials}")))
+            }
+        };
+        if let Some((name, value)) = auth {
+            headers.push(ResolvedHeader {
+                name,
+                value: Secret::new(value),
+            });
+        }
+
+        Ok(headers)
+    }
+
+    async fn resolve(&mut self, env: &Env, secret: &SecretRef) -> AppResult<Secret> {
+        let value = match secret {
+            SecretRef::EnvVar(name) => std::env::var(name).ok(),
+            SecretRef::SecretsFile(key) => self.file_secrets().await?.get(key).cloned(),
+        };
+        value
+            .map(Secret::new)
+            .ok_or_else(|| AppError::UnresolvedSecret {
+                env_id: env.id.clone(),
+                secret: secret.clone(),
+            })
+    }
+
+    // The variables of the secrets file
+    pub async fn file_secrets(&mut self) -> AppResult<&HashMap<String, String>> {
+        if self.file_secrets.is_none() {
+            let content = match fs::read_to_string(&self.secrets_file).await {
+                Ok(content) => content,
+                // a missing file has no secrets, the reference is reported as unresolved
+                Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    return Err(AppError::FileIoError {
+                        filename: SECRETS_FILENAME.to_string(),
+                        source: e.into(),
+                    });
+                }
+            };
+            self.file_secrets = Some(parse_dotenv(&content));
+        }
+        Ok(self.file_secrets.get_or_insert_default())
+    }
+}
+
+// `KEY=value` lines, with optional `export ` prefixes and quotes around values.
+// Empty lines and lines starting with `#` are ignored
+fn parse_dotenv(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|quote| {
+                    value
+                        .strip_prefix(*quote)
+                        .and_then(|v| v.strip_suffix(*quote))
+                })
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,138 @@
+use crate::app_core::is_app_filename;
+use evalessence_api::app::AppId;
+use nanoid::nanoid;
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs;
+
+// Deleted app files are moved to {config_dir}/.trash/{deleted_at}-{suffix}-{filename}, deleted_at
+// being in milliseconds since the epoch: a rename keeps the modification time of the file, so the
+// deletion time is part of the name. The random suffix keeps the files deleted in the same
+// millisecond apart. The directory is hidden, so list and watch ignore it.
+pub const TRASH_DIR: &str = ".trash";
+
+// Characters of the random suffix, none of them is a separator of the trash id
+const SUFFIX_ALPHABET: [char; 36] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i',
+    'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z',
+];
+const SUFFIX_LEN: usize = 8;
+
+pub struct AppTrash {
+    dir: PathBuf,
+}
+
+// A file of the trash, its name is the trash id
+pub struct TrashEntry {
+    pub trash_id: String,
+    pub id: AppId,
+    pub filename: String,
+    pub deleted_at: SystemTime,
+}
+
+impl AppTrash {
+    pub fn new(config_dir: &Path) -> Self {
+        Self {
+            dir: config_dir.join(TRASH_DIR),
+        }
+    }
+
+    pub fn path(&self, trash_id: &str) -> PathBuf {
+        self.dir.join(trash_id)
+    }
+
+    // Move the app file at `path` to the trash, returning its trash id
+    pub async fn put(&self, path: &Path, filename: &str) -> io::Result<String> {
+        fs::create_dir_all(&self.dir).await?;
+        let deleted_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?;
+        let trash_id = new_trash_id(deleted_at.as_millis(), filename);
+        fs::rename(path, self.path(&trash_id)).await?;
+        Ok(trash_id)
+    }
+
+    // The trashed files, in no particular order
+    pub async fn list(&self) -> io::Result<Vec<TrashEntry>> {
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            // nothing has been deleted yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        let mut trashed = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(entry) = parse_trash_id(&entry.file_name().to_string_lossy()) {
+                trashed.push(entry);
+            }
+        }
+        Ok(trashed)
+    }
+
+    // The trashed file `trash_id`, None if it is not in the trash
+    pub async fn find(&self, trash_id: &str) -> io::Result<Option<TrashEntry>> {
+        // the trash id comes from the caller, it must not be able to point outside of the trash
+        let Some(entry) = parse_trash_id(trash_id) else {
+            return Ok(None);
+        };
+        let exists = fs::try_exists(self.path(trash_id)).await?;
+        Ok(exists.then_some(entry))
+    }
+
+    pub async fn remove(&self, trash_id: &str) -> io::Result<()> {
+        fs::remove_file(self.path(trash_id)).await
+    }
+
+    // Remove the files deleted more than `retention` ago, returning them
+    pub async fn purge_expired(&self, retention: Duration) -> io::Result<Vec<TrashEntry>> {
+        let now = SystemTime::now();
+        let mut purged = Vec::new();
+        for entry in self.list().await? {
+            let age = now.duration_since(entry.deleted_at).unwrap_or_default();
+            if age > retention {
+                match self.remove(&entry.trash_id).await {
+                    // purged concurrently by another process
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    result => result?,
+                }
+                purged.push(entry);
+            }
+        }
+        Ok(purged)
+    }
+}
+
+// A new trash id for the app file `filename` deleted at `deleted_at` (in milliseconds)
+pub fn new_trash_id(deleted_at: impl Display, filename: &str) -> String {
+    let suffix = nanoid!(SUFFIX_LEN, &SUFFIX_ALPHABET);
+    format!("{deleted_at}-{suffix}-{filename}")
+}
+
+// `{deleted_at}-{suffix}-app-{id}.yaml`, or `{deleted_at}-app-{id}.yaml` for the files deleted
+// before trash ids had a suffix
+fn parse_trash_id(trash_id: &str) -> Option<TrashEntry> {
+    let (millis, rest) = trash_id.split_once('-')?;
+    let millis: u64 = millis.parse().ok()?;
+    let filename = match rest.split_once('-') {
+        Some((suffix, filename))
+            if suffix.len() == SUFFIX_LEN
+                && suffix.chars().all(|c| SUFFIX_ALPHABET.contains(&c)) =>
+        {
+            filename
+        }
+        _ => rest,
+    };
+    if !is_app_filename(filename) || filename.contains(['/', '\\']) {
+        return None;
+    }
+    let id = filename.strip_prefix("app-")?.strip_suffix(".yaml")?;
+    Some(TrashEntry {
+        trash_id: trash_id.to_string(),
+        id: AppId::new(id).ok()?,
+        filename: filename.to_string(),
+        deleted_at: UNIX_EPOCH + Duration::from_millis(millis),
+    })
+}
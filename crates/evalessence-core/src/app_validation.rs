@@ -0,0 +1,129 @@
+use crate::app_interpolation::{check_template, has_placeholders};
+use evalessence_api::app::{Dataset, Env, Pipeline, ValidationIssue, ValidationIssues};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use url::Url;
+
+// Semantic checks run on an app config after deserialization (load) and before serialization (save).
+// All problems are collected, so the user can fix a file in one pass.
+// Values with `${VAR}` placeholders are checked once resolved, only their syntax is checked here.
+pub fn validate(
+    envs: &[Env],
+    datasets: &[Dataset],
+    pipelines: &[Pipeline],
+) -> Result<(), ValidationIssues> {
+    let mut issues = Vec::new();
+
+    check_unique_ids(
+        &mut issues,
+        "envs",
+        envs.iter().map(|e| (&e.id, e.id.as_str())),
+    );
+    check_unique_ids(
+        &mut issues,
+        "datasets",
+        datasets.iter().map(|d| (&d.id, d.id.as_str())),
+    );
+    check_unique_ids(
+        &mut issues,
+        "pipelines",
+        pipelines.iter().map(|p| (&p.id, p.id.as_str())),
+    );
+
+    for (i, env) in envs.iter().enumerate() {
+        if let Some(message) = check_url_template(&env.url) {
+            issues.push(issue(format!("envs[{i}].url"), message));
+        }
+        for (j, header) in env.headers.iter().enumerate() {
+            if !is_header_name(&header.name) {
+                issues.push(issue(
+                    format!("envs[{i}].headers[{j}].name"),
+                    format!("'{}' is not a valid header name", header.name),
+                ));
+            }
+        }
+    }
+
+    let env_ids: HashSet<_> = envs.iter().map(|e| &e.id).collect();
+    let dataset_ids: HashSet<_> = datasets.iter().map(|d| &d.id).collect();
+    for (i, pipeline) in pipelines.iter().enumerate() {
+        if has_placeholders(&pipeline.route) {
+            if let Err(message) = check_template(&pipeline.route) {
+                issues.push(issue(format!("pipelines[{i}].route"), message));
+            }
+        } else if !pipeline.route.starts_with('/') {
+            issues.push(issue(
+                format!("pipelines[{i}].route"),
+                format!("route '{}' must start with '/'", pipeline.route),
+            ));
+        }
+        if !env_ids.contains(&pipeline.env_id) {
+            issues.push(issue(
+                format!("pipelines[{i}].env_id"),
+                format!("env '{}' does not exist", pipeline.env_id),
+            ));
+        }
+        if !dataset_ids.contains(&pipeline.dataset_id) {
+            issues.push(issue(
+                format!("pipelines[{i}].dataset_id"),
+                format!("dataset '{}' does not exist", pipeline.dataset_id),
+            ));
+        }
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationIssues(issues))
+    }
+}
+
+const fn issue(path: String, message: String) -> ValidationIssue {
+    ValidationIssue { path, message }
+}
+
+// Report every entity reusing an id already used by a previous entity of the same list
+fn check_unique_ids<'a, Id: Eq + Hash + 'a>(
+    issues: &mut Vec<ValidationIssue>,
+    list: &str,
+    ids: impl Iterator<Item = (&'a Id, &'a str)>,
+) {
+    let mut first_index: HashMap<&Id, usize> = HashMap::new();
+    for (i, (id, raw)) in ids.enumerate() {
+        if let Some(first) = first_index.get(id) {
+            issues.push(issue(
+                format!("{list}[{i}].id"),
+                format!("id '{raw}' is already used by {list}[{first}]"),
+            ));
+        } else {
+            first_index.insert(id, i);
+        }
+    }
+}
+
+// RFC 9110 token: visible ASCII characters, except separators
+fn is_header_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn check_url_template(url: &str) -> Option<String> {
+    if has_placeholders(url) {
+        check_template(url).err()
+    } else {
+        check_url(url)
+    }
+}
+
+fn check_url(url: &str) -> Option<String> {
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => None,
+        Ok(parsed) => Some(format!(
+            "url '{url}' must use http or https, not '{}'",
+            parsed.scheme()
+        )),
+        Err(e) => Some(format!("url '{url}' is malformed: {e}")),
+    }
+}
@@ -0,0 +1,176 @@
+use crate::app_core::{FileAppService, is_app_filename};
+use crate::app_layers::check_shared_filename;
+use evalessence_api::app::{AppError, AppEvent, AppEventStream, AppId, AppResult};
+use notify::{Event, RecursiveMode, Watcher};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::{fs, io};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+// Watch the app config files directly in `config_dir`, and the shared files they extend.
+// atomic_write_async writes into a temporary file (in a hidden sub directory) before renaming it,
+// so only app-*.yaml files and extended files are considered, and events that do not change
+// the content of an app (same etag) are dropped. The etags are the ones `get` returns: the etag
+// of an extending app covers the shared file, whose changes modify the apps extending it.
+pub fn watch_config_dir(config_dir: &Path) -> AppResult<AppEventStream> {
+    let mut known = current_apps(config_dir)?;
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let dir = config_dir.to_path_buf();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let changes = match res {
+            Ok(event) => event
+                .paths
+                .iter()
+                .flat_map(|path| detect_changes(&dir, path, &mut known))
+                .collect(),
+            Err(e) => vec![Err(AppError::Internal { source: e.into() })],
+        };
+        for change in changes {
+            // the receiver is gone when the stream is dropped, nothing left to notify
+            if tx.send(change).is_err() {
+                return;
+            }
+        }
+    })
+    .map_err(|e| AppError::Internal { source: e.into() })?;
+
+    watcher
+        .watch(config_dir, RecursiveMode::NonRecursive)
+        .map_err(|e| AppError::Internal { source: e.into() })?;
+
+    // the watcher is moved into the stream, so it stops watching when the stream is dropped
+    let stream = UnboundedReceiverStream::new(rx).map(move |change| {
+        let _ = &watcher;
+        change
+    });
+
+    Ok(Box::pin(stream))
+}
+
+// The state of an app file as last seen by the watcher
+struct WatchedApp {
+    // None if the file has no valid id, it can't be loaded
+    id: Option<AppId>,
+    etag: String,
+    // the shared file it extends
+    extends: Option<String>,
+}
+
+// Only the id and the extended file are read, other fields are ignored
+#[derive(Deserialize)]
+struct FileProbe {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    extends: Option<String>,
+}
+
+// each app config file at the time the watch starts
+fn current_apps(config_dir: &Path) -> AppResult<HashMap<String, WatchedApp>> {
+    let entries = fs::read_dir(config_dir).map_err(|e| AppError::Internal { source: e.into() })?;
+
+    Ok(entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().to_string_lossy().into_owned();
+            // other files, ex: the audit log, may be large and are not read
+            if !is_app_filename(&name) {
+                return None;
+            }
+            let bytes = fs::read(config_dir.join(&name)).ok()?;
+            Some((name, watched_app(config_dir, &bytes)))
+        })
+        .collect())
+}
+
+// The id and the etag of an app file as `get` computes them, the raw file is used when the
+// shared file can't be read, loading the app reports why
+fn watched_app(config_dir: &Path, bytes: &[u8]) -> WatchedApp {
+    let (id, extends) = match serde_saphyr::from_slice::<FileProbe>(bytes) {
+        Ok(probe) => (probe.id, probe.extends),
+        Err(_) => (None, None),
+    };
+    let id = id.and_then(|id| AppId::new(id).ok());
+    let extends = extends.filter(|extends| check_shared_filename(extends).is_ok());
+    let shared = extends
+        .as_ref()
+        .and_then(|extends| fs::read(config_dir.join(extends)).ok());
+    let etag = match (&extends, shared) {
+        (Some(extends), Some(shared)) => {
+            FileAppService::calculate_extended_etag(bytes, extends, &shared)
+        }
+        _ => FileAppService::calculate_etag(bytes),
+    };
+    WatchedApp { id, etag, extends }
+}
+
+fn detect_changes(
+    config_dir: &Path,
+    path: &Path,
+    known: &mut HashMap<String, WatchedApp>,
+) -> Vec<AppResult<AppEvent>> {
+    let Some(filename) = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+    else {
+        return vec![];
+    };
+    if is_app_filename(&filename) {
+        return detect_change(config_dir, filename, known)
+            .into_iter()
+            .collect();
+    }
+
+    // a shared file, the apps extending it have changed
+    let extending: Vec<String> = known
+        .iter()
+        .filter(|(_, app)| app.extends.as_ref() == Some(&filename))
+        .map(|(name, _)| name.clone())
+        .collect();
+    extending
+        .into_iter()
+        .filter_map(|name| detect_change(config_dir, name, known))
+        .collect()
+}
+
+fn detect_change(
+    config_dir: &Path,
+    filename: String,
+    known: &mut HashMap<String, WatchedApp>,
+) -> Option<AppResult<AppEvent>> {
+    match fs::read(config_dir.join(&filename)) {
+        Ok(bytes) => {
+            let app = watched_app(config_dir, &bytes);
+            let (id, etag) = (app.id.clone(), app.etag.clone());
+            let previous = known.insert(filename.clone(), app);
+            if previous
+                .as_ref()
+                .is_some_and(|previous| previous.etag == etag)
+            {
+                return None;
+            }
+            let Some(id) = id else {
+                return Some(Err(AppError::ValidationError {
+                    filename,
+                    source: anyhow::anyhow!("the app file has no valid id"),
+                }));
+            };
+            Some(Ok(match previous {
+                None => AppEvent::Created { id, filename, etag },
+                Some(_) => AppEvent::Modified { id, filename, etag },
+            }))
+        }
+        // the deletion of a file without id has nothing to tell, its changes were errors
+        Err(e) if e.kind() == io::ErrorKind::NotFound => known
+            .remove(&filename)
+            .and_then(|app| app.id)
+            .map(|id| Ok(AppEvent::Deleted { id, filename })),
+        Err(e) => Some(Err(AppError::FileIoError {
+            filename,
+            source: e.into(),
+        })),
+    }
+}
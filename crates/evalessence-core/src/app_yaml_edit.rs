@@ -0,0 +1,440 @@
+use saphyr_parser::{Event, Marker, Parser, ScalarStyle, Span};
+use serde_json::Value;
+
+// Rewrite the YAML document `source` so it deserializes to `new`, changing only the parts that
+// differ: comments, key order, quoting and blank lines of the rest of the document are kept.
+// Returns None if the document uses constructs that are not edited in place (anchors, tags,
+// several documents...), or if the edited document would not match `new`: the caller then
+// writes the whole document again.
+pub fn edit_yaml(source: &str, new: &Value) -> Option<String> {
+    let old: Value = serde_saphyr::from_str(source).ok()?;
+    let root = Tree::parse(source)?;
+
+    let mut editor = Editor {
+        source,
+        line_starts: line_starts(source),
+        edits: Vec::new(),
+    };
+    editor.edit_node(&root, &old, new)?;
+    let edited = editor.apply()?;
+
+    // the edits are checked, not trusted
+    let reloaded: Value = serde_saphyr::from_str(&edited).ok()?;
+    (&reloaded == new).then_some(edited)
+}
+
+#[derive(Clone, Copy)]
+struct Pos {
+    byte: usize,
+    // 1-indexed
+    line: usize,
+    col: usize,
+}
+
+enum Kind {
+    Scalar { value: String, style: ScalarStyle },
+    Mapping(Vec<(Node, Node)>),
+    Sequence(Vec<Node>),
+}
+
+struct Node {
+    kind: Kind,
+    start: Pos,
+    // end of the last character of the node, comments after it excluded
+    end: Pos,
+    // `[...]` and `{...}` nodes are replaced as a whole
+    flow: bool,
+}
+
+// The nodes of a document with their location, built from the events of the parser
+struct Tree<'a> {
+    source: &'a str,
+    events: std::vec::IntoIter<(Event<'a>, Span)>,
+}
+
+impl<'a> Tree<'a> {
+    fn parse(source: &'a str) -> Option<Node> {
+        let events: Vec<(Event<'a>, Span)> = Parser::new_from_str(source)
+            .collect::<Result<_, _>>()
+            .ok()?;
+        let mut tree = Tree {
+            source,
+            events: events.into_iter(),
+        };
+
+        matches!(tree.next()?.0, Event::StreamStart).then_some(())?;
+        matches!(tree.next()?.0, Event::DocumentStart(_)).then_some(())?;
+        let root = tree.node()?;
+        matches!(tree.next()?.0, Event::DocumentEnd).then_some(())?;
+        // a single document
+        matches!(tree.next()?.0, Event::StreamEnd).then_some(root)
+    }
+
+    fn next(&mut self) -> Option<(Event<'a>, Span)> {
+        self.events.next()
+    }
+
+    fn pos(marker: Marker) -> Option<Pos> {
+        Some(Pos {
+            byte: marker.byte_offset()?,
+            line: marker.line(),
+            col: marker.col(),
+        })
+    }
+
+    fn is_flow(&self, start: Pos, opening: u8) -> bool {
+        self.source.as_bytes().get(start.byte) == Some(&opening)
+    }
+
+    fn node(&mut self) -> Option<Node> {
+        let (event, span) = self.next()?;
+        let start = Self::pos(span.start)?;
+        match event {
+            // anchored or tagged nodes are not edited
+            Event::Scalar(value, style, 0, None) => Some(Node {
+                kind: Kind::Scalar {
+                    value: value.into_owned(),
+                    style,
+                },
+                start,
+                end: Self::pos(span.end)?,
+                flow: false,
+            }),
+            Event::SequenceStart(0, None) => {
+                let flow = self.is_flow(start, b'[');
+                let mut items = Vec::new();
+                loop {
+                    if matches!(
+                        self.events.as_slice().first(),
+                        Some((Event::SequenceEnd, _))
+                    ) {
+                        let (_, end_span) = self.next()?;
+                        let end = Self::container_end(flow, end_span, items.last(), start)?;
+                        return Some(Node {
+                            kind: Kind::Sequence(items),
+                            start,
+                            end,
+                            flow,
+                        });
+                    }
+                    items.push(self.node()?);
+                }
+            }
+            Event::MappingStart(0, None) => {
+                let flow = self.is_flow(start, b'{');
+                let mut pairs: Vec<(Node, Node)> = Vec::new();
+                loop {
+                    if matches!(self.events.as_slice().first(), Some((Event::MappingEnd, _))) {
+                        let (_, end_span) = self.next()?;
+                        let last = pairs.last().map(|(_, value)| value);
+                        let end = Self::container_end(flow, end_span, last, start)?;
+                        return Some(Node {
+                            kind: Kind::Mapping(pairs),
+                            start,
+                            end,
+                            flow,
+                        });
+                    }
+                    let key = self.node()?;
+                    matches!(key.kind, Kind::Scalar { .. }).then_some(())?;
+                    let value = self.node()?;
+                    pairs.push((key, value));
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // The end event of a block container is reported at the next token, after its comments
+    fn container_end(flow: bool, end_span: Span, last: Option<&Node>, start: Pos) -> Option<Pos> {
+        if flow {
+            Self::pos(end_span.end)
+        } else {
+            Some(last.map_or(start, |node| node.end))
+        }
+    }
+}
+
+struct Editor<'a> {
+    source: &'a str,
+    // byte offset of the start of each line, line n at index n - 1
+    line_starts: Vec<usize>,
+    // (start, end, replacement) byte ranges of the source
+    edits: Vec<(usize, usize, String)>,
+}
+
+impl Editor<'_> {
+    fn edit_node(&mut self, node: &Node, old: &Value, new: &Value) -> Option<()> {
+        if old == new {
+            return Some(());
+        }
+        match (&node.kind, new) {
+            (Kind::Scalar { style, .. }, new) if !new.is_object() && !new.is_array() => {
+                let mut scalar = render_scalar(new, *style)?;
+                // `key:` without value
+                if node.start.byte == node.end.byte {
+                    scalar.insert(0, ' ');
+                }
+                self.edits.push((node.start.byte, node.end.byte, scalar));
+                Some(())
+            }
+            (Kind::Mapping(pairs), Value::Object(new_map)) if !node.flow => {
+                let old_map = old.as_object()?;
+                for (key, value) in pairs {
+                    let Kind::Scalar { value: name, .. } = &key.kind else {
+                        return None;
+                    };
+                    let old_value = old_map.get(name)?;
+                    match new_map.get(name) {
+                        Some(new_value) => self.edit_value(key, value, old_value, new_value)?,
+                        None => self.remove_lines(key.start, value, key.start.col)?,
+                    }
+                }
+
+                let added: serde_json::Map<String, Value> = new_map
+                    .iter()
+                    .filter(|(name, _)| !old_map.contains_key(*name))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                if !added.is_empty() {
+                    let block = render_block(&Value::Object(added), node.start.col)?;
+                    self.insert_after(node, block);
+                }
+                Some(())
+            }
+            (Kind::Sequence(items), Value::Array(new_items))
+                if !node.flow && !new_items.is_empty() =>
+            {
+                self.edit_sequence(node, items, old.as_array()?, new_items)
+            }
+            _ => None,
+        }
+    }
+
+    // Edit the value of a mapping entry in place, or replace it entirely
+    fn edit_value(&mut self, key: &Node, value: &Node, old: &Value, new: &Value) -> Option<()> {
+        let mark = self.edits.len();
+        if self.edit_node(value, old, new).is_some() {
+            return Some(());
+        }
+        self.edits.truncate(mark);
+
+        // from the `:` after the key to the end of the value
+        let colon = key.end.byte + self.source.get(key.end.byte..)?.find(':')? + 1;
+        let replacement = match new {
+            Value::Array(items) if !items.is_empty() => {
+                format!("\n{}", render_block(new, key.start.col + 2)?)
+                    .trim_end()
+                    .to_string()
+            }
+            Value::Object(map) if !map.is_empty() => {
+                format!("\n{}", render_block(new, key.start.col + 2)?)
+                    .trim_end()
+                    .to_string()
+            }
+            _ => format!(" {}", render_scalar(new, ScalarStyle::Plain)?),
+        };
+        self.edits.push((colon, value.end.byte, replacement));
+        Some(())
+    }
+
+    fn edit_sequence(
+        &mut self,
+        node: &Node,
+        items: &[Node],
+        old: &[Value],
+        new: &[Value],
+    ) -> Option<()> {
+        let dash_col = self.dash_col(items.first()?)?;
+        let old_ids: Option<Vec<&str>> = old.iter().map(entity_id).collect();
+        let new_ids: Option<Vec<&str>> = new.iter().map(entity_id).collect();
+
+        let added: Vec<Value> = if let (Some(old_ids), Some(new_ids)) = (old_ids, new_ids) {
+            // entities are matched by id, the kept ones must stay in the same order
+            let kept: Vec<&str> = old_ids
+                .iter()
+                .copied()
+                .filter(|id| new_ids.contains(id))
+                .collect();
+            let new_kept: Vec<&str> = new_ids
+                .iter()
+                .copied()
+                .filter(|id| old_ids.contains(id))
+                .collect();
+            (kept == new_kept).then_some(())?;
+            // and the new ones must come after them
+            let first_added = new_ids
+                .iter()
+                .position(|id| !old_ids.contains(id))
+                .unwrap_or(new_ids.len());
+            (new_ids
+                .iter()
+                .skip(first_added)
+                .all(|id| !old_ids.contains(id)))
+            .then_some(())?;
+
+            for ((item, old_item), id) in items.iter().zip(old).zip(&old_ids) {
+                match new_ids.iter().position(|new_id| new_id == id) {
+                    Some(index) => self.edit_node(item, old_item, new.get(index)?)?,
+                    None => self.remove_item(item, dash_col)?,
+                }
+            }
+            new.get(first_added..)?.to_vec()
+        } else {
+            // other lists are matched by position
+            for (index, (item, old_item)) in items.iter().zip(old).enumerate() {
+                match new.get(index) {
+                    Some(new_item) => self.edit_node(item, old_item, new_item)?,
+                    None => self.remove_item(item, dash_col)?,
+                }
+            }
+            new.get(old.len()..).unwrap_or_default().to_vec()
+        };
+
+        if !added.is_empty() {
+            let block = render_block(&Value::Array(added), dash_col)?;
+            self.insert_after(node, block);
+        }
+        Some(())
+    }
+
+    // Column of the `-` of a sequence item, which must be alone before the item on its line
+    fn dash_col(&self, item: &Node) -> Option<usize> {
+        let line_start = *self.line_starts.get(item.start.line - 1)?;
+        let prefix = self.source.get(line_start..item.start.byte)?;
+        (prefix.trim() == "-").then_some(())?;
+        prefix.find('-')
+    }
+
+    fn remove_item(&mut self, item: &Node, dash_col: usize) -> Option<()> {
+        (self.dash_col(item)? == dash_col).then_some(())?;
+        let start = Pos {
+            byte: self.line_starts.get(item.start.line - 1)? + dash_col,
+            col: dash_col,
+            ..item.start
+        };
+        self.remove_lines(start, item, dash_col)
+    }
+
+    // Remove the lines from `start` to the end of `last`, with the comments just above them.
+    // `start` must be the first thing on its line
+    fn remove_lines(&mut self, start: Pos, last: &Node, indent: usize) -> Option<()> {
+        let first_line = start.line;
+        let line_start = *self.line_starts.get(first_line - 1)?;
+        let prefix = self.source.get(line_start..start.byte)?;
+        (prefix.len() == start.col && prefix.trim().is_empty()).then_some(())?;
+
+        let mut from_line = first_line;
+        while from_line > 1 {
+            let line = self.line(from_line - 1)?;
+            let indented = line.len() - line.trim_start().len() >= indent;
+            if !(indented && line.trim_start().starts_with('#')) {
+                break;
+            }
+            from_line -= 1;
+        }
+
+        let from = *self.line_starts.get(from_line - 1)?;
+        let to = self.next_line_start(last);
+        self.edits.push((from, to, String::new()));
+        Some(())
+    }
+
+    // Insert `block` on the lines following `node`
+    fn insert_after(&mut self, node: &Node, block: String) {
+        let at = self.next_line_start(node);
+        let block = if at == self.source.len() && !self.source.ends_with('\n') {
+            format!("\n{block}")
+        } else {
+            block
+        };
+        self.edits.push((at, at, block));
+    }
+
+    fn next_line_start(&self, node: &Node) -> usize {
+        self.line_starts
+            .get(node.end.line)
+            .copied()
+            .unwrap_or(self.source.len())
+    }
+
+    fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line - 1)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.source.len());
+        self.source.get(start..end)
+    }
+
+    fn apply(mut self) -> Option<String> {
+        self.edits.sort_by_key(|(start, _, _)| *start);
+        // overlapping edits: a change made twice
+        for pair in self.edits.windows(2) {
+            if let [(_, end, _), (next_start, _, _)] = pair
+                && end > next_start
+            {
+                return None;
+            }
+        }
+
+        let mut edited = self.source.to_string();
+        for (start, end, replacement) in self.edits.iter().rev() {
+            edited.replace_range(*start..*end, replacement);
+        }
+        Some(edited)
+    }
+}
+
+fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+fn entity_id(value: &Value) -> Option<&str> {
+    value.get("id")?.as_str()
+}
+
+// A scalar written on a single line, quoted like the value it replaces
+fn render_scalar(value: &Value, style: ScalarStyle) -> Option<String> {
+    if let Value::String(s) = value {
+        match style {
+            // single quoted scalars have no escapes but their doubled quotes, strings with
+            // line breaks or other control characters are double quoted
+            ScalarStyle::SingleQuoted if !s.contains(char::is_control) => {
+                return Some(format!("'{}'", s.replace('\'', "''")));
+            }
+            ScalarStyle::SingleQuoted | ScalarStyle::DoubleQuoted => {
+                return serde_json::to_string(s).ok();
+            }
+            _ => {}
+        }
+    }
+    let rendered = serde_saphyr::to_string(value).ok()?;
+    let rendered = rendered.trim_end();
+    if rendered.contains('\n') {
+        // a JSON string is a valid YAML double quoted scalar
+        return serde_json::to_string(value).ok();
+    }
+    Some(rendered.to_string())
+}
+
+// A block mapping or sequence, indented by `indent` spaces, ending with a new line
+fn render_block(value: &Value, indent: usize) -> Option<String> {
+    let rendered = serde_saphyr::to_string(value).ok()?;
+    let padding = " ".repeat(indent);
+    Some(
+        rendered
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    "\n".to_string()
+                } else {
+                    format!("{padding}{line}\n")
+                }
+            })
+            .collect(),
+    )
+}
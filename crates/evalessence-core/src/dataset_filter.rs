@@ -0,0 +1,151 @@
+use duckdb::types::Value;
+use evalessence_api::dataset::{CompareOp, Filter, FilterField, FilterValue, OrderDirection};
+
+// Compile `filter` to a SQL condition with `?` placeholders. The values of the filter are pushed
+// to `params` in the order of their placeholders, only raw SQL filters are written as is
+pub fn filter_sql(filter: &Filter, params: &mut Vec<Value>) -> String {
+    match filter {
+        Filter::Compare { field, op, value } => {
+            let field = field_sql(field, Some(value), params);
+            params.push(param(value));
+            format!("{field} {} ?", compare_sql(*op))
+        }
+        Filter::In { field, values } => {
+            if values.is_empty() {
+                return "FALSE".to_string();
+            }
+            let field = field_sql(field, values.first(), params);
+            params.extend(values.iter().map(param));
+            let placeholders = vec!["?"; values.len()].join(", ");
+            format!("{field} IN ({placeholders})")
+        }
+        Filter::Between { field, low, high } => {
+            let field = field_sql(field, Some(low), params);
+            params.push(param(low));
+            params.push(param(high));
+            format!("{field} BETWEEN ? AND ?")
+        }
+        Filter::IsNull(field) => format!("{} IS NULL", field_sql(field, None, params)),
+        Filter::IsNotNull(field) => format!("{} IS NOT NULL", field_sql(field, None, params)),
+        Filter::And(filters) => join(filters, " AND ", "TRUE", params),
+        Filter::Or(filters) => join(filters, " OR ", "FALSE", params),
+        Filter::Not(filter) => format!("NOT ({})", filter_sql(filter, params)),
+        Filter::RawSql(sql) => format!("({sql})"),
+    }
+}
+
+// Whether `filter` contains a raw SQL condition, which can't be serialized
+pub fn has_raw_sql(filter: &Filter) -> bool {
+    match filter {
+        Filter::RawSql(_) => true,
+        Filter::And(filters) | Filter::Or(filters) => filters.iter().any(has_raw_sql),
+        Filter::Not(filter) => has_raw_sql(filter),
+        _ => false,
+    }
+}
+
+// The rows after the one with `last_value` in the `order_by` column and `last_id`, sorted by
+// `order_by` then by id, rows with a null value coming last
+pub fn after_filter(
+    order_by: Option<&(String, OrderDirection)>,
+    last_value: Option<&FilterValue>,
+    last_id: &str,
+) -> Filter {
+    let direction = order_by.map_or(OrderDirection::Asc, |(_, direction)| *direction);
+    let after = match direction {
+        OrderDirection::Asc => CompareOp::Gt,
+        OrderDirection::Desc => CompareOp::Lt,
+    };
+    let after_id = Filter::Compare {
+        field: FilterField::column("id"),
+        op: after,
+        value: FilterValue::String(last_id.to_string()),
+    };
+    let Some((column, _)) = order_by else {
+        return after_id;
+    };
+
+    let field = FilterField::column(column);
+    match last_value {
+        Some(value) => Filter::Or(vec![
+            Filter::Compare {
+                field: field.clone(),
+                op: after,
+                value: value.clone(),
+            },
+            Filter::And(vec![
+                Filter::Compare {
+                    field: field.clone(),
+                    op: CompareOp::Eq,
+                    value: value.clone(),
+                },
+                after_id,
+            ]),
+            Filter::IsNull(field),
+        ]),
+        None => Filter::And(vec![Filter::IsNull(field), after_id]),
+    }
+}
+
+// Column names come from the caller, they are quoted so they can't end the identifier
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn join(filters: &[Filter], separator: &str, empty: &str, params: &mut Vec<Value>) -> String {
+    if filters.is_empty() {
+        return empty.to_string();
+    }
+    let conditions: Vec<String> = filters
+        .iter()
+        .map(|filter| format!("({})", filter_sql(filter, params)))
+        .collect();
+    conditions.join(separator)
+}
+
+// The field, the value inside JSON being converted to the type of the value it's compared to
+fn field_sql(field: &FilterField, value: Option<&FilterValue>, params: &mut Vec<Value>) -> String {
+    let column = quote_identifier(&field.column);
+    if field.json_path.is_empty() {
+        return column;
+    }
+
+    params.push(Value::Text(json_pointer(&field.json_path)));
+    let json = format!("json_extract_string({column}, ?)");
+    match value {
+        Some(FilterValue::Bool(_)) => format!("TRY_CAST({json} AS BOOLEAN)"),
+        Some(FilterValue::Int(_)) => format!("TRY_CAST({json} AS BIGINT)"),
+        Some(FilterValue::Float(_)) => format!("TRY_CAST({json} AS DOUBLE)"),
+        Some(FilterValue::String(_)) | None => json,
+    }
+}
+
+// JSON pointer of the path (RFC 6901), keys may contain any character
+fn json_pointer(path: &[String]) -> String {
+    let mut pointer = String::new();
+    for key in path {
+        pointer.push('/');
+        pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+    }
+    pointer
+}
+
+const fn compare_sql(op: CompareOp) -> &'static str {
+    match op {
+        CompareOp::Eq => "=",
+        CompareOp::Ne => "<>",
+        CompareOp::Lt => "<",
+        CompareOp::Le => "<=",
+        CompareOp::Gt => ">",
+        CompareOp::Ge => ">=",
+    }
+}
+
+fn param(value: &FilterValue) -> Value {
+    match value {
+        FilterValue::Bool(b) => Value::Boolean(*b),
+        FilterValue::Int(i) => Value::BigInt(*i),
+        FilterValue::Float(f) => Value::Double(*f),
+        FilterValue::String(s) => Value::Text(s.clone()),
+    }
+}
@@ -0,0 +1,140 @@
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use duckdb::types::Value;
+use duckdb::{Connection, params_from_iter};
+use evalessence_api::dataset::{DatasetError, Result, SendableRecordBatchReader};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::thread;
+
+// Batches read ahead of the reader, the other ones are read from DuckDB when it needs them
+const BUFFERED_BATCHES: usize = 2;
+
+type BatchResult = std::result::Result<RecordBatch, ArrowError>;
+
+// Run `sql` on `conn` (its own connection to the database, in a transaction started by
+// `begin_read`) in a thread sending the batches of the result to the returned reader as DuckDB
+// produces them. The errors of the query are returned here when it can't start, or as the last
+// item of the reader when it fails while it is read. The thread stops when the reader is dropped.
+pub fn stream_query(
+    conn: Connection,
+    sql: String,
+    params: Vec<Value>,
+) -> Result<SendableRecordBatchReader> {
+    let (started_tx, started_rx) = sync_channel(1);
+    let (batches_tx, batches_rx) = sync_channel(BUFFERED_BATCHES);
+    thread::spawn(
+        move || match produce(&conn, &sql, &params, &started_tx, &batches_tx) {
+            Err(Failure::Start(e)) => {
+                let _ = started_tx.try_send(Err(e));
+            }
+            Err(Failure::Read(e)) => {
+                let _ = batches_tx.send(Err(ArrowError::ExternalError(
+                    anyhow::anyhow!("Failed to read the query result: {e}").into(),
+                )));
+            }
+            Ok(()) => {}
+        },
+    );
+
+    let schema = started_rx
+        .recv()
+        .map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to start query: {e}"),
+        })?
+        .map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to execute query: {e}"),
+        })?;
+    Ok(Box::new(StreamReader {
+        schema,
+        batches: Mutex::new(batches_rx),
+    }))
+}
+
+// Open a connection to the database of `conn` for a reader, in a transaction: the queries run
+// on it read the tables as they were at the first one, and a failed query aborts it
+pub fn begin_read(conn: &Connection) -> Result<Connection> {
+    let reader = conn.try_clone().map_err(|e| DatasetError::Internal {
+        source: anyhow::anyhow!("Failed to open reader connection: {e}"),
+    })?;
+    reader
+        .execute_batch("BEGIN TRANSACTION")
+        .map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to start reader transaction: {e}"),
+        })?;
+    Ok(reader)
+}
+
+enum Failure {
+    Start(duckdb::Error),
+    Read(String),
+}
+
+fn produce(
+    conn: &Connection,
+    sql: &str,
+    params: &[Value],
+    started: &SyncSender<duckdb::Result<SchemaRef>>,
+    batches: &SyncSender<BatchResult>,
+) -> std::result::Result<(), Failure> {
+    // the schema of a streamed result must be known before it is read, DuckDB doesn't run
+    // the query to answer an empty one
+    let schema = conn
+        .prepare(&format!("SELECT * FROM ({sql}) LIMIT 0"))
+        .and_then(|mut empty| Ok(empty.query_arrow(params_from_iter(params))?.get_schema()))
+        .map_err(Failure::Start)?;
+    let mut stmt = conn.prepare(sql).map_err(Failure::Start)?;
+    let stream = stmt
+        .stream_arrow(params_from_iter(params), schema.clone())
+        .map_err(Failure::Start)?;
+    if started.send(Ok(schema)).is_err() {
+        return Ok(());
+    }
+
+    // duckdb-rs panics if a batch can't be converted, the reader gets an error instead of
+    // a truncated result
+    let sent = panic::catch_unwind(AssertUnwindSafe(|| {
+        for batch in stream {
+            if batches.send(Ok(batch)).is_err() {
+                // the reader has been dropped
+                return false;
+            }
+        }
+        true
+    }));
+    match sent {
+        Ok(false) => Ok(()),
+        Ok(true) => check_complete(conn),
+        Err(_) => Err(Failure::Read("a batch can't be converted".to_string())),
+    }
+}
+
+// duckdb-rs ends a streamed result without an error when the query fails while it is read.
+// The failure aborts the transaction of the reader (which a COMMIT would roll back silently),
+// the next statement fails if it happened
+fn check_complete(conn: &Connection) -> std::result::Result<(), Failure> {
+    conn.execute_batch("SELECT 1")
+        .map_err(|e| Failure::Read(format!("the query failed after its first rows: {e}")))
+}
+
+struct StreamReader {
+    schema: SchemaRef,
+    // readers must be Sync, the receiver is only used through `&mut self`
+    batches: Mutex<Receiver<BatchResult>>,
+}
+
+impl Iterator for StreamReader {
+    type Item = BatchResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.batches.get_mut().ok()?.recv().ok()
+    }
+}
+
+impl RecordBatchReader for StreamReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
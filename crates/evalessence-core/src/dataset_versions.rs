@@ -0,0 +1,162 @@
+use crate::file_utils::atomic_write;
+use evalessence_api::app::DatasetId;
+use evalessence_api::dataset::{DatasetVersion, VersionRetention};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The versions of a dataset are immutable parquet files in {base_path}/{dataset_id}/, listed with
+// their metadata in {base_path}/{dataset_id}/versions.json. A version is either a snapshot of
+// every row, {version}.parquet, or the changes since the previous version: the upserted rows in
+// {version}.delta.parquet and the ids of the deleted ones in {version}.deleted.parquet.
+// The files of a version are written before the list, so the listed versions are always complete.
+const VERSIONS_FILE: &str = "versions.json";
+const VERSION_EXTENSION: &str = ".parquet";
+const DELTA_EXTENSION: &str = ".delta.parquet";
+const DELETED_EXTENSION: &str = ".deleted.parquet";
+
+// Versions read the files of the versions since the last snapshot, a snapshot is saved once
+// there are this many deltas to read
+pub const MAX_DELTAS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionEntry {
+    #[serde(flatten)]
+    pub version: DatasetVersion,
+    // lists written before deltas only have snapshots
+    #[serde(default = "snapshot_by_default")]
+    pub snapshot: bool,
+}
+
+const fn snapshot_by_default() -> bool {
+    true
+}
+
+pub struct DatasetVersions {
+    base_path: PathBuf,
+}
+
+impl DatasetVersions {
+    pub fn new(base_path: &Path) -> Self {
+        Self {
+            base_path: base_path.to_path_buf(),
+        }
+    }
+
+    fn dataset_dir(&self, id: &DatasetId) -> PathBuf {
+        self.base_path.join(id.as_str())
+    }
+
+    // The list of the versions, rewritten by every new version
+    pub fn list_path(&self, id: &DatasetId) -> PathBuf {
+        self.dataset_dir(id).join(VERSIONS_FILE)
+    }
+
+    pub fn snapshot_path(&self, id: &DatasetId, version: u64) -> PathBuf {
+        self.dataset_dir(id)
+            .join(format!("{version}{VERSION_EXTENSION}"))
+    }
+
+    pub fn delta_path(&self, id: &DatasetId, version: u64) -> PathBuf {
+        self.dataset_dir(id)
+            .join(format!("{version}{DELTA_EXTENSION}"))
+    }
+
+    pub fn deleted_path(&self, id: &DatasetId, version: u64) -> PathBuf {
+        self.dataset_dir(id)
+            .join(format!("{version}{DELETED_EXTENSION}"))
+    }
+
+    // The single file of a dataset created before versions, replaced by its first version
+    pub fn unversioned_path(&self, id: &DatasetId) -> PathBuf {
+        self.base_path.join(format!("{id}{VERSION_EXTENSION}"))
+    }
+
+    // Create the dir of the files of the dataset versions, if needed
+    pub fn create_dir(&self, id: &DatasetId) -> io::Result<()> {
+        fs::create_dir_all(self.dataset_dir(id))
+    }
+
+    // The versions of the dataset, oldest first, empty if it has never been created
+    pub fn list(&self, id: &DatasetId) -> io::Result<Vec<VersionEntry>> {
+        match fs::read(self.list_path(id)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(io::Error::other),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Record `entry`, its files must have been written
+    pub fn add(&self, id: &DatasetId, entry: VersionEntry) -> io::Result<()> {
+        let mut entries = self.list(id)?;
+        entries.push(entry);
+        self.write(id, &entries)
+    }
+
+    pub fn write(&self, id: &DatasetId, entries: &[VersionEntry]) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(entries).map_err(io::Error::other)?;
+        atomic_write(self.list_path(id), &bytes)
+    }
+
+    // Remove the files of every version, and the file of the dataset before versions
+    pub fn remove_all(&self, id: &DatasetId) -> io::Result<()> {
+        match fs::remove_dir_all(self.dataset_dir(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        match fs::remove_file(self.unversioned_path(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // Remove the files of `version` that exist, as a snapshot or as a delta
+    pub fn remove_files(&self, id: &DatasetId, version: u64) -> io::Result<()> {
+        for path in [
+            self.snapshot_path(id, version),
+            self.delta_path(id, version),
+            self.deleted_path(id, version),
+        ] {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// The entries of the versions read to rebuild the last one of `entries`: the last snapshot,
+// then the deltas after it
+pub fn chain(entries: &[VersionEntry]) -> &[VersionEntry] {
+    let start = entries.iter().rposition(|e| e.snapshot).unwrap_or(0);
+    &entries[start..]
+}
+
+// The versions kept by `retention` and the other ones, in the order of `entries`
+pub fn split_retained(
+    entries: Vec<VersionEntry>,
+    retention: &VersionRetention,
+) -> (Vec<VersionEntry>, Vec<VersionEntry>) {
+    // the latest version is the one updates start from, it is always kept
+    let keep_last = retention.keep_last.max(1);
+    let first_of_last = entries.len().saturating_sub(keep_last);
+    let pinned: HashSet<u64> = retention.keep_versions.iter().copied().collect();
+    let mut kept = vec![];
+    let mut removed = vec![];
+    for (i, entry) in entries.into_iter().enumerate() {
+        if i >= first_of_last
+            || pinned.contains(&entry.version.version)
+            || retention
+                .keep_since
+                .is_some_and(|since| entry.version.created_at >= since)
+        {
+            kept.push(entry);
+        } else {
+            removed.push(entry);
+        }
+    }
+    (kept, removed)
+}
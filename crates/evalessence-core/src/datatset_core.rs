@@ -1,21 +1,44 @@
-use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-
-use arrow::array::Array;
-use arrow::record_batch::{RecordBatch, RecordBatchIterator};
-use duckdb::Connection;
+use std::time::SystemTime;
+
+use crate::dataset_filter::{after_filter, filter_sql, has_raw_sql, quote_identifier};
+use crate::dataset_stream::{begin_read, stream_query};
+use crate::dataset_versions::{DatasetVersions, MAX_DELTAS, VersionEntry, chain, split_retained};
+use arrow::array::{Array, AsArray};
+use arrow::compute::{CastOptions, cast_with_options, concat_batches};
+use arrow::datatypes::{DataType, Float64Type, Int64Type};
+use arrow::record_batch::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use duckdb::types::Value;
 use duckdb::vtab::arrow::{ArrowVTab, arrow_recordbatch_to_query_params};
+use duckdb::{Connection, params_from_iter};
+use evalessence_api::app::DatasetId;
 use evalessence_api::dataset::{
-    DatasetError, DatasetService, Delete, OrderDirection, Result, SendableRecordBatchReader,
+    Cursor, DatasetError, DatasetOperation, DatasetService, DatasetVersion, Delete, Filter,
+    FilterValue, OrderDirection, Result, SamplePage, SendableRecordBatchReader, VersionRetention,
 };
 
 pub struct DuckDbDatasetService {
     conn: Arc<Mutex<Connection>>,
-    base_path: PathBuf,
+    versions: DatasetVersions,
+    // the version list of each loaded table when its latest version was loaded or saved,
+    // locked after `conn`
+    loaded: Mutex<HashMap<DatasetId, FileStamp>>,
+}
+
+// Version lists are only rewritten as a whole, a change of their modification time or size
+// means another process has modified them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
 }
 
 impl DuckDbDatasetService {
-    /// Create a new `DuckDbDatasetService` backed by parquet files at `base_path`.
+    /// Create a new `DuckDbDatasetService` backed by parquet files at `base_path`, one per
+    /// version of each dataset.
     ///
     /// # Errors
     /// Returns [`DatasetError::Internal`] if the in-memory `DuckDB` connection cannot be opened.
@@ -23,64 +46,247 @@ impl DuckDbDatasetService {
         let conn = Connection::open_in_memory().map_err(|e| DatasetError::Internal {
             source: anyhow::anyhow!("Failed to open connection: {e}"),
         })?;
+        // `arrow` table function used to read record batches in upserts
+        conn.register_table_function::<ArrowVTab>("arrow")
+            .map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to register ArrowVTab: {e}"),
+            })?;
 
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
-            base_path: base_path.as_ref().to_path_buf(),
+            versions: DatasetVersions::new(base_path.as_ref()),
+            loaded: Mutex::new(HashMap::new()),
         })
     }
 
-    fn dataset_path(&self, dataset_id: &str) -> PathBuf {
-        self.base_path.join(format!("{dataset_id}.parquet"))
+    // The versions of the dataset, oldest first, read with `conn` locked.
+    // Datasets created before versions have a single file, it becomes their first version
+    fn dataset_versions(
+        &self,
+        conn: &Connection,
+        dataset_id: &DatasetId,
+    ) -> Result<Vec<VersionEntry>> {
+        let entries = self
+            .versions
+            .list(dataset_id)
+            .map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to read versions: {e}"),
+            })?;
+        let path = self.versions.unversioned_path(dataset_id);
+        let Some(stamp) = file_stamp(&path)? else {
+            return Ok(entries);
+        };
+        if !entries.is_empty() {
+            return Ok(entries);
+        }
+
+        let row_count = count_rows(conn, &parquet_source(&path))?;
+        let entry = VersionEntry {
+            version: DatasetVersion {
+                version: 1,
+                created_at: stamp.modified,
+                operation: DatasetOperation::Create,
+                upserted_rows: row_count,
+                deleted_rows: 0,
+                row_count,
+            },
+            snapshot: true,
+        };
+        self.versions
+            .create_dir(dataset_id)
+            .and_then(|()| fs::rename(&path, self.versions.snapshot_path(dataset_id, 1)))
+            .and_then(|()| self.versions.add(dataset_id, entry.clone()))
+            .map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to version {}: {e}", path.display()),
+            })?;
+        Ok(vec![entry])
     }
 
-    fn ensure_table_loaded(&self, dataset_id: &str) -> Result<()> {
-        let path = self.dataset_path(dataset_id);
+    // The query of the rows of the last version of `entries`
+    fn version_source(&self, dataset_id: &DatasetId, entries: &[VersionEntry]) -> String {
+        let chain = chain(entries);
+        if let [entry] = chain
+            && entry.snapshot
+        {
+            return parquet_source(
+                &self
+                    .versions
+                    .snapshot_path(dataset_id, entry.version.version),
+            );
+        }
+
+        // the rows of the snapshot and of the deltas, the latest change of each id is kept
+        let changes = chain
+            .iter()
+            .flat_map(|entry| {
+                let version = entry.version.version;
+                let rows = if entry.snapshot {
+                    self.versions.snapshot_path(dataset_id, version)
+                } else {
+                    self.versions.delta_path(dataset_id, version)
+                };
+                let mut changes = vec![format!(
+                    "SELECT *, {version} AS __version, false AS __deleted FROM {}",
+                    parquet_source(&rows)
+                )];
+                if !entry.snapshot && entry.version.deleted_rows > 0 {
+                    changes.push(format!(
+                        "SELECT id, {version} AS __version, true AS __deleted FROM {}",
+                        parquet_source(&self.versions.deleted_path(dataset_id, version))
+                    ));
+                }
+                changes
+            })
+            .collect::<Vec<_>>()
+            .join(" UNION ALL BY NAME ");
+        format!(
+            "(SELECT * EXCLUDE (__version, __deleted, __rank) FROM ( \
+               SELECT *, row_number() OVER (PARTITION BY id ORDER BY __version DESC) AS __rank \
+               FROM ({changes})) \
+             WHERE __rank = 1 AND NOT __deleted)"
+        )
+    }
+
+    // Load the latest version of the table, unless it is already loaded
+    fn ensure_table_loaded(&self, dataset_id: &DatasetId) -> Result<()> {
         let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
             source: anyhow::anyhow!("Failed to lock connection: {e}"),
         })?;
+        let mut loaded = self.loaded.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock loaded tables: {e}"),
+        })?;
 
-        if path.exists() {
-            let path_str = path.display();
-            let sql = format!(
-                "CREATE OR REPLACE TABLE {dataset_id} AS SELECT * FROM read_parquet('{path_str}')"
-            );
+        let entries = self.dataset_versions(&conn, dataset_id)?;
+        let stamp = file_stamp(&self.versions.list_path(dataset_id))?;
+        if loaded.get(dataset_id) == stamp.as_ref() {
+            return Ok(());
+        }
+        let table = table_name(dataset_id);
+        if let Some(stamp) = stamp
+            && !entries.is_empty()
+        {
+            let source = self.version_source(dataset_id, &entries);
+            let sql = format!("CREATE OR REPLACE TABLE {table} AS SELECT * FROM {source}");
             conn.execute(&sql, []).map_err(|e| DatasetError::Internal {
                 source: anyhow::anyhow!("Failed to load table: {e}"),
             })?;
+            loaded.insert(dataset_id.clone(), stamp);
+        } else {
+            // the dataset has been removed since the table was loaded
+            conn.execute(&format!("DROP TABLE IF EXISTS {table}"), [])
+                .map_err(|e| DatasetError::Internal {
+                    source: anyhow::anyhow!("Failed to unload table: {e}"),
+                })?;
+            loaded.remove(dataset_id);
         }
 
         Ok(())
     }
 
-    fn save_table(&self, dataset_id: &str) -> Result<()> {
-        let path = self.dataset_path(dataset_id);
-        let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
-            source: anyhow::anyhow!("Failed to lock connection: {e}"),
+    // Save the table as a new version, the one following the latest version. Only the rows
+    // changed by the update are written, in `upserted_ids` and `deleted_ids`, unless it is time
+    // for a snapshot
+    fn save_version(
+        &self,
+        conn: &Connection,
+        dataset_id: &DatasetId,
+        upserted_rows: usize,
+        deleted_rows: usize,
+    ) -> Result<DatasetVersion> {
+        let mut loaded = self.loaded.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock loaded tables: {e}"),
         })?;
 
-        let path_str = path.display();
-        let sql = format!("COPY {dataset_id} TO '{path_str}' (FORMAT PARQUET)");
-        conn.execute(&sql, []).map_err(|e| DatasetError::Internal {
-            source: anyhow::anyhow!("Failed to save table: {e}"),
-        })?;
+        let table = table_name(dataset_id);
+        let entries = self.dataset_versions(conn, dataset_id)?;
+        let previous = entries.last().map(|e| e.version.version);
+        let row_count = count_rows(conn, &table)?;
+        let entry = VersionEntry {
+            version: DatasetVersion {
+                version: previous.map_or(1, |v| v + 1),
+                created_at: SystemTime::now(),
+                operation: if previous.is_some() {
+                    DatasetOperation::Update
+                } else {
+                    DatasetOperation::Create
+                },
+                upserted_rows,
+                deleted_rows,
+                row_count,
+            },
+            // a delta as large as the table is not worth reading with the previous versions
+            snapshot: entries.is_empty()
+                || chain(&entries).len() > MAX_DELTAS
+                || upserted_rows + deleted_rows >= row_count,
+        };
+
+        let version = entry.version.version;
+        self.versions
+            .create_dir(dataset_id)
+            .map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to create version directory: {e}"),
+            })?;
+        let mut copies = vec![];
+        if entry.snapshot {
+            copies.push((table, self.versions.snapshot_path(dataset_id, version)));
+        } else {
+            copies.push((
+                format!("(SELECT * FROM {table} WHERE id IN (SELECT id FROM upserted_ids))"),
+                self.versions.delta_path(dataset_id, version),
+            ));
+            if deleted_rows > 0 {
+                copies.push((
+                    "(SELECT DISTINCT id FROM deleted_ids)".to_string(),
+                    self.versions.deleted_path(dataset_id, version),
+                ));
+            }
+        }
+        for (rows, path) in copies {
+            let sql = format!("COPY {rows} TO {} (FORMAT PARQUET)", sql_string(&path));
+            conn.execute(&sql, []).map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to save version: {e}"),
+            })?;
+        }
+        self.versions
+            .add(dataset_id, entry.clone())
+            .map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to record version: {e}"),
+            })?;
 
-        Ok(())
+        // the table is already the saved version, it doesn't need to be reloaded
+        match file_stamp(&self.versions.list_path(dataset_id))? {
+            Some(stamp) => loaded.insert(dataset_id.clone(), stamp),
+            None => loaded.remove(dataset_id),
+        };
+
+        Ok(entry.version)
     }
-}
 
-impl DatasetService for DuckDbDatasetService {
-    fn update(
+    // Upsert then delete rows, a new version is saved if they changed the table
+    fn apply_update(
         &self,
-        dataset_id: String,
+        dataset_id: &DatasetId,
         upsert: Option<SendableRecordBatchReader>,
         delete: Option<Delete>,
-    ) -> Result<()> {
-        self.ensure_table_loaded(&dataset_id)?;
+    ) -> Result<Option<DatasetVersion>> {
+        self.ensure_table_loaded(dataset_id)?;
 
         let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
             source: anyhow::anyhow!("Failed to lock connection: {e}"),
         })?;
+        let table = table_name(dataset_id);
+        // a version is only saved if some rows have been upserted or deleted
+        let mut changed = false;
+        let mut upserted_rows = 0;
+        let mut deleted_rows = 0;
+        // the ids of the changed rows, a delta version only saves them
+        conn.execute_batch(
+            "CREATE OR REPLACE TEMP TABLE upserted_ids (id VARCHAR); \
+             CREATE OR REPLACE TEMP TABLE deleted_ids (id VARCHAR);",
+        )
+        .map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to create changed ids tables: {e}"),
+        })?;
 
         // Handle upsert
         if let Some(reader) = upsert {
@@ -89,106 +295,558 @@ impl DatasetService for DuckDbDatasetService {
                 .map_err(DatasetError::ArrowError)?;
 
             if !batches.is_empty() {
-                conn.register_table_function::<ArrowVTab>("arrow")
-                    .map_err(|e| DatasetError::Internal {
-                        source: anyhow::anyhow!("Failed to register ArrowVTab: {e}"),
-                    })?;
-
+                changed = true;
                 for batch in batches {
+                    upserted_rows += batch.num_rows();
+                    // tables loaded from parquet have no primary key, so rows with the same id
+                    // are replaced through a staging table instead of INSERT OR REPLACE
                     let params = arrow_recordbatch_to_query_params(batch);
                     conn.execute(
-                        &format!("INSERT OR REPLACE INTO {dataset_id} SELECT * FROM arrow(?, ?)"),
+                        "CREATE OR REPLACE TEMP TABLE upsert_batch AS SELECT * FROM arrow(?, ?)",
                         params,
                     )
+                    .map_err(|e| DatasetError::Internal {
+                        source: anyhow::anyhow!("Failed to stage upsert data: {e}"),
+                    })?;
+
+                    let sql = if table_exists(&conn, dataset_id)? {
+                        format!(
+                            "DELETE FROM {table} WHERE id IN (SELECT id FROM upsert_batch); \
+                             INSERT INTO {table} SELECT * FROM upsert_batch;"
+                        )
+                    } else {
+                        format!("CREATE TABLE {table} AS SELECT * FROM upsert_batch;")
+                    };
+                    conn.execute_batch(&format!(
+                        "{sql} INSERT INTO upserted_ids SELECT id FROM upsert_batch;"
+                    ))
                     .map_err(|e| DatasetError::Internal {
                         source: anyhow::anyhow!("Failed to upsert data: {e}"),
                     })?;
                 }
+
+                conn.execute("DROP TABLE upsert_batch", []).map_err(|e| {
+                    DatasetError::Internal {
+                        source: anyhow::anyhow!("Failed to drop staged upsert data: {e}"),
+                    }
+                })?;
             }
         }
 
-        // Handle delete
+        // Handle delete, the ids of the deleted rows are kept for the version
         if let Some(delete_spec) = delete {
             match delete_spec {
                 Delete::ByIds(ids) => {
-                    let id_values: Vec<String> = (0..Array::len(&ids))
-                        .map(|i| ids.value(i).to_string())
+                    let id_values: Vec<Value> = (0..Array::len(&ids))
+                        .map(|i| Value::Text(ids.value(i).to_string()))
                         .collect();
 
                     if !id_values.is_empty() {
-                        let placeholders = id_values
-                            .iter()
-                            .map(|v| format!("'{}'", v.replace('\'', "''")))
-                            .collect::<Vec<_>>()
-                            .join(", ");
-
-                        let sql = format!("DELETE FROM {dataset_id} WHERE id IN ({placeholders})");
-                        conn.execute(&sql, []).map_err(|e| DatasetError::Internal {
-                            source: anyhow::anyhow!("Failed to delete by IDs: {e}"),
-                        })?;
+                        let placeholders = vec!["?"; id_values.len()].join(", ");
+                        let sql = format!(
+                            "INSERT INTO deleted_ids SELECT id FROM {table} WHERE id IN ({placeholders})"
+                        );
+                        conn.execute(&sql, params_from_iter(id_values))
+                            .map_err(|e| DatasetError::Internal {
+                                source: anyhow::anyhow!("Failed to delete by IDs: {e}"),
+                            })?;
                     }
                 }
-                Delete::Where(where_clause) => {
-                    let sql = format!("DELETE FROM {dataset_id} WHERE {where_clause}");
-                    conn.execute(&sql, []).map_err(|e| DatasetError::Internal {
-                        source: anyhow::anyhow!("Failed to delete with WHERE: {e}"),
+                Delete::Where(filter) => {
+                    let mut params = vec![];
+                    let sql = format!(
+                        "INSERT INTO deleted_ids SELECT id FROM {table} WHERE {}",
+                        filter_sql(&filter, &mut params)
+                    );
+                    conn.execute(&sql, params_from_iter(params)).map_err(|e| {
+                        DatasetError::Internal {
+                            source: anyhow::anyhow!("Failed to delete with filter: {e}"),
+                        }
                     })?;
                 }
             }
+            let sql = format!("DELETE FROM {table} WHERE id IN (SELECT id FROM deleted_ids)");
+            deleted_rows += conn.execute(&sql, []).map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to delete rows: {e}"),
+            })?;
         }
 
-        drop(conn);
-        self.save_table(&dataset_id)?;
+        if !changed && deleted_rows == 0 {
+            return Ok(None);
+        }
+        self.save_version(&conn, dataset_id, upserted_rows, deleted_rows)
+            .map(Some)
+    }
 
+    // Forget the table, the next call loads it from its latest version
+    fn unload_table(&self, dataset_id: &DatasetId) -> Result<()> {
+        let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock connection: {e}"),
+        })?;
+        let mut loaded = self.loaded.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock loaded tables: {e}"),
+        })?;
+        conn.execute(
+            &format!("DROP TABLE IF EXISTS {}", table_name(dataset_id)),
+            [],
+        )
+        .map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to unload table: {e}"),
+        })?;
+        loaded.remove(dataset_id);
         Ok(())
     }
 
+    // The page of the rows matching `filter` after the row `after` (its order_by value and id)
+    fn page(
+        &self,
+        dataset_id: &DatasetId,
+        filter: Option<Filter>,
+        order_by: Option<(String, OrderDirection)>,
+        after: Option<(Option<FilterValue>, String)>,
+        limit: Option<usize>,
+    ) -> Result<SamplePage> {
+        // the filter is kept by the cursor, which must be serializable to be sent to a client
+        if filter.as_ref().is_some_and(has_raw_sql) {
+            return Err(DatasetError::Internal {
+                source: anyhow::anyhow!("Pages can't be selected with a raw SQL filter"),
+            });
+        }
+        self.ensure_table_loaded(dataset_id)?;
+
+        // ids are unique, they make the order total so that a page can start after a row
+        let direction = order_by.as_ref().map_or(OrderDirection::Asc, |(_, d)| *d);
+        let mut order = vec![];
+        if let Some((column, direction)) = &order_by {
+            order.push((column.clone(), *direction));
+        }
+        order.push(("id".to_string(), direction));
+        let mut conditions: Vec<Filter> = filter.iter().cloned().collect();
+        if let Some((last_value, last_id)) = &after {
+            conditions.push(after_filter(
+                order_by.as_ref(),
+                last_value.as_ref(),
+                last_id,
+            ));
+        }
+        let page_filter = Some(Filter::And(conditions));
+
+        // the count, the rows and the end of the page are read in the same transaction, from
+        // the same version
+        let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock connection: {e}"),
+        })?;
+        let reader = begin_read(&conn)?;
+        drop(conn);
+        let table = table_name(dataset_id);
+
+        let (sql, params) =
+            build_select_query("count(*)", &table, filter.clone(), None, None, None);
+        let total_count: i64 = reader
+            .query_row(&sql, params_from_iter(params), |row| row.get(0))
+            .map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to count rows: {e}"),
+            })?;
+
+        // one more row than the page tells whether there is a next page
+        let (sql, params) = build_select_query(
+            "*",
+            &table,
+            page_filter,
+            Some(order),
+            limit.map(|limit| limit + 1),
+            None,
+        );
+        let rows = stream_query(reader, sql, params)?;
+        let Some(limit) = limit else {
+            // every row has been selected
+            return Ok(SamplePage {
+                items: rows,
+                cursor: None,
+                total_count: usize::try_from(total_count).unwrap_or_default(),
+            });
+        };
+
+        // the page has at most `limit` rows, it is read before it is returned to find its end
+        let schema = rows.schema();
+        let batches = rows
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(DatasetError::ArrowError)?;
+        let rows = concat_batches(&schema, &batches).map_err(DatasetError::ArrowError)?;
+        let page = rows.slice(0, rows.num_rows().min(limit));
+        let cursor = match limit.checked_sub(1) {
+            // no row has been selected, the next page starts where this one does
+            None => after,
+            Some(_) if rows.num_rows() <= limit => None,
+            Some(last) => {
+                let last_value = match &order_by {
+                    Some((column, _)) => key_value(&page, column, last)?,
+                    None => None,
+                };
+                let last_id = key_value(&page, "id", last)?;
+                let Some(FilterValue::String(last_id)) = last_id else {
+                    return Err(DatasetError::Internal {
+                        source: anyhow::anyhow!("Pages need an id column of strings"),
+                    });
+                };
+                Some((last_value, last_id))
+            }
+        };
+
+        Ok(SamplePage {
+            items: Box::new(RecordBatchIterator::new(vec![Ok(page)], schema)),
+            cursor: cursor.map(|(last_value, last_id)| Cursor {
+                filter,
+                order_by,
+                last_value,
+                last_id,
+            }),
+            total_count: usize::try_from(total_count).unwrap_or_default(),
+        })
+    }
+}
+
+impl DatasetService for DuckDbDatasetService {
+    fn exists(&self, dataset_id: DatasetId) -> Result<bool> {
+        Ok(self.versions.list_path(&dataset_id).exists()
+            || self.versions.unversioned_path(&dataset_id).exists())
+    }
+
+    fn update(
+        &self,
+        dataset_id: DatasetId,
+        upsert: Option<SendableRecordBatchReader>,
+        delete: Option<Delete>,
+    ) -> Result<Option<DatasetVersion>> {
+        let result = self.apply_update(&dataset_id, upsert, delete);
+        if result.is_err() {
+            // the table may differ from its latest version, the version is the one to keep
+            self.unload_table(&dataset_id)?;
+        }
+        result
+    }
+
     fn select(
         &self,
-        dataset_id: String,
-        where_clause: Option<String>,
+        dataset_id: DatasetId,
+        filter: Option<Filter>,
         order_by: Option<Vec<(String, OrderDirection)>>,
         limit: Option<usize>,
         offset: Option<usize>,
     ) -> Result<SendableRecordBatchReader> {
         self.ensure_table_loaded(&dataset_id)?;
 
-        let sql = build_select_query(&dataset_id, where_clause, order_by, limit, offset);
+        let (sql, params) = build_select_query(
+            "*",
+            &table_name(&dataset_id),
+            filter,
+            order_by,
+            limit,
+            offset,
+        );
 
         let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
             source: anyhow::anyhow!("Failed to lock connection: {e}"),
         })?;
+        let reader = begin_read(&conn)?;
+        // the reader has its own connection, other operations don't wait for it
+        drop(conn);
+        stream_query(reader, sql, params)
+    }
+
+    fn select_page(
+        &self,
+        dataset_id: DatasetId,
+        filter: Option<Filter>,
+        order_by: Option<(String, OrderDirection)>,
+        limit: Option<usize>,
+    ) -> Result<SamplePage> {
+        self.page(&dataset_id, filter, order_by, None, limit)
+    }
+
+    fn select_next(
+        &self,
+        dataset_id: DatasetId,
+        cursor: Cursor,
+        limit: Option<usize>,
+    ) -> Result<SamplePage> {
+        self.page(
+            &dataset_id,
+            cursor.filter,
+            cursor.order_by,
+            Some((cursor.last_value, cursor.last_id)),
+            limit,
+        )
+    }
+
+    fn list_versions(&self, dataset_id: DatasetId) -> Result<Vec<DatasetVersion>> {
+        let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock connection: {e}"),
+        })?;
+        let entries = self.dataset_versions(&conn, &dataset_id)?;
+        Ok(entries.into_iter().rev().map(|e| e.version).collect())
+    }
+
+    fn select_version(
+        &self,
+        dataset_id: DatasetId,
+        version: u64,
+        filter: Option<Filter>,
+        order_by: Option<Vec<(String, OrderDirection)>>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<SendableRecordBatchReader> {
+        // versions are immutable, they are read from their files without being loaded.
+        // The query is started before the lock is released, so compact can't remove the files
+        // before they are opened. It doesn't wait for the rows, which are streamed
+        let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock connection: {e}"),
+        })?;
+        let entries = self.dataset_versions(&conn, &dataset_id)?;
+        let Some(position) = entries.iter().position(|e| e.version.version == version) else {
+            return Err(DatasetError::VersionNotFound {
+                dataset_id,
+                version,
+            });
+        };
+
+        let source = self.version_source(&dataset_id, &entries[..=position]);
+        let (sql, params) = build_select_query("*", &source, filter, order_by, limit, offset);
+        stream_query(begin_read(&conn)?, sql, params)
+    }
+
+    fn compact(
+        &self,
+        dataset_id: DatasetId,
+        retention: VersionRetention,
+    ) -> Result<Vec<DatasetVersion>> {
+        let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock connection: {e}"),
+        })?;
+        let mut loaded = self.loaded.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock loaded tables: {e}"),
+        })?;
+
+        let entries = self.dataset_versions(&conn, &dataset_id)?;
+        let (mut kept, removed) = split_retained(entries.clone(), &retention);
+        if removed.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // a kept delta whose previous version is removed can't be rebuilt from its chain,
+        // it is saved as a snapshot from the versions before they are removed
+        let removed_versions: HashSet<u64> = removed.iter().map(|e| e.version.version).collect();
+        let mut rewritten = vec![];
+        for entry in &mut kept {
+            let version = entry.version.version;
+            let position = entries
+                .iter()
+                .position(|e| e.version.version == version)
+                .unwrap_or_default();
+            if entry.snapshot
+                || position == 0
+                || !removed_versions.contains(&entries[position - 1].version.version)
+            {
+                continue;
+            }
+            let source = self.version_source(&dataset_id, &entries[..=position]);
+            let sql = format!(
+                "COPY (SELECT * FROM {source}) TO {} (FORMAT PARQUET)",
+                sql_string(&self.versions.snapshot_path(&dataset_id, version))
+            );
+            conn.execute(&sql, []).map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to save version {version} as a snapshot: {e}"),
+            })?;
+            entry.snapshot = true;
+            rewritten.push(version);
+        }
 
-        let mut stmt = conn.prepare(&sql).map_err(|e| DatasetError::Internal {
-            source: anyhow::anyhow!("Failed to prepare statement: {e}"),
+        // the list is written before the files are removed, so listed versions keep their files
+        self.versions
+            .write(&dataset_id, &kept)
+            .map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to record versions: {e}"),
+            })?;
+        for version in rewritten {
+            for path in [
+                self.versions.delta_path(&dataset_id, version),
+                self.versions.deleted_path(&dataset_id, version),
+            ] {
+                if path.exists() {
+                    fs::remove_file(&path).map_err(|e| DatasetError::Internal {
+                        source: anyhow::anyhow!("Failed to remove {}: {e}", path.display()),
+                    })?;
+                }
+            }
+        }
+        for entry in &removed {
+            self.versions
+                .remove_files(&dataset_id, entry.version.version)
+                .map_err(|e| DatasetError::Internal {
+                    source: anyhow::anyhow!("Failed to remove versions: {e}"),
+                })?;
+        }
+
+        // the latest version is kept, a loaded table doesn't need to be reloaded
+        if let Some(loaded_stamp) = loaded.get_mut(&dataset_id) {
+            match file_stamp(&self.versions.list_path(&dataset_id))? {
+                Some(stamp) => *loaded_stamp = stamp,
+                None => {
+                    loaded.remove(&dataset_id);
+                }
+            }
+        }
+
+        Ok(removed.into_iter().rev().map(|e| e.version).collect())
+    }
+
+    fn remove(&self, dataset_id: DatasetId) -> Result<()> {
+        let conn = self.conn.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock connection: {e}"),
         })?;
-        let arrow = stmt.query_arrow([]).map_err(|e| DatasetError::Internal {
-            source: anyhow::anyhow!("Failed to execute query: {e}"),
+        let mut loaded = self.loaded.lock().map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to lock loaded tables: {e}"),
         })?;
 
-        // Collect all batches eagerly so we can release the connection lock
-        let schema = arrow.get_schema();
-        let batches: Vec<RecordBatch> = arrow.collect();
+        self.versions
+            .remove_all(&dataset_id)
+            .map_err(|e| DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to remove dataset: {e}"),
+            })?;
+        conn.execute(
+            &format!("DROP TABLE IF EXISTS {}", table_name(&dataset_id)),
+            [],
+        )
+        .map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to unload table: {e}"),
+        })?;
+        loaded.remove(&dataset_id);
+        Ok(())
+    }
+}
 
-        Ok(Box::new(RecordBatchIterator::new(
-            batches.into_iter().map(Ok),
-            schema,
-        )))
+// The value of `column` in the row `row` of `batch`, None if it is null. It is kept by a cursor
+// to start the next page after this row
+fn key_value(batch: &RecordBatch, column: &str, row: usize) -> Result<Option<FilterValue>> {
+    let array = batch
+        .column_by_name(column)
+        .ok_or_else(|| DatasetError::Internal {
+            source: anyhow::anyhow!("No column {column} to order pages by"),
+        })?
+        .slice(row, 1);
+    if array.is_null(0) {
+        return Ok(None);
     }
+    // values that don't fit in the type of the filter are errors, not nulls
+    let options = CastOptions {
+        safe: false,
+        ..CastOptions::default()
+    };
+    let cast_to = |to_type: &DataType| {
+        cast_with_options(&array, to_type, &options).map_err(DatasetError::ArrowError)
+    };
+    Ok(Some(match array.data_type() {
+        DataType::Boolean => FilterValue::Bool(array.as_boolean().value(0)),
+        t if t.is_integer() || matches!(t, DataType::Decimal128(_, 0)) => FilterValue::Int(
+            cast_to(&DataType::Int64)?
+                .as_primitive::<Int64Type>()
+                .value(0),
+        ),
+        t if t.is_floating() => FilterValue::Float(
+            cast_to(&DataType::Float64)?
+                .as_primitive::<Float64Type>()
+                .value(0),
+        ),
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => FilterValue::String(
+            cast_to(&DataType::Utf8)?
+                .as_string::<i32>()
+                .value(0)
+                .to_string(),
+        ),
+        other => {
+            return Err(DatasetError::Internal {
+                source: anyhow::anyhow!("Pages can't be ordered by a column of type {other}"),
+            });
+        }
+    }))
+}
+
+// Dataset ids are slugs generated from names (ex: golden-set-x_Y1). They can't contain quotes,
+// but they must be quoted in SQL since they contain '-' and may start with a digit
+fn table_name(dataset_id: &DatasetId) -> String {
+    format!("\"{dataset_id}\"")
+}
+
+// The stamp of the file at `path`, None if there is no file
+fn file_stamp(path: &Path) -> Result<Option<FileStamp>> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(DatasetError::Internal {
+                source: anyhow::anyhow!("Failed to read metadata of {}: {e}", path.display()),
+            });
+        }
+    };
+    let modified = metadata.modified().map_err(|e| DatasetError::Internal {
+        source: anyhow::anyhow!(
+            "Failed to read modification time of {}: {e}",
+            path.display()
+        ),
+    })?;
+    Ok(Some(FileStamp {
+        modified,
+        len: metadata.len(),
+    }))
+}
+
+// A string literal of `path` for a query
+fn sql_string(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', "''"))
+}
+
+// The table function reading the parquet file at `path`
+fn parquet_source(path: &Path) -> String {
+    format!("read_parquet({})", sql_string(path))
+}
+
+fn count_rows(conn: &Connection, from: &str) -> Result<usize> {
+    let count: i64 = conn
+        .query_row(&format!("SELECT count(*) FROM {from}"), [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| DatasetError::Internal {
+            source: anyhow::anyhow!("Failed to count rows: {e}"),
+        })?;
+    Ok(usize::try_from(count).unwrap_or_default())
+}
+
+fn table_exists(conn: &Connection, dataset_id: &DatasetId) -> Result<bool> {
+    conn.query_row(
+        "SELECT count(*) > 0 FROM duckdb_tables() WHERE table_name = ?",
+        [dataset_id.as_str()],
+        |row| row.get(0),
+    )
+    .map_err(|e| DatasetError::Internal {
+        source: anyhow::anyhow!("Failed to check table existence: {e}"),
+    })
 }
 
+// The query of `columns` from the table or table function `from`, and the values of its parameters
 fn build_select_query(
-    dataset_id: &str,
-    where_clause: Option<String>,
+    columns: &str,
+    from: &str,
+    filter: Option<Filter>,
     order_by: Option<Vec<(String, OrderDirection)>>,
     limit: Option<usize>,
     offset: Option<usize>,
-) -> String {
-    let mut sql = format!("SELECT * FROM {dataset_id}");
+) -> (String, Vec<Value>) {
+    let mut sql = format!("SELECT {columns} FROM {from}");
+    let mut params = vec![];
 
-    if let Some(where_str) = where_clause {
+    if let Some(filter) = filter {
         sql.push_str(" WHERE ");
-        sql.push_str(&where_str);
+        sql.push_str(&filter_sql(&filter, &mut params));
     }
 
     if let Some(order_vec) = order_by
@@ -201,7 +859,8 @@ fn build_select_query(
                     OrderDirection::Asc => "ASC",
                     OrderDirection::Desc => "DESC",
                 };
-                format!("{col} {dir_str}")
+                // the default of DuckDB, pages rely on it
+                format!("{} {dir_str} NULLS LAST", quote_identifier(col))
             })
             .collect::<Vec<_>>()
             .join(", ");
@@ -218,5 +877,5 @@ fn build_select_query(
         sql.push_str(" OFFSET ");
         sql.push_str(&off.to_string());
     }
-    sql
+    (sql, params)
 }
@@ -1,7 +1,23 @@
 use atomicwrites::{AllowOverwrite, AtomicFile};
+use std::fs::{File, OpenOptions};
 use std::{io, io::Write, path::PathBuf};
 use tokio::task;
 
+// Replace the content of `path` at once, readers never see a partially written file
+pub fn atomic_write(path: impl Into<PathBuf>, data: &[u8]) -> io::Result<()> {
+    let af = AtomicFile::new(path.into(), AllowOverwrite);
+
+    af.write(|f| {
+        f.write_all(data)?;
+        // Optional: f.sync_all()?; // Ensures data hits the disk
+        Ok(())
+    })
+    .map_err(|e| match e {
+        // Flatten the nested error types
+        atomicwrites::Error::Internal(err) | atomicwrites::Error::User(err) => err,
+    })
+}
+
 // Utility for atomic file writes in an async context
 // we need this because several calls might try to write the same file at the same time
 pub async fn atomic_write_async(
@@ -11,20 +27,26 @@ pub async fn atomic_write_async(
     let path = path.into();
     let data = data.into();
 
-    task::spawn_blocking(move || {
-        let af = AtomicFile::new(path, AllowOverwrite);
+    task::spawn_blocking(move || atomic_write(path, &data))
+        .await
+        // Convert JoinError to io::Error
+        .map_err(io::Error::other)?
+}
 
-        af.write(|f| {
-            f.write_all(&data)?;
-            // Optional: f.sync_all()?; // Ensures data hits the disk
-            Ok(())
-        })
-        .map_err(|e| match e {
-            // Flatten the nested error types
-            atomicwrites::Error::Internal(err) | atomicwrites::Error::User(err) => err,
-        })
+// Take an exclusive advisory lock on `path` (created if missing), released when the returned file
+// is dropped. The lock is shared with other processes, so they wait for it like other tasks do
+pub async fn lock_file_async(path: impl Into<PathBuf>) -> io::Result<File> {
+    let path = path.into();
+
+    task::spawn_blocking(move || {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        file.lock()?;
+        Ok(file)
     })
     .await
-    // Convert JoinError to io::Error
     .map_err(io::Error::other)?
 }
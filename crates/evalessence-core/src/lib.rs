@@ -1,3 +1,23 @@
+mod app_archive;
+mod app_audit;
 pub mod app_core;
+pub mod app_db;
+mod app_diff;
+mod app_history;
+mod app_index;
+mod app_interpolation;
+mod app_layers;
+mod app_listing;
+mod app_merge;
+mod app_migrations;
+mod app_probe;
+mod app_secrets;
+mod app_trash;
+mod app_validation;
+mod app_watch;
+mod app_yaml_edit;
+mod dataset_filter;
+mod dataset_stream;
+mod dataset_versions;
 pub mod datatset_core;
 mod file_utils;
@@ -0,0 +1,239 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use arrow::array::{RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatchIterator;
+use evalessence_api::app::{App, AppService, AuditQuery, DatasetId};
+use evalessence_api::dataset::DatasetService;
+use evalessence_core::app_core::FileAppService;
+use evalessence_core::datatset_core::DuckDbDatasetService;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+// Create an app with one env, one filled dataset and one pipeline using both
+async fn app_with_dataset(svc: &FileAppService, datasets: &DuckDbDatasetService) -> App {
+    let app = svc.create("Source".to_string()).await.unwrap();
+    let app = svc
+        .add_env(
+            app.id.clone(),
+            app.etag,
+            "local".to_string(),
+            "http://localhost:8000".to_string(),
+        )
+        .await
+        .unwrap();
+    let app = svc
+        .add_dataset(app.id.clone(), app.etag, "golden".to_string())
+        .await
+        .unwrap();
+    let app = svc
+        .add_pipeline(
+            app.id.clone(),
+            app.etag,
+            "chat".to_string(),
+            "/chat".to_string(),
+            app.envs[0].id.clone(),
+            app.datasets[0].id.clone(),
+        )
+        .await
+        .unwrap();
+
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Utf8, false)]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![Arc::new(StringArray::from(vec!["s1", "s2"]))],
+    )
+    .unwrap();
+    datasets
+        .update(
+            app.datasets[0].id.clone(),
+            Some(Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))),
+            None,
+        )
+        .unwrap();
+
+    app
+}
+
+fn row_count(datasets: &DuckDbDatasetService, dataset_id: &DatasetId) -> usize {
+    datasets
+        .select(dataset_id.clone(), None, None, None, None)
+        .unwrap()
+        .map(|batch| batch.unwrap().num_rows())
+        .sum()
+}
+
+#[tokio::test]
+async fn duplicate_creates_new_app_with_a_copy_of_its_datasets() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let datasets = DuckDbDatasetService::new(td.path()).unwrap();
+    let source = app_with_dataset(&svc, &datasets).await;
+    let source = svc
+        .add_dataset(source.id.clone(), source.etag, "empty".to_string())
+        .await
+        .unwrap();
+
+    let copy = svc
+        .duplicate(source.id.clone(), "Variant".to_string(), &datasets)
+        .await
+        .unwrap();
+
+    assert!(copy.id.to_string().starts_with("variant-"));
+    assert_eq!(copy.filename, format!("app-{}.yaml", copy.id));
+    assert_eq!(copy.name, "Variant");
+    assert_eq!(copy.envs, source.envs);
+    assert_ne!(copy.datasets[0].id, source.datasets[0].id);
+    assert!(copy.datasets[0].id.as_str().starts_with("golden-"));
+    assert_eq!(copy.pipelines[0].dataset_id, copy.datasets[0].id);
+    assert_eq!(row_count(&datasets, &copy.datasets[0].id), 2);
+    // datasets never filled stay empty
+    assert!(!datasets.exists(copy.datasets[1].id.clone()).unwrap());
+    assert_eq!(svc.list().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn failed_duplicates_are_neither_trashed_nor_audited() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let datasets = DuckDbDatasetService::new(td.path()).unwrap();
+    let source = app_with_dataset(&svc, &datasets).await;
+    let audited = svc.audit_log(AuditQuery::default()).await.unwrap().len();
+
+    // the rows of the source dataset can't be read anymore, so they can't be copied
+    for entry in std::fs::read_dir(td.path().join(source.datasets[0].id.as_str())).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "parquet") {
+            std::fs::write(path, b"not a parquet file").unwrap();
+        }
+    }
+    let datasets = DuckDbDatasetService::new(td.path()).unwrap();
+
+    assert!(
+        svc.duplicate(source.id.clone(), "Variant".to_string(), &datasets)
+            .await
+            .is_err()
+    );
+    assert_eq!(svc.list().await.unwrap().len(), 1);
+    assert!(svc.list_trash().await.unwrap().is_empty());
+    assert_eq!(
+        svc.audit_log(AuditQuery::default()).await.unwrap().len(),
+        audited
+    );
+}
+
+#[tokio::test]
+async fn export_then_import_into_another_config_dir() {
+    let (src_dir, dst_dir, archive_dir) =
+        (tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap());
+    let archive = archive_dir.path().join("source.tar");
+
+    let src = FileAppService::new(src_dir.path());
+    let src_datasets = DuckDbDatasetService::new(src_dir.path()).unwrap();
+    let app = app_with_dataset(&src, &src_datasets).await;
+
+    src.export(app.id.clone(), &src_datasets, archive.clone())
+        .await
+        .unwrap();
+
+    let dst = FileAppService::new(dst_dir.path());
+    let dst_datasets = DuckDbDatasetService::new(dst_dir.path()).unwrap();
+    let imported = dst.import(archive, &dst_datasets).await.unwrap();
+
+    // no collision: ids are kept
+    assert_eq!(imported.id, app.id);
+    assert_eq!(imported.filename, app.filename);
+    assert_eq!(imported.datasets[0].id, app.datasets[0].id);
+    assert_eq!(imported.pipelines[0].route, "/chat");
+    assert_eq!(row_count(&dst_datasets, &imported.datasets[0].id), 2);
+}
+
+#[tokio::test]
+async fn import_remaps_ids_on_collision() {
+    let (td, archive_dir) = (tempdir().unwrap(), tempdir().unwrap());
+    let archive = archive_dir.path().join("source.tar");
+    let svc = FileAppService::new(td.path());
+    let datasets = DuckDbDatasetService::new(td.path()).unwrap();
+    let app = app_with_dataset(&svc, &datasets).await;
+
+    svc.export(app.id.clone(), &datasets, archive.clone())
+        .await
+        .unwrap();
+    let imported = svc.import(archive, &datasets).await.unwrap();
+
+    assert_ne!(imported.id, app.id);
+    assert!(imported.id.to_string().starts_with("source-"));
+    assert_ne!(imported.datasets[0].id, app.datasets[0].id);
+    assert!(imported.datasets[0].id.as_str().starts_with("golden-"));
+    // pipelines follow the remapped dataset
+    assert_eq!(imported.pipelines[0].dataset_id, imported.datasets[0].id);
+    assert_eq!(row_count(&datasets, &imported.datasets[0].id), 2);
+
+    // the original app is untouched
+    let original = svc.get(app.id.clone()).await.unwrap();
+    assert_eq!(original.etag, app.etag);
+    assert_eq!(svc.list().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn failed_imports_leave_no_app_and_no_dataset() {
+    let (src_dir, dst_dir, archive_dir) =
+        (tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap());
+    let src = FileAppService::new(src_dir.path());
+    let src_datasets = DuckDbDatasetService::new(src_dir.path()).unwrap();
+    let app = app_with_dataset(&src, &src_datasets).await;
+    let app = src
+        .add_dataset(app.id.clone(), app.etag, "broken".to_string())
+        .await
+        .unwrap();
+    let exported = archive_dir.path().join("source.tar");
+    src.export(app.id.clone(), &src_datasets, exported.clone())
+        .await
+        .unwrap();
+
+    // the content of the second dataset can't be read, after the first one is written
+    let archive = archive_dir.path().join("broken.tar");
+    let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+    let mut entries = tar::Archive::new(File::open(&exported).unwrap());
+    for entry in entries.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().into_owned();
+        let mut data = vec![];
+        entry.read_to_end(&mut data).unwrap();
+        builder
+            .append_data(&mut entry.header().clone(), path, data.as_slice())
+            .unwrap();
+    }
+    let garbage = b"not an arrow stream";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(garbage.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(
+            &mut header,
+            format!("datasets/{}.arrow", app.datasets[1].id),
+            garbage.as_slice(),
+        )
+        .unwrap();
+    builder.finish().unwrap();
+    drop(builder);
+
+    let dst = FileAppService::new(dst_dir.path());
+    let dst_datasets = DuckDbDatasetService::new(dst_dir.path()).unwrap();
+    assert!(dst.import(archive, &dst_datasets).await.is_err());
+    assert!(dst.list().await.unwrap().is_empty());
+    assert!(!dst_datasets.exists(app.datasets[0].id.clone()).unwrap());
+    assert!(!dst_datasets.exists(app.datasets[1].id.clone()).unwrap());
+}
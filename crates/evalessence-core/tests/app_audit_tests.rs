@@ -0,0 +1,154 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{AppChange, AppService, AuditAction, AuditQuery};
+use evalessence_core::app_core::FileAppService;
+use evalessence_core::app_db::DuckDbAppService;
+use serde_json::json;
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+#[tokio::test]
+async fn every_change_is_logged_with_its_actor_etags_and_diff() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path()).with_actor("alice");
+
+    let created = svc.create("Audited".to_string()).await.unwrap();
+    let mut renamed = created.clone();
+    renamed.name = "Renamed".to_string();
+    let renamed = svc.update(renamed).await.unwrap();
+    let with_env = svc
+        .add_env(
+            renamed.id.clone(),
+            renamed.etag.clone(),
+            "local".to_string(),
+            "http://localhost:8000".to_string(),
+        )
+        .await
+        .unwrap();
+    svc.delete(created.id.clone()).await.unwrap();
+
+    let log = svc.audit_log(AuditQuery::default()).await.unwrap();
+    let actions: Vec<AuditAction> = log.iter().map(|e| e.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            AuditAction::Create,
+            AuditAction::Update,
+            AuditAction::Update,
+            AuditAction::Delete
+        ]
+    );
+    assert!(
+        log.iter()
+            .all(|e| e.actor == "alice" && e.app_id == created.id)
+    );
+
+    // etags chain from one change to the next
+    let etags: Vec<_> = log
+        .iter()
+        .map(|e| (e.old_etag.clone(), e.new_etag.clone()))
+        .collect();
+    assert_eq!(
+        etags,
+        vec![
+            (None, Some(created.etag.clone())),
+            (Some(created.etag), Some(renamed.etag.clone())),
+            (Some(renamed.etag), Some(with_env.etag.clone())),
+            (Some(with_env.etag), None),
+        ]
+    );
+
+    assert_eq!(
+        log[1].changes,
+        vec![AppChange {
+            path: "name".to_string(),
+            old: Some(json!("Audited")),
+            new: Some(json!("Renamed")),
+        }]
+    );
+    let env_paths: Vec<&str> = log[2].changes.iter().map(|c| c.path.as_str()).collect();
+    assert_eq!(env_paths, vec![format!("envs[{}]", with_env.envs[0].id)]);
+    // a creation adds every field, a deletion removes them
+    assert!(log[0].changes.iter().all(|c| c.old.is_none()));
+    assert!(log[3].changes.iter().all(|c| c.new.is_none()));
+    assert!(log[3].changes.iter().any(|c| c.path == "name"));
+}
+
+#[tokio::test]
+async fn log_is_filtered_by_app_and_time_range() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+
+    let first = svc.create("First".to_string()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let between = SystemTime::now();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let second = svc.create("Second".to_string()).await.unwrap();
+    svc.delete(first.id.clone()).await.unwrap();
+
+    let of_first = svc
+        .audit_log(AuditQuery {
+            app_id: Some(first.id.clone()),
+            ..AuditQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(of_first.len(), 2);
+
+    let before = svc
+        .audit_log(AuditQuery {
+            until: Some(between),
+            ..AuditQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(before.len(), 1);
+    assert_eq!(before[0].app_id, first.id);
+
+    let after = svc
+        .audit_log(AuditQuery {
+            since: Some(between),
+            ..AuditQuery::default()
+        })
+        .await
+        .unwrap();
+    let ids: Vec<_> = after.iter().map(|e| e.app_id.clone()).collect();
+    assert_eq!(ids, vec![second.id, first.id]);
+}
+
+#[tokio::test]
+async fn log_ignores_an_entry_cut_by_a_crash_and_keeps_growing() {
+    let td = tempdir().unwrap();
+    let database = td.path().join("apps.duckdb");
+    let svc = DuckDbAppService::new(&database)
+        .unwrap()
+        .with_actor("server");
+    let app = svc.create("Logged".to_string()).await.unwrap();
+
+    let log_path = td.path().join(".audit.jsonl");
+    let mut log_file = fs::OpenOptions::new()
+        .append(true)
+        .open(&log_path)
+        .await
+        .unwrap();
+    log_file.write_all(b"{\"app_id\":\"cut").await.unwrap();
+    drop(log_file);
+    assert_eq!(svc.audit_log(AuditQuery::default()).await.unwrap().len(), 1);
+
+    svc.delete(app.id.clone()).await.unwrap();
+    let log = svc.audit_log(AuditQuery::default()).await.unwrap();
+    let actions: Vec<AuditAction> = log.iter().map(|e| e.action).collect();
+    assert_eq!(actions, vec![AuditAction::Create, AuditAction::Delete]);
+    assert_eq!(log[1].actor, "server");
+}
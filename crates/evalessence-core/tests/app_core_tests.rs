@@ -8,10 +8,16 @@
     clippy::case_sensitive_file_extension_comparisons
 )]
 
-use evalessence_api::app::{AppError, AppService};
-use evalessence_core::app_core::FileAppService;
+use evalessence_api::app::{
+    AppError, AppEvent, AppEventStream, AppId, AppService, EntityKind, Env, EnvId, ValidationIssue,
+    ValidationIssues,
+};
+use evalessence_core::app_core::{FileAppService, app_config_schema};
+use std::time::Duration;
 use tempfile::tempdir;
 use tokio::fs;
+use tokio::time::timeout;
+use tokio_stream::StreamExt;
 
 // use pretty_assertions for better test failure diffs
 use pretty_assertions::assert_eq;
@@ -22,7 +28,7 @@ async fn create_get_consistent() {
     let svc = FileAppService::new(td.path());
 
     let created_app = svc.create("My App".to_string()).await.unwrap();
-    let get_app = svc.get(created_app.filename.clone()).await.unwrap();
+    let get_app = svc.get(created_app.id.clone()).await.unwrap();
 
     assert_eq!(created_app.etag, get_app.etag); // apps are the same
     assert_eq!(created_app.name, "My App");
@@ -91,10 +97,18 @@ async fn update_succeeds_with_matching_etag_and_conflicts_on_stale_etag() {
     // set stale etag
     stale.etag = original_etag;
 
+    // both versions changed the name: it can't be merged
     let err = svc.update(stale).await.unwrap_err();
     match err {
-        AppError::Conflict { filename: f } => assert_eq!(f, filename),
-        other => panic!("expected conflict, got {other:?}"),
+        AppError::MergeConflict {
+            filename: f,
+            conflicts,
+        } => {
+            assert_eq!(f, filename);
+            let paths: Vec<&str> = conflicts.iter().map(|c| c.path.as_str()).collect();
+            assert_eq!(paths, vec!["name"]);
+        }
+        other => panic!("expected merge conflict, got {other:?}"),
     }
 }
 
@@ -104,37 +118,453 @@ async fn delete_removes_file_and_get_fails_afterwards() {
     let svc = FileAppService::new(td.path());
 
     let app = svc.create("ToDelete".to_string()).await.unwrap();
-    let filename = app.filename.clone();
-    let path = td.path().join(&filename);
+    let path = td.path().join(&app.filename);
     assert!(path.exists());
 
-    svc.delete(filename.clone()).await.unwrap();
+    svc.delete(app.id.clone()).await.unwrap();
     assert!(!path.exists());
 
-    let err = svc.get(filename).await.unwrap_err();
+    let err = svc.get(app.id.clone()).await.unwrap_err();
+    match err {
+        AppError::NotFound { id } => assert_eq!(id, app.id),
+        other => panic!("expected not found, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn get_non_existent_id_returns_not_found() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+
+    let err = svc
+        .get(AppId::new("does-not-exist").unwrap())
+        .await
+        .unwrap_err();
+    match err {
+        AppError::NotFound { id } => assert_eq!(id.as_str(), "does-not-exist"),
+        other => panic!("expected not found, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn get_finds_files_edited_outside_of_the_service() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.create("Original".to_string()).await.unwrap();
+
+    // a copy of the file keeps the same id: the lookup is ambiguous
+    let copy = td.path().join("app-copy-abcd.yaml");
+    fs::copy(td.path().join(&app.filename), &copy)
+        .await
+        .unwrap();
+    let err = svc.get(app.id.clone()).await.unwrap_err();
+    match err {
+        AppError::DuplicateId { id, filenames } => {
+            assert_eq!(id, app.id);
+            assert_eq!(
+                filenames,
+                vec!["app-copy-abcd.yaml".to_string(), app.filename.clone()]
+            );
+        }
+        other => panic!("expected duplicate id, got {other:?}"),
+    }
+
+    // once the copy is removed the original can be read again
+    fs::remove_file(&copy).await.unwrap();
+    assert_eq!(svc.get(app.id.clone()).await.unwrap().etag, app.etag);
+
+    // a renamed file is found by its id, but its name must match it
+    let renamed = td.path().join("app-renamed-abcd.yaml");
+    fs::rename(td.path().join(&app.filename), &renamed)
+        .await
+        .unwrap();
+    let err = svc.get(app.id.clone()).await.unwrap_err();
+    match err {
+        AppError::IdMismatch { filename, id } => {
+            assert_eq!(filename, "app-renamed-abcd.yaml");
+            assert_eq!(id, app.id);
+        }
+        other => panic!("expected id mismatch, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn add_update_remove_entities() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+
+    let app = svc.create("Entities".to_string()).await.unwrap();
+    let app = svc
+        .add_env(
+            app.id.clone(),
+            app.etag,
+            "Local Env".to_string(),
+            "http://localhost:8000".to_string(),
+        )
+        .await
+        .unwrap();
+    let app = svc
+        .add_dataset(app.id.clone(), app.etag, "Golden Set".to_string())
+        .await
+        .unwrap();
+    let env = app.envs[0].clone();
+    let dataset = app.datasets[0].clone();
+    assert!(env.id.as_str().starts_with("local-env-")); // ids are generated like app ids
+    assert!(dataset.id.as_str().starts_with("golden-set-"));
+
+    let app = svc
+        .add_pipeline(
+            app.id.clone(),
+            app.etag,
+            "Chat".to_string(),
+            "/chat".to_string(),
+            env.id.clone(),
+            dataset.id.clone(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(app.pipelines.len(), 1);
+
+    let mut renamed = env.clone();
+    renamed.url = "http://localhost:9000".to_string();
+    let app = svc
+        .update_env(app.id.clone(), app.etag, renamed)
+        .await
+        .unwrap();
+    assert_eq!(app.envs[0].url, "http://localhost:9000");
+
+    let pipeline_id = app.pipelines[0].id.clone();
+    let app = svc
+        .remove_pipeline(app.id.clone(), app.etag, pipeline_id)
+        .await
+        .unwrap();
+    let app = svc
+        .remove_env(app.id.clone(), app.etag, env.id)
+        .await
+        .unwrap();
+    let app = svc
+        .remove_dataset(app.id.clone(), app.etag, dataset.id)
+        .await
+        .unwrap();
+
+    // changes are persisted
+    let reloaded = svc.get(app.id.clone()).await.unwrap();
+    assert_eq!(reloaded.etag, app.etag);
+    assert!(reloaded.envs.is_empty());
+    assert!(reloaded.datasets.is_empty());
+    assert!(reloaded.pipelines.is_empty());
+}
+
+#[tokio::test]
+async fn referenced_entities_cannot_be_removed_nor_dangling() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+
+    let app = svc.create("Refs".to_string()).await.unwrap();
+    let app = svc
+        .add_env(
+            app.id.clone(),
+            app.etag,
+            "prod".to_string(),
+            "https://p".to_string(),
+        )
+        .await
+        .unwrap();
+    let app = svc
+        .add_dataset(app.id.clone(), app.etag, "data".to_string())
+        .await
+        .unwrap();
+    let env_id = app.envs[0].id.clone();
+    let dataset_id = app.datasets[0].id.clone();
+
+    // a pipeline can't point to a missing env
+    let err = svc
+        .add_pipeline(
+            app.id.clone(),
+            app.etag.clone(),
+            "p".to_string(),
+            "/p".to_string(),
+            EnvId::new("missing").unwrap(),
+            dataset_id.clone(),
+        )
+        .await
+        .unwrap_err();
+    match err {
+        AppError::EntityNotFound { kind, id, .. } => {
+            assert_eq!(kind, EntityKind::Env);
+            assert_eq!(id, "missing");
+        }
+        other => panic!("expected entity not found, got {other:?}"),
+    }
+
+    let app = svc
+        .add_pipeline(
+            app.id.clone(),
+            app.etag,
+            "p".to_string(),
+            "/p".to_string(),
+            env_id.clone(),
+            dataset_id.clone(),
+        )
+        .await
+        .unwrap();
+    let pipeline_id = app.pipelines[0].id.clone();
+
+    let err = svc
+        .remove_env(app.id.clone(), app.etag.clone(), env_id)
+        .await
+        .unwrap_err();
+    match err {
+        AppError::StillReferenced {
+            kind, pipeline_ids, ..
+        } => {
+            assert_eq!(kind, EntityKind::Env);
+            assert_eq!(pipeline_ids, vec![pipeline_id.clone()]);
+        }
+        other => panic!("expected still referenced, got {other:?}"),
+    }
+
+    let err = svc
+        .remove_dataset(app.id.clone(), app.etag.clone(), dataset_id)
+        .await
+        .unwrap_err();
     match err {
-        AppError::FileIoError {
-            filename: _,
-            source: _,
-        } => {}
-        other => panic!("expected file io error, got {other:?}"),
+        AppError::StillReferenced { kind, .. } => assert_eq!(kind, EntityKind::Dataset),
+        other => panic!("expected still referenced, got {other:?}"),
     }
+
+    // nothing has been written
+    let reloaded = svc.get(app.id.clone()).await.unwrap();
+    assert_eq!(reloaded.etag, app.etag);
 }
 
 #[tokio::test]
-async fn get_non_existent_file_returns_fileioerror() {
+async fn entity_operations_conflict_on_stale_etag() {
     let td = tempdir().unwrap();
     let svc = FileAppService::new(td.path());
 
+    let app = svc.create("Stale".to_string()).await.unwrap();
+    svc.add_dataset(app.id.clone(), app.etag.clone(), "first".to_string())
+        .await
+        .unwrap();
+
     let err = svc
-        .get("does-not-exist.yaml".to_string())
+        .add_dataset(app.id.clone(), app.etag, "second".to_string())
         .await
         .unwrap_err();
+    assert!(matches!(err, AppError::Conflict { .. }));
+}
+
+// Extract the field paths reported by a ValidationError
+fn issue_paths(err: &AppError) -> Vec<String> {
     match err {
-        AppError::FileIoError {
-            filename: _,
-            source: _,
-        } => {}
-        other => panic!("expected file io error, got {other:?}"),
+        AppError::ValidationError { source, .. } => source
+            .downcast_ref::<ValidationIssues>()
+            .expect("validation issues")
+            .0
+            .iter()
+            .map(|ValidationIssue { path, .. }| path.clone())
+            .collect(),
+        other => panic!("expected validation error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn get_and_list_report_every_semantic_problem() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+
+    let yaml = "
+id: broken-abcd
+name: Broken
+envs:
+  - id: local
+    url: http://localhost:8000
+    name: Local
+  - id: local
+    url: not a url
+    name: Local again
+datasets:
+  - id: data
+    name: Data
+pipelines:
+  - id: chat
+    name: Chat
+    route: /chat
+    env_id: local
+    dataset_id: data
+  - id: summary
+    name: Summary
+    route: summary
+    env_id: prod
+    dataset_id: missing
+";
+    fs::write(td.path().join("app-broken-abcd.yaml"), yaml)
+        .await
+        .unwrap();
+
+    let err = svc
+        .get(AppId::new("broken-abcd").unwrap())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        issue_paths(&err),
+        vec![
+            "envs[1].id",
+            "envs[1].url",
+            "pipelines[1].route",
+            "pipelines[1].env_id",
+            "pipelines[1].dataset_id",
+        ]
+    );
+
+    let res = svc.list().await.unwrap();
+    assert_eq!(res.len(), 1);
+    assert!(matches!(res[0], Err(AppError::ValidationError { .. })));
+}
+
+#[tokio::test]
+async fn ids_must_be_slugs() {
+    for id in ["", "../escape", "x'; DROP TABLE t; --", "with space", "é"] {
+        let err = EnvId::new(id).unwrap_err();
+        assert_eq!(err.kind, "env");
     }
+    assert_eq!(
+        AppId::new("a/b").unwrap_err().to_string(),
+        "app id 'a/b' must be 1 to 128 ASCII letters, digits, '-' or '_'"
+    );
+    assert!(AppId::new("x".repeat(129)).is_err());
+    assert_eq!(EnvId::new("local-Env_1").unwrap().as_str(), "local-Env_1");
+
+    // ids read from a config file are checked too
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let yaml = "\
+schema_version: 1
+id: bad-abcd
+name: Bad
+envs:
+  - id: \"../../etc\"
+    name: Local
+    url: http://localhost
+datasets: []
+pipelines: []
+";
+    fs::write(td.path().join("app-bad-abcd.yaml"), yaml)
+        .await
+        .unwrap();
+    let err = svc.get(AppId::new("bad-abcd").unwrap()).await.unwrap_err();
+    match err {
+        AppError::ValidationError { filename, source } => {
+            assert_eq!(filename, "app-bad-abcd.yaml");
+            assert!(source.to_string().contains("env id '../../etc' must be"));
+        }
+        other => panic!("expected validation error, got {other:?}"),
+    }
+
+    // generated ids stay valid for long names
+    let app = svc.create("a very long name ".repeat(20)).await.unwrap();
+    assert!(app.id.as_str().len() <= 128);
+}
+
+#[tokio::test]
+async fn update_refuses_invalid_app_and_keeps_file_untouched() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+
+    let app = svc.create("Valid".to_string()).await.unwrap();
+    let env = Env {
+        id: EnvId::new("local").unwrap(),
+        url: "ftp://localhost".to_string(),
+        name: "Local".to_string(),
+        headers: vec![],
+        auth: None,
+    };
+    let mut invalid = app.clone();
+    invalid.envs = vec![env.clone(), env];
+
+    let err = svc.update(invalid).await.unwrap_err();
+    assert_eq!(
+        issue_paths(&err),
+        vec!["envs[1].id", "envs[0].url", "envs[1].url"]
+    );
+
+    let reloaded = svc.get(app.id.clone()).await.unwrap();
+    assert_eq!(reloaded.etag, app.etag);
+}
+
+#[tokio::test]
+async fn watch_reports_changes_of_app_files_only() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let existing = svc.create("Existing".to_string()).await.unwrap();
+
+    let mut events = svc.watch().unwrap();
+
+    // not an app file
+    fs::write(td.path().join("notes.txt"), "hello")
+        .await
+        .unwrap();
+    let created = svc.create("Watched".to_string()).await.unwrap();
+    assert_eq!(
+        next_event(&mut events).await,
+        AppEvent::Created {
+            id: created.id.clone(),
+            filename: created.filename.clone(),
+            etag: created.etag,
+        }
+    );
+
+    // each event is awaited before the next change: a file deleted before its creation
+    // is processed would not be reported at all
+    let mut renamed = existing.clone();
+    renamed.name = "Renamed".to_string();
+    let updated = svc.update(renamed).await.unwrap();
+    assert_eq!(
+        next_event(&mut events).await,
+        AppEvent::Modified {
+            id: existing.id.clone(),
+            filename: existing.filename,
+            etag: updated.etag,
+        }
+    );
+
+    svc.delete(created.id.clone()).await.unwrap();
+    assert_eq!(
+        next_event(&mut events).await,
+        AppEvent::Deleted {
+            id: created.id,
+            filename: created.filename,
+        }
+    );
+}
+
+async fn next_event(events: &mut AppEventStream) -> AppEvent {
+    timeout(Duration::from_secs(5), events.next())
+        .await
+        .expect("event before timeout")
+        .unwrap()
+        .unwrap()
+}
+
+#[test]
+fn app_config_schema_describes_on_disk_format() {
+    let schema = app_config_schema();
+
+    let required = schema["required"].as_array().unwrap();
+    for field in ["id", "name", "envs", "datasets", "pipelines"] {
+        assert!(required.iter().any(|r| r == field), "{field} is required");
+    }
+    // files written before the schema version existed are still valid
+    assert!(!required.iter().any(|r| r == "schema_version"));
+    assert_eq!(schema["properties"]["schema_version"]["default"], 0);
+    // etag and filename are not persisted
+    assert!(schema["properties"].get("etag").is_none());
+    assert!(schema["properties"].get("filename").is_none());
+
+    assert_eq!(schema["$defs"]["Env"]["properties"]["url"]["format"], "uri");
+    assert_eq!(
+        schema["$defs"]["Pipeline"]["properties"]["route"]["pattern"],
+        "^/"
+    );
 }
@@ -0,0 +1,269 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use arrow::array::{RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatchIterator;
+use evalessence_api::app::{AppError, AppService, AuditQuery};
+use evalessence_api::dataset::DatasetService;
+use evalessence_core::app_core::FileAppService;
+use evalessence_core::app_db::DuckDbAppService;
+use evalessence_core::datatset_core::DuckDbDatasetService;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::fs;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+#[tokio::test]
+async fn apps_are_saved_with_etags_and_stale_updates_are_merged() {
+    let td = tempdir().unwrap();
+    let database = td.path().join("apps.duckdb");
+    let svc = DuckDbAppService::new(&database).unwrap();
+
+    let app = svc.create("My App".to_string()).await.unwrap();
+    let with_env = svc
+        .add_env(
+            app.id.clone(),
+            app.etag.clone(),
+            "local".to_string(),
+            "http://localhost:8000".to_string(),
+        )
+        .await
+        .unwrap();
+    assert_ne!(with_env.etag, app.etag);
+
+    // a stale entity operation conflicts
+    let err = svc
+        .add_dataset(app.id.clone(), app.etag.clone(), "golden".to_string())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::Conflict { .. }));
+
+    // a stale update of another field is merged with the env added since
+    let mut renamed = app.clone();
+    renamed.name = "Renamed".to_string();
+    let merged = svc.update(renamed).await.unwrap();
+    assert_eq!(merged.name, "Renamed");
+    assert_eq!(merged.envs, with_env.envs);
+
+    // apps are persisted across service instances
+    drop(svc);
+    let reopened = DuckDbAppService::new(&database).unwrap();
+    let reloaded = reopened.get(app.id.clone()).await.unwrap();
+    assert_eq!(reloaded.etag, merged.etag);
+    assert_eq!(reloaded.name, "Renamed");
+    let revisions = reopened.list_revisions(app.id).await.unwrap();
+    assert_eq!(revisions.len(), 3);
+    assert_eq!(revisions[0].etag, merged.etag);
+}
+
+#[tokio::test]
+async fn deleted_apps_are_restored_as_they_were_until_purged() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbAppService::new(td.path().join("apps.duckdb")).unwrap();
+
+    let app = svc.create("Trashed".to_string()).await.unwrap();
+    svc.delete(app.id.clone()).await.unwrap();
+    assert!(matches!(
+        svc.get(app.id.clone()).await.unwrap_err(),
+        AppError::NotFound { .. }
+    ));
+    let trash = svc.list_trash().await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].name.as_deref(), Some("Trashed"));
+
+    let restored = svc.restore(trash[0].trash_id.clone()).await.unwrap();
+    // the content is restored as it was, with the same etag
+    assert_eq!((restored.etag, restored.name), (app.etag, app.name));
+    assert!(svc.list_trash().await.unwrap().is_empty());
+
+    svc.delete(app.id).await.unwrap();
+    let trash_id = svc.list_trash().await.unwrap()[0].trash_id.clone();
+    svc.purge(trash_id.clone()).await.unwrap();
+    assert!(matches!(
+        svc.restore(trash_id).await.unwrap_err(),
+        AppError::TrashNotFound { .. }
+    ));
+
+    // with no retention, deleted apps are purged right away
+    let svc = svc.with_trash_retention(Duration::ZERO);
+    let app = svc.create("Gone".to_string()).await.unwrap();
+    svc.delete(app.id).await.unwrap();
+    assert!(svc.list_trash().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn failed_duplicates_are_neither_trashed_nor_audited() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbAppService::new(td.path().join("apps.duckdb")).unwrap();
+    let datasets_dir = td.path().join("datasets");
+    let datasets = DuckDbDatasetService::new(&datasets_dir).unwrap();
+    let app = svc.create("Source".to_string()).await.unwrap();
+    let app = svc
+        .add_dataset(app.id.clone(), app.etag, "golden".to_string())
+        .await
+        .unwrap();
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Utf8, false)]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![Arc::new(StringArray::from(vec!["s1"]))],
+    )
+    .unwrap();
+    datasets
+        .update(
+            app.datasets[0].id.clone(),
+            Some(Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))),
+            None,
+        )
+        .unwrap();
+    let audited = svc.audit_log(AuditQuery::default()).await.unwrap().len();
+
+    // the rows of the dataset can't be read anymore, so they can't be copied
+    for entry in std::fs::read_dir(datasets_dir.join(app.datasets[0].id.as_str())).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "parquet") {
+            std::fs::write(path, b"not a parquet file").unwrap();
+        }
+    }
+    let datasets = DuckDbDatasetService::new(&datasets_dir).unwrap();
+
+    assert!(
+        svc.duplicate(app.id.clone(), "Variant".to_string(), &datasets)
+            .await
+            .is_err()
+    );
+    assert_eq!(svc.list().await.unwrap().len(), 1);
+    assert!(svc.list_trash().await.unwrap().is_empty());
+    assert_eq!(
+        svc.audit_log(AuditQuery::default()).await.unwrap().len(),
+        audited
+    );
+}
+
+#[tokio::test]
+async fn config_dir_round_trips_through_the_database() {
+    let td = tempdir().unwrap();
+    let config_dir = td.path().join("config");
+    fs::create_dir(&config_dir).await.unwrap();
+    let files = FileAppService::new(&config_dir);
+    let app = files.create("Commented".to_string()).await.unwrap();
+    let path = config_dir.join(&app.filename);
+    let content = fs::read_to_string(&path).await.unwrap();
+    fs::write(&path, format!("# kept by the database\n{content}"))
+        .await
+        .unwrap();
+    let app = files.get(app.id).await.unwrap();
+    fs::write(
+        config_dir.join("shared.yaml"),
+        "envs:\n  - id: local\n    name: Local\n    url: http://localhost:8000\n",
+    )
+    .await
+    .unwrap();
+    let mut extending = files.create("Extending".to_string()).await.unwrap();
+    extending.extends = Some("shared.yaml".to_string());
+    let extending = files.update(extending).await.unwrap();
+
+    let db = DuckDbAppService::new(td.path().join("apps.duckdb")).unwrap();
+    let imported = db.import_config_dir(&config_dir).await.unwrap().apps;
+    assert_eq!(imported.len(), 2);
+    assert!(imported.iter().all(Result::is_ok));
+
+    // files are stored as they are, so their etags are unchanged
+    let stored = db.get(app.id.clone()).await.unwrap();
+    assert_eq!(
+        (stored.etag, stored.filename),
+        (app.etag.clone(), app.filename.clone())
+    );
+    // apps extending a shared file are stored with the shared entities included
+    let flattened = db.get(extending.id.clone()).await.unwrap();
+    assert_eq!(flattened.extends, None);
+    assert_eq!(flattened.envs.len(), 1);
+
+    // importing again reports the apps already in the database
+    let again = db.import_config_dir(&config_dir).await.unwrap().apps;
+    assert!(
+        again
+            .iter()
+            .all(|r| matches!(r, Err(AppError::AlreadyExists { .. })))
+    );
+
+    let exported_dir = td.path().join("exported");
+    let exported = db.export_config_dir(&exported_dir).await.unwrap();
+    assert_eq!(exported.len(), 2);
+    let exported_files = FileAppService::new(&exported_dir);
+    assert_eq!(
+        exported_files.get(app.id.clone()).await.unwrap().etag,
+        app.etag
+    );
+    let exported_content = fs::read_to_string(exported_dir.join(&app.filename))
+        .await
+        .unwrap();
+    assert!(exported_content.starts_with("# kept by the database"));
+
+    // existing files are not overwritten
+    let again = db.export_config_dir(&exported_dir).await.unwrap();
+    assert!(
+        again
+            .iter()
+            .all(|r| matches!(r, Err(AppError::AlreadyExists { .. })))
+    );
+}
+
+#[tokio::test]
+async fn revisions_and_deleted_apps_are_imported_with_the_config_dir() {
+    let td = tempdir().unwrap();
+    let config_dir = td.path().join("config");
+    fs::create_dir(&config_dir).await.unwrap();
+    let files = FileAppService::new(&config_dir);
+    let v1 = files.create("Original".to_string()).await.unwrap();
+    let mut renamed = v1.clone();
+    renamed.name = "Renamed".to_string();
+    let v2 = files.update(renamed).await.unwrap();
+    let deleted = files.create("Deleted".to_string()).await.unwrap();
+    let mut renamed = deleted.clone();
+    renamed.name = "Deleted renamed".to_string();
+    files.update(renamed).await.unwrap();
+    files.delete(deleted.id.clone()).await.unwrap();
+    let trash_id = files.list_trash().await.unwrap()[0].trash_id.clone();
+
+    let db = DuckDbAppService::new(td.path().join("apps.duckdb")).unwrap();
+    let imported = db.import_config_dir(&config_dir).await.unwrap();
+    assert_eq!(imported.apps.len(), 1);
+    assert!(imported.apps.iter().all(Result::is_ok));
+    assert_eq!(imported.trash.len(), 1);
+    assert_eq!(imported.trash[0].as_ref().unwrap(), &trash_id);
+
+    let revisions = db.list_revisions(v1.id.clone()).await.unwrap();
+    let etags: Vec<&str> = revisions.iter().map(|r| r.etag.as_str()).collect();
+    assert_eq!(etags, vec![&v2.etag, &v1.etag]);
+    let old = db
+        .get_revision(v1.id.clone(), v1.etag.clone())
+        .await
+        .unwrap();
+    assert_eq!(old.name, "Original");
+
+    let trash = db.list_trash().await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].trash_id, trash_id);
+    assert_eq!(trash[0].name.as_deref(), Some("Deleted renamed"));
+
+    // importing again reports the deleted apps already in the database
+    let again = db.import_config_dir(&config_dir).await.unwrap();
+    assert_eq!(again.trash.len(), 1);
+    assert!(matches!(
+        again.trash[0],
+        Err(AppError::AlreadyExists { .. })
+    ));
+
+    // restored apps keep their revisions
+    let restored = db.restore(trash_id).await.unwrap();
+    assert_eq!(db.list_revisions(restored.id).await.unwrap().len(), 2);
+}
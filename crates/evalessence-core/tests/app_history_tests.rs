@@ -0,0 +1,163 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{AppChange, AppError, AppService};
+use evalessence_core::app_core::FileAppService;
+use serde_json::json;
+use tempfile::tempdir;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+#[tokio::test]
+async fn updates_are_recorded_as_revisions() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let v1 = svc.create("History".to_string()).await.unwrap();
+    let v2 = svc
+        .add_env(
+            v1.id.clone(),
+            v1.etag.clone(),
+            "local".to_string(),
+            "http://localhost:8000".to_string(),
+        )
+        .await
+        .unwrap();
+    let mut renamed = v2.clone();
+    renamed.name = "Renamed".to_string();
+    let v3 = svc.update(renamed).await.unwrap();
+
+    let revisions = svc.list_revisions(v1.id.clone()).await.unwrap();
+    let etags: Vec<&str> = revisions.iter().map(|r| r.etag.as_str()).collect();
+    assert_eq!(etags, vec![&v3.etag, &v2.etag, &v1.etag]);
+    assert!(revisions[0].saved_at >= revisions[1].saved_at);
+
+    let old = svc
+        .get_revision(v1.id.clone(), v1.etag.clone())
+        .await
+        .unwrap();
+    assert_eq!(old.name, "History");
+    assert!(old.envs.is_empty());
+    assert_eq!(old.etag, v1.etag);
+
+    let err = svc
+        .get_revision(v1.id.clone(), "../../app".to_string())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::RevisionNotFound { .. }));
+}
+
+#[tokio::test]
+async fn diff_matches_entities_by_id() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let v1 = svc.create("Diff".to_string()).await.unwrap();
+    let v2 = svc
+        .add_env(
+            v1.id.clone(),
+            v1.etag.clone(),
+            "local".to_string(),
+            "http://localhost:8000".to_string(),
+        )
+        .await
+        .unwrap();
+    let mut env = v2.envs[0].clone();
+    env.url = "http://localhost:9000".to_string();
+    let v3 = svc
+        .update_env(v1.id.clone(), v2.etag.clone(), env.clone())
+        .await
+        .unwrap();
+
+    let changes = svc
+        .diff_revisions(v1.id.clone(), v2.etag.clone(), v3.etag.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        changes,
+        vec![AppChange {
+            path: format!("envs[{}].url", env.id),
+            old: Some(json!("http://localhost:8000")),
+            new: Some(json!("http://localhost:9000")),
+        }]
+    );
+
+    let changes = svc
+        .diff_revisions(v1.id.clone(), v3.etag, v1.etag)
+        .await
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path, format!("envs[{}]", env.id));
+    assert_eq!(changes[0].new, None);
+}
+
+#[tokio::test]
+async fn revert_writes_an_old_revision_as_a_new_version() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let v1 = svc.create("Original".to_string()).await.unwrap();
+    let mut renamed = v1.clone();
+    renamed.name = "Renamed".to_string();
+    let v2 = svc.update(renamed).await.unwrap();
+
+    // the current etag is required, like for update
+    let err = svc
+        .revert(v1.id.clone(), v1.etag.clone(), v1.etag.clone())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::Conflict { .. }));
+
+    let reverted = svc
+        .revert(v1.id.clone(), v2.etag.clone(), v1.etag.clone())
+        .await
+        .unwrap();
+    assert_eq!(reverted.name, "Original");
+    // same content as v1, so same etag
+    assert_eq!(reverted.etag, v1.etag);
+
+    let revisions = svc.list_revisions(v1.id).await.unwrap();
+    let etags: Vec<&str> = revisions.iter().map(|r| r.etag.as_str()).collect();
+    assert_eq!(etags, vec![&v1.etag, &v2.etag]);
+}
+
+#[tokio::test]
+async fn revisions_keep_the_shared_file_they_extended() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    tokio::fs::write(
+        td.path().join("shared.yaml"),
+        "envs:\n  - id: local\n    name: Local\n    url: http://localhost:8000\n",
+    )
+    .await
+    .unwrap();
+    let created = svc.create("Layered".to_string()).await.unwrap();
+    let mut extending = created.clone();
+    extending.extends = Some("shared.yaml".to_string());
+    let v1 = svc.update(extending).await.unwrap();
+    let mut renamed = v1.clone();
+    renamed.name = "Renamed".to_string();
+    svc.update(renamed).await.unwrap();
+
+    // the shared file changes after v1 has been recorded
+    tokio::fs::write(
+        td.path().join("shared.yaml"),
+        "envs:\n  - id: local\n    name: Local\n    url: http://localhost:9000\n",
+    )
+    .await
+    .unwrap();
+
+    let old = svc
+        .get_revision(v1.id.clone(), v1.etag.clone())
+        .await
+        .unwrap();
+    assert_eq!(old.name, "Layered");
+    assert_eq!(old.envs, v1.envs);
+    assert_eq!(old.envs[0].url, "http://localhost:8000");
+
+    let current = svc.get(v1.id).await.unwrap();
+    assert_eq!(current.envs[0].url, "http://localhost:9000");
+}
@@ -0,0 +1,107 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{App, AppError, AppService, EnvHeader, HeaderValue};
+use evalessence_core::app_core::FileAppService;
+use tempfile::tempdir;
+use tokio::fs;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+async fn app_with_env(svc: &FileAppService, url: &str) -> App {
+    let app = svc.create("Templated".to_string()).await.unwrap();
+    svc.add_env(app.id, app.etag, "local".to_string(), url.to_string())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn placeholders_are_resolved_on_use_and_kept_on_save() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    fs::write(td.path().join(".env"), "API_PORT=9000\n")
+        .await
+        .unwrap();
+    let template = "http://${API_HOST:-localhost}:${API_PORT}";
+    let app = app_with_env(&svc, template).await;
+
+    let mut env = app.envs[0].clone();
+    // cargo sets this variable when running tests
+    env.headers = vec![EnvHeader {
+        name: "X-Client".to_string(),
+        value: HeaderValue::Plain("${CARGO_PKG_NAME}/$${literal}".to_string()),
+    }];
+    let app = svc
+        .update_env(app.id.clone(), app.etag, env.clone())
+        .await
+        .unwrap();
+
+    let resolved = svc.resolve_app(app.id.clone()).await.unwrap();
+    assert_eq!(resolved.envs[0].url, "http://localhost:9000");
+    assert_eq!(resolved.etag, app.etag);
+    let headers = svc
+        .resolve_env_headers(app.id.clone(), env.id.clone())
+        .await
+        .unwrap();
+    assert_eq!(headers[0].value.expose(), "evalessence-core/${literal}");
+
+    // the templates are what get returns and update writes back
+    let mut renamed = svc.get(app.id.clone()).await.unwrap();
+    assert_eq!(renamed.envs[0].url, template);
+    renamed.name = "Renamed".to_string();
+    let renamed = svc.update(renamed).await.unwrap();
+    let on_disk = fs::read_to_string(td.path().join(&renamed.filename))
+        .await
+        .unwrap();
+    assert!(on_disk.contains(template));
+}
+
+#[tokio::test]
+async fn unset_variable_is_reported_with_its_location() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = app_with_env(&svc, "https://${EVALESSENCE_TEST_UNSET_HOST}/api").await;
+
+    match svc.resolve_app(app.id).await.unwrap_err() {
+        AppError::UnresolvedVariable {
+            filename,
+            path,
+            variable,
+        } => {
+            assert_eq!(filename, app.filename);
+            assert_eq!(path, format!("envs[{}].url", app.envs[0].id));
+            assert_eq!(variable, "EVALESSENCE_TEST_UNSET_HOST");
+        }
+        other => panic!("expected unresolved variable, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn malformed_placeholders_are_rejected_on_save() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.create("Malformed".to_string()).await.unwrap();
+
+    let err = svc
+        .add_env(
+            app.id,
+            app.etag,
+            "local".to_string(),
+            "http://${HOST:-localhost".to_string(),
+        )
+        .await
+        .unwrap_err();
+    match err {
+        AppError::ValidationError { source, .. } => assert_eq!(
+            source.to_string(),
+            "envs[0].url: placeholder '${HOST:-localhost' is not closed by '}'"
+        ),
+        other => panic!("expected validation error, got {other:?}"),
+    }
+}
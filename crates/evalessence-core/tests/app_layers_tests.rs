@@ -0,0 +1,200 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{App, AppError, AppEvent, AppEventStream, AppService, EnvId};
+use evalessence_core::app_core::FileAppService;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::fs;
+use tokio::time::timeout;
+use tokio_stream::StreamExt;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+const SHARED_ENVS: &str = "\
+envs:
+  - id: local
+    name: Local
+    url: http://localhost:8000
+  - id: staging
+    name: Staging
+    url: https://staging.example.com
+";
+
+// An app extending shared.yaml, with the shared envs above
+async fn extending_app(svc: &FileAppService, config_dir: &Path) -> App {
+    fs::write(config_dir.join("shared.yaml"), SHARED_ENVS)
+        .await
+        .unwrap();
+    let mut app = svc.create("Layered".to_string()).await.unwrap();
+    app.extends = Some("shared.yaml".to_string());
+    svc.update(app).await.unwrap()
+}
+
+fn env_ids(app: &App) -> Vec<&str> {
+    app.envs.iter().map(|e| e.id.as_str()).collect()
+}
+
+#[tokio::test]
+async fn shared_entities_are_included_and_only_overrides_are_written() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = extending_app(&svc, td.path()).await;
+    assert_eq!(env_ids(&app), vec!["local", "staging"]);
+
+    // pipelines of the app can use the shared envs
+    let app = svc
+        .add_dataset(app.id.clone(), app.etag, "golden".to_string())
+        .await
+        .unwrap();
+    let app = svc
+        .add_pipeline(
+            app.id.clone(),
+            app.etag,
+            "chat".to_string(),
+            "/chat".to_string(),
+            EnvId::new("staging").unwrap(),
+            app.datasets[0].id.clone(),
+        )
+        .await
+        .unwrap();
+
+    let mut local = app.envs[0].clone();
+    local.url = "http://localhost:9000".to_string();
+    let app = svc
+        .update_env(app.id.clone(), app.etag, local)
+        .await
+        .unwrap();
+    assert_eq!(env_ids(&app), vec!["local", "staging"]);
+    assert_eq!(app.envs[0].url, "http://localhost:9000");
+
+    // only the overridden env is written in the app file, the shared file is untouched
+    let on_disk = fs::read_to_string(td.path().join(&app.filename))
+        .await
+        .unwrap();
+    assert!(on_disk.contains("extends: shared.yaml"));
+    assert!(on_disk.contains("http://localhost:9000"));
+    assert!(!on_disk.contains("staging.example.com"));
+    assert_eq!(
+        fs::read_to_string(td.path().join("shared.yaml"))
+            .await
+            .unwrap(),
+        SHARED_ENVS
+    );
+
+    let loaded = svc.get(app.id).await.unwrap();
+    assert_eq!(loaded.envs, app.envs);
+    assert_eq!(loaded.etag, app.etag);
+}
+
+#[tokio::test]
+async fn etag_covers_the_shared_file() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = extending_app(&svc, td.path()).await;
+
+    fs::write(
+        td.path().join("shared.yaml"),
+        SHARED_ENVS.replace("staging.example.com", "staging.example.org"),
+    )
+    .await
+    .unwrap();
+
+    let reloaded = svc.get(app.id.clone()).await.unwrap();
+    assert_ne!(reloaded.etag, app.etag);
+    assert_eq!(reloaded.envs[1].url, "https://staging.example.org");
+
+    let err = svc
+        .add_dataset(app.id, app.etag, "golden".to_string())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::Conflict { .. }));
+}
+
+#[tokio::test]
+async fn shared_entities_cannot_be_removed_from_an_app() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = extending_app(&svc, td.path()).await;
+
+    let err = svc
+        .remove_env(app.id, app.etag, EnvId::new("local").unwrap())
+        .await
+        .unwrap_err();
+    match err {
+        AppError::ValidationError { filename, source } => {
+            assert_eq!(filename, app.filename);
+            assert_eq!(
+                source.to_string(),
+                "Env 'local' is shared by 'shared.yaml', it can't be removed from the app"
+            );
+        }
+        other => panic!("expected validation error, got {other:?}"),
+    }
+}
+
+async fn next_event(events: &mut AppEventStream) -> AppEvent {
+    timeout(Duration::from_secs(5), events.next())
+        .await
+        .expect("event before timeout")
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn watched_changes_of_extending_apps_have_the_etag_of_get() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = extending_app(&svc, td.path()).await;
+    let mut events = svc.watch().unwrap();
+
+    let mut renamed = app.clone();
+    renamed.name = "Renamed".to_string();
+    let updated = svc.update(renamed).await.unwrap();
+
+    // the etag can be used to update the app without a conflict
+    assert_eq!(
+        next_event(&mut events).await,
+        AppEvent::Modified {
+            id: app.id.clone(),
+            filename: app.filename.clone(),
+            etag: updated.etag.clone(),
+        }
+    );
+    assert_eq!(svc.get(app.id).await.unwrap().etag, updated.etag);
+}
+
+#[tokio::test]
+async fn changes_of_a_shared_file_modify_the_apps_extending_it() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = extending_app(&svc, td.path()).await;
+    let mut events = svc.watch().unwrap();
+
+    fs::write(
+        td.path().join("shared.yaml"),
+        SHARED_ENVS.replace("staging.example.com", "staging.example.org"),
+    )
+    .await
+    .unwrap();
+
+    let reloaded = svc.get(app.id.clone()).await.unwrap();
+    assert_ne!(reloaded.etag, app.etag);
+    // the file may be seen while it is written, the last event has its content
+    loop {
+        let AppEvent::Modified { id, filename, etag } = next_event(&mut events).await else {
+            panic!("the app is modified");
+        };
+        assert_eq!((&id, &filename), (&app.id, &app.filename));
+        if etag == reloaded.etag {
+            break;
+        }
+    }
+}
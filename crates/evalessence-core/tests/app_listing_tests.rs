@@ -0,0 +1,180 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{AppError, AppHeaderPage, AppListOptions, AppOrder, AppService};
+use evalessence_api::dataset::OrderDirection;
+use evalessence_core::app_core::FileAppService;
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::fs;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+fn names(page: &AppHeaderPage) -> Vec<&str> {
+    page.headers.iter().map(|h| h.name.as_str()).collect()
+}
+
+#[tokio::test]
+async fn headers_are_filtered_sorted_and_paginated() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    for name in ["Chat bot", "Search", "chat summary", "Chatter", "Translate"] {
+        svc.create(name.to_string()).await.unwrap();
+    }
+
+    let all = svc.list_headers(AppListOptions::default()).await.unwrap();
+    assert_eq!(
+        names(&all),
+        vec!["Chat bot", "Chatter", "Search", "Translate", "chat summary"]
+    );
+    assert_eq!(all.total_count, 5);
+
+    let options = AppListOptions {
+        direction: OrderDirection::Desc,
+        name_filter: Some("CHAT".to_string()),
+        offset: 1,
+        limit: Some(1),
+        ..AppListOptions::default()
+    };
+    let page = svc.list_headers(options.clone()).await.unwrap();
+    assert_eq!(names(&page), vec!["Chatter"]);
+    // the total counts every matching app, not only the page
+    assert_eq!(page.total_count, 3);
+
+    let last = svc
+        .list_headers(AppListOptions {
+            offset: 2,
+            limit: Some(10),
+            ..options
+        })
+        .await
+        .unwrap();
+    assert_eq!(names(&last), vec!["Chat bot"]);
+}
+
+#[tokio::test]
+async fn headers_have_counts_and_modification_times() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let old = svc.create("Old".to_string()).await.unwrap();
+    // modification times of the files must differ
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let recent = svc.create("Recent".to_string()).await.unwrap();
+    let recent = svc
+        .add_env(
+            recent.id.clone(),
+            recent.etag,
+            "local".to_string(),
+            "http://localhost:8000".to_string(),
+        )
+        .await
+        .unwrap();
+    svc.add_dataset(recent.id.clone(), recent.etag, "golden".to_string())
+        .await
+        .unwrap();
+
+    let page = svc
+        .list_headers(AppListOptions {
+            order_by: AppOrder::Modified,
+            direction: OrderDirection::Desc,
+            ..AppListOptions::default()
+        })
+        .await
+        .unwrap();
+    let ids: Vec<_> = page.headers.iter().map(|h| h.id.clone()).collect();
+    assert_eq!(ids, vec![recent.id, old.id]);
+
+    let header = &page.headers[0];
+    assert_eq!(header.filename, recent.filename);
+    assert_eq!(
+        (
+            header.env_count,
+            header.dataset_count,
+            header.pipeline_count
+        ),
+        (1, 1, 0)
+    );
+    assert!(header.modified > page.headers[1].modified);
+}
+
+#[tokio::test]
+async fn unloadable_files_are_reported_with_every_page() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    for i in 0..40 {
+        svc.create(format!("App {i:02}")).await.unwrap();
+    }
+    fs::write(td.path().join("app-broken.yaml"), "not: [valid")
+        .await
+        .unwrap();
+
+    let page = svc
+        .list_headers(AppListOptions {
+            offset: 30,
+            limit: Some(20),
+            ..AppListOptions::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(page.total_count, 40);
+    assert_eq!(page.headers.len(), 10);
+    assert_eq!(page.headers[0].name, "App 30");
+    assert_eq!(page.errors.len(), 1);
+    assert!(matches!(
+        &page.errors[0],
+        AppError::ValidationError { filename, .. } if filename == "app-broken.yaml"
+    ));
+
+    // the full listing keeps loading every file too
+    assert_eq!(svc.list().await.unwrap().len(), 41);
+}
+
+#[tokio::test]
+async fn headers_follow_the_changes_of_the_app_and_shared_files() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let shared = td.path().join("shared.yaml");
+    let env = |id: &str| format!("  - id: {id}\n    name: {id}\n    url: http://{id}:8000\n");
+    fs::write(&shared, format!("envs:\n{}", env("local")))
+        .await
+        .unwrap();
+    let mut app = svc.create("Cached".to_string()).await.unwrap();
+    app.extends = Some("shared.yaml".to_string());
+    let app = svc.update(app).await.unwrap();
+    let path = td.path().join(&app.filename);
+
+    let header = |page: AppHeaderPage| {
+        let header = &page.headers[0];
+        (header.name.clone(), header.env_count)
+    };
+    let listed = svc.list_headers(AppListOptions::default()).await.unwrap();
+    assert_eq!(header(listed), ("Cached".to_string(), 1));
+
+    fs::write(
+        &shared,
+        format!("envs:\n{}{}", env("local"), env("staging")),
+    )
+    .await
+    .unwrap();
+    let listed = svc.list_headers(AppListOptions::default()).await.unwrap();
+    assert_eq!(header(listed), ("Cached".to_string(), 2));
+
+    let yaml = fs::read_to_string(&path).await.unwrap();
+    fs::write(&path, yaml.replace("name: Cached", "name: Edited outside"))
+        .await
+        .unwrap();
+    let listed = svc.list_headers(AppListOptions::default()).await.unwrap();
+    assert_eq!(header(listed), ("Edited outside".to_string(), 2));
+
+    // a file that can't be loaded anymore is reported, not listed from its previous content
+    fs::write(&path, "not: [valid").await.unwrap();
+    let listed = svc.list_headers(AppListOptions::default()).await.unwrap();
+    assert!(listed.headers.is_empty());
+    assert_eq!(listed.errors.len(), 1);
+}
@@ -0,0 +1,77 @@
+// remove lints that do not make sense in tests
+#![allow(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use evalessence_api::app::{AppError, AppId, AppService, Dataset, DatasetId};
+use evalessence_core::app_core::FileAppService;
+use std::collections::HashSet;
+use std::env;
+use std::process::Command;
+use tempfile::tempdir;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+// The worker processes are this test binary, running only `update_worker` with these variables set
+const CONFIG_DIR_VAR: &str = "EVALESSENCE_TEST_CONFIG_DIR";
+const APP_ID_VAR: &str = "EVALESSENCE_TEST_APP_ID";
+const WORKER_VAR: &str = "EVALESSENCE_TEST_WORKER";
+
+const WORKERS: usize = 4;
+const UPDATES_PER_WORKER: usize = 20;
+
+// Add datasets to the app one update at a time, reloading it on conflicts.
+// Does nothing when run as a regular test
+#[tokio::test]
+async fn update_worker() {
+    let (Ok(config_dir), Ok(app_id), Ok(worker)) = (
+        env::var(CONFIG_DIR_VAR),
+        env::var(APP_ID_VAR),
+        env::var(WORKER_VAR),
+    ) else {
+        return;
+    };
+    let svc = FileAppService::new(config_dir);
+    let app_id = AppId::new(app_id).unwrap();
+
+    for i in 0..UPDATES_PER_WORKER {
+        loop {
+            let mut app = svc.get(app_id.clone()).await.unwrap();
+            app.datasets.push(Dataset {
+                id: DatasetId::new(format!("worker-{worker}-{i}")).unwrap(),
+                name: format!("Worker {worker} update {i}"),
+            });
+            match svc.update(app).await {
+                Ok(_) => break,
+                Err(AppError::Conflict { .. }) => {}
+                Err(e) => panic!("unexpected error: {e}"),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn concurrent_updates_from_several_processes_are_not_lost() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.create("Shared".to_string()).await.unwrap();
+
+    let exe = env::current_exe().unwrap();
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|worker| {
+            Command::new(&exe)
+                .args(["update_worker", "--exact", "--test-threads=1"])
+                .env(CONFIG_DIR_VAR, td.path())
+                .env(APP_ID_VAR, app.id.as_str())
+                .env(WORKER_VAR, worker.to_string())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut worker in workers {
+        assert!(worker.wait().unwrap().success());
+    }
+
+    let app = svc.get(app.id).await.unwrap();
+    let dataset_ids: HashSet<DatasetId> = app.datasets.into_iter().map(|d| d.id).collect();
+    assert_eq!(dataset_ids.len(), WORKERS * UPDATES_PER_WORKER);
+}
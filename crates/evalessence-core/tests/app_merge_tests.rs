@@ -0,0 +1,134 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{App, AppError, AppService, Dataset, DatasetId, FieldConflict};
+use evalessence_core::app_core::FileAppService;
+use serde_json::json;
+use tempfile::tempdir;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+// An app with one env, one dataset and two pipelines "first" and "second"
+async fn app_with_pipelines(svc: &FileAppService) -> App {
+    let app = svc.create("Merge".to_string()).await.unwrap();
+    let app = svc
+        .add_env(
+            app.id.clone(),
+            app.etag,
+            "local".to_string(),
+            "http://localhost:8000".to_string(),
+        )
+        .await
+        .unwrap();
+    let app = svc
+        .add_dataset(app.id.clone(), app.etag, "golden".to_string())
+        .await
+        .unwrap();
+    let mut app = app;
+    for name in ["first", "second"] {
+        app = svc
+            .add_pipeline(
+                app.id.clone(),
+                app.etag,
+                name.to_string(),
+                format!("/{name}"),
+                app.envs[0].id.clone(),
+                app.datasets[0].id.clone(),
+            )
+            .await
+            .unwrap();
+    }
+    app
+}
+
+#[tokio::test]
+async fn updates_of_different_pipelines_are_merged() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let base = app_with_pipelines(&svc).await;
+
+    let mut alice = base.clone();
+    alice.pipelines[0].route = "/first/v2".to_string();
+    svc.update(alice).await.unwrap();
+
+    // made from the same version, saved after alice's update
+    let mut bob = base.clone();
+    bob.pipelines[1].name = "Second renamed".to_string();
+    bob.datasets.push(Dataset {
+        id: DatasetId::new("extra").unwrap(),
+        name: "Extra".to_string(),
+    });
+    let merged = svc.update(bob).await.unwrap();
+
+    assert_eq!(merged.pipelines[0].route, "/first/v2");
+    assert_eq!(merged.pipelines[1].name, "Second renamed");
+    assert_eq!(merged.datasets.len(), 2);
+    assert_eq!(svc.get(base.id).await.unwrap().etag, merged.etag);
+}
+
+#[tokio::test]
+async fn overlapping_updates_report_the_conflicting_fields() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let base = app_with_pipelines(&svc).await;
+    let (first, second) = (base.pipelines[0].clone(), base.pipelines[1].clone());
+
+    let mut alice = base.clone();
+    alice.pipelines[0].route = "/alice".to_string();
+    alice.pipelines.remove(1);
+    let saved = svc.update(alice).await.unwrap();
+
+    let mut bob = base.clone();
+    bob.pipelines[0].route = "/bob".to_string();
+    bob.pipelines[0].name = "First by bob".to_string();
+    bob.pipelines[1].route = "/second/v2".to_string();
+    let err = svc.update(bob).await.unwrap_err();
+
+    match err {
+        AppError::MergeConflict { conflicts, .. } => assert_eq!(
+            conflicts,
+            vec![
+                FieldConflict {
+                    path: format!("pipelines[{}].route", first.id),
+                    base: Some(json!("/first")),
+                    ours: Some(json!("/bob")),
+                    theirs: Some(json!("/alice")),
+                },
+                FieldConflict {
+                    path: format!("pipelines[{}]", second.id),
+                    base: Some(serde_json::to_value(&second).unwrap()),
+                    ours: Some(json!({
+                        "id": second.id.as_str(),
+                        "name": "second",
+                        "route": "/second/v2",
+                        "env_id": second.env_id.as_str(),
+                        "dataset_id": second.dataset_id.as_str(),
+                    })),
+                    theirs: None,
+                },
+            ]
+        ),
+        other => panic!("expected merge conflict, got {other:?}"),
+    }
+
+    // nothing has been written
+    assert_eq!(svc.get(base.id).await.unwrap().etag, saved.etag);
+}
+
+#[tokio::test]
+async fn unknown_base_version_is_a_conflict() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.create("Unknown".to_string()).await.unwrap();
+
+    let mut stale = app;
+    stale.etag = "0".repeat(64);
+    let err = svc.update(stale).await.unwrap_err();
+    assert!(matches!(err, AppError::Conflict { .. }));
+}
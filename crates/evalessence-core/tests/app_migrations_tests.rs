@@ -0,0 +1,92 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{AppError, AppId, AppService};
+use evalessence_core::app_core::FileAppService;
+use tempfile::tempdir;
+use tokio::fs;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+// Format written before `schema_version` was introduced
+const V0_APP: &str = "
+id: legacy-abcd
+name: Legacy
+envs:
+  - id: local
+    url: http://localhost:8000
+    name: Local
+datasets:
+  - id: data
+    name: Data
+pipelines:
+  - id: chat
+    name: Chat
+    route: /chat
+    env_id: local
+    dataset_id: data
+";
+
+#[tokio::test]
+async fn v0_file_is_migrated_in_memory_then_on_update() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let path = td.path().join("app-legacy-abcd.yaml");
+    fs::write(&path, V0_APP).await.unwrap();
+
+    let app = svc.get(AppId::new("legacy-abcd").unwrap()).await.unwrap();
+    assert_eq!(app.name, "Legacy");
+    assert_eq!(app.envs[0].url, "http://localhost:8000");
+    assert_eq!(app.pipelines[0].env_id, app.envs[0].id);
+
+    // reading does not touch the file
+    assert_eq!(fs::read_to_string(&path).await.unwrap(), V0_APP);
+
+    let updated = svc.update(app).await.unwrap();
+    let on_disk = fs::read_to_string(&path).await.unwrap();
+    assert!(on_disk.contains("schema_version: 1"));
+    assert_eq!(updated.pipelines.len(), 1);
+}
+
+#[tokio::test]
+async fn created_files_have_current_schema_version() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+
+    let app = svc.create("Fresh".to_string()).await.unwrap();
+    let on_disk = fs::read_to_string(td.path().join(&app.filename))
+        .await
+        .unwrap();
+    assert!(on_disk.starts_with("schema_version: 1\n"));
+}
+
+#[tokio::test]
+async fn newer_schema_version_is_rejected() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let future = format!("schema_version: 99\n{V0_APP}");
+    fs::write(td.path().join("app-legacy-abcd.yaml"), future)
+        .await
+        .unwrap();
+
+    let err = svc
+        .get(AppId::new("legacy-abcd").unwrap())
+        .await
+        .unwrap_err();
+    match err {
+        AppError::ValidationError { source, .. } => {
+            assert!(
+                source
+                    .to_string()
+                    .contains("newer than the supported version")
+            );
+        }
+        other => panic!("expected validation error, got {other:?}"),
+    }
+}
@@ -0,0 +1,314 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{App, AppService, EnvHeader, HeaderValue, ProbeOptions, RouteProbe};
+use evalessence_core::app_core::FileAppService;
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair, date_time_ymd,
+};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::tempdir;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+// Requests received by the stub server, as "METHOD /path x-team-header body"
+type Received = Arc<Mutex<Vec<String>>>;
+
+// A minimal HTTP server answering 404 on /missing and 200 everywhere else
+async fn stub_server() -> (SocketAddr, Received) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = Received::default();
+
+    let log = received.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let log = log.clone();
+            tokio::spawn(async move { handle(stream, &log).await });
+        }
+    });
+    (addr, received)
+}
+
+async fn handle(mut stream: impl AsyncRead + AsyncWrite + Unpin, log: &Received) {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+    let head_end = loop {
+        let n = match stream.read(&mut chunk).await {
+            // closed without a request
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        buffer.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).to_lowercase();
+    let header = |name: &str| {
+        head.lines()
+            .find_map(|line| line.strip_prefix(&format!("{name}: ")))
+            .unwrap_or_default()
+            .to_string()
+    };
+    let content_length: usize = header("content-length").parse().unwrap_or(0);
+    while buffer.len() < head_end + content_length {
+        let n = stream.read(&mut chunk).await.unwrap();
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+
+    let request_line: Vec<&str> = head.lines().next().unwrap().split(' ').collect();
+    let (method, path) = (request_line[0].to_uppercase(), request_line[1].to_string());
+    let body = String::from_utf8_lossy(&buffer[head_end..]).into_owned();
+    log.lock().unwrap().push(
+        format!("{method} {path} {} {body}", header("x-team"))
+            .trim_end()
+            .to_string(),
+    );
+
+    let status = if path == "/missing" {
+        "404 Not Found"
+    } else {
+        "200 OK"
+    };
+    let response = format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+    stream.write_all(response.as_bytes()).await.unwrap();
+}
+
+// The stub server over TLS, with a certificate for localhost valid until the start of 2031,
+// issued by the returned CA certificate (DER encoded)
+async fn tls_stub_server() -> (SocketAddr, Vec<u8>, Received) {
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "evalessence test CA");
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "localhost");
+    params.not_after = date_time_ymd(2031, 1, 1);
+    let certificate = params.signed_by(&key, &ca).unwrap();
+
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![certificate.der().clone()],
+            PrivateKeyDer::try_from(key.serialize_der()).unwrap(),
+        )
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = Received::default();
+    let log = received.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (acceptor, log) = (acceptor.clone(), log.clone());
+            tokio::spawn(async move {
+                if let Ok(stream) = acceptor.accept(stream).await {
+                    handle(stream, &log).await;
+                }
+            });
+        }
+    });
+    (addr, ca.der().to_vec(), received)
+}
+
+// An app with an env on `url` and three pipelines: /chat, /missing and /summary with a ping sample
+async fn app_with_routes(svc: &FileAppService, url: String) -> App {
+    let app = svc.create("Probe".to_string()).await.unwrap();
+    let app = svc
+        .add_env(app.id.clone(), app.etag, "local".to_string(), url)
+        .await
+        .unwrap();
+    let mut env = app.envs[0].clone();
+    env.headers = vec![EnvHeader {
+        name: "X-Team".to_string(),
+        value: HeaderValue::Plain("evals".to_string()),
+    }];
+    let app = svc.update_env(app.id.clone(), app.etag, env).await.unwrap();
+    let mut app = svc
+        .add_dataset(app.id.clone(), app.etag, "golden".to_string())
+        .await
+        .unwrap();
+    for route in ["/chat", "/missing", "/summary"] {
+        app = svc
+            .add_pipeline(
+                app.id.clone(),
+                app.etag,
+                route.trim_start_matches('/').to_string(),
+                route.to_string(),
+                app.envs[0].id.clone(),
+                app.datasets[0].id.clone(),
+            )
+            .await
+            .unwrap();
+    }
+    app.pipelines[2].ping = Some(json!({"input": "ping"}));
+    svc.update(app).await.unwrap()
+}
+
+#[tokio::test]
+async fn probe_reports_status_of_env_and_routes() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let (addr, received) = stub_server().await;
+    let app = app_with_routes(&svc, format!("http://{addr}/")).await;
+
+    let health = svc
+        .probe_env(
+            app.id.clone(),
+            app.envs[0].id.clone(),
+            ProbeOptions {
+                routes: Some(RouteProbe::Head),
+                ..ProbeOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(health.env.reachable);
+    assert_eq!(health.env.status, Some(200));
+    assert!(health.env.latency.is_some());
+    // plain http
+    assert_eq!(health.env.tls, None);
+
+    let routes: Vec<(&str, Option<u16>)> = health
+        .routes
+        .iter()
+        .map(|(_, probe)| (probe.url.as_str(), probe.status))
+        .collect();
+    let base = format!("http://{addr}");
+    assert_eq!(
+        routes,
+        vec![
+            (format!("{base}/chat").as_str(), Some(200)),
+            (format!("{base}/missing").as_str(), Some(404)),
+            (format!("{base}/summary").as_str(), Some(200)),
+        ]
+    );
+
+    let mut received = received.lock().unwrap().clone();
+    received.sort();
+    assert_eq!(
+        received,
+        vec![
+            "GET / evals",
+            "HEAD /chat evals",
+            "HEAD /missing evals",
+            r#"POST /summary evals {"input":"ping"}"#,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unreachable_env_is_reported_in_the_result() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    // bind then drop a listener to get a port nobody listens on
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let app = app_with_routes(&svc, format!("http://{addr}")).await;
+
+    let health = svc
+        .probe_env(
+            app.id.clone(),
+            app.envs[0].id.clone(),
+            ProbeOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert!(!health.env.reachable);
+    assert_eq!(health.env.status, None);
+    assert!(health.env.error.is_some());
+    // routes are only probed on demand
+    assert!(health.routes.is_empty());
+}
+
+#[tokio::test]
+async fn probe_reports_the_tls_session_of_https_envs() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let (addr, ca, received) = tls_stub_server().await;
+    let app = app_with_routes(&svc, format!("https://localhost:{}", addr.port())).await;
+
+    let health = svc
+        .probe_env(
+            app.id.clone(),
+            app.envs[0].id.clone(),
+            ProbeOptions {
+                routes: Some(RouteProbe::Head),
+                root_certificates: vec![ca],
+                ..ProbeOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(health.env.reachable, "{:?}", health.env.error);
+    assert_eq!(health.env.status, Some(200));
+    let tls = health.env.tls.unwrap();
+    assert!(tls.peer_certificate.is_some());
+    assert_eq!(
+        tls.not_after,
+        // 2031-01-01, 22280 days after the epoch
+        Some(UNIX_EPOCH + Duration::from_hours(22_280 * 24))
+    );
+    assert_eq!(tls.protocol_version.as_deref(), Some("TLSv1_3"));
+    // the routes are on the same server
+    assert!(
+        health
+            .routes
+            .iter()
+            .all(|(_, probe)| probe.tls.as_ref() == Some(&tls))
+    );
+    assert_eq!(received.lock().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn untrusted_certificates_are_reported_as_unreachable() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let (addr, _, _) = tls_stub_server().await;
+    let app = app_with_routes(&svc, format!("https://localhost:{}", addr.port())).await;
+
+    let health = svc
+        .probe_env(
+            app.id.clone(),
+            app.envs[0].id.clone(),
+            ProbeOptions::default(),
+        )
+        .await
+        .unwrap();
+
+    assert!(!health.env.reachable);
+    assert_eq!(health.env.tls, None);
+    assert!(health.env.error.is_some());
+}
@@ -0,0 +1,124 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{App, AppError, AppService, EnvAuth, EnvHeader, HeaderValue, SecretRef};
+use evalessence_core::app_core::FileAppService;
+use tempfile::tempdir;
+use tokio::fs;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+async fn app_with_env(svc: &FileAppService) -> App {
+    let app = svc.create("Secrets".to_string()).await.unwrap();
+    svc.add_env(
+        app.id,
+        app.etag,
+        "staging".to_string(),
+        "https://staging.example.com".to_string(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn secrets_are_resolved_at_call_time_and_never_written() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = app_with_env(&svc).await;
+
+    let mut env = app.envs[0].clone();
+    env.headers = vec![
+        EnvHeader {
+            name: "X-Team".to_string(),
+            value: HeaderValue::Plain("evals".to_string()),
+        },
+        EnvHeader {
+            name: "X-Api-Key".to_string(),
+            value: HeaderValue::Secret(SecretRef::SecretsFile("API_KEY".to_string())),
+        },
+    ];
+    // cargo sets this variable when running tests
+    env.auth = Some(EnvAuth::Bearer {
+        token: SecretRef::EnvVar("CARGO_PKG_NAME".to_string()),
+    });
+    let app = svc
+        .update_env(app.id.clone(), app.etag.clone(), env.clone())
+        .await
+        .unwrap();
+
+    // the secrets file is read at call time, not when the env is saved
+    fs::write(
+        td.path().join(".env"),
+        "# staging secrets\nexport API_KEY=\"s3cr3t-key\"\n",
+    )
+    .await
+    .unwrap();
+
+    let headers = svc
+        .resolve_env_headers(app.id.clone(), env.id.clone())
+        .await
+        .unwrap();
+    let resolved: Vec<(&str, &str)> = headers
+        .iter()
+        .map(|h| (h.name.as_str(), h.value.expose()))
+        .collect();
+    assert_eq!(
+        resolved,
+        vec![
+            ("X-Team", "evals"),
+            ("X-Api-Key", "s3cr3t-key"),
+            ("Authorization", "Bearer evalessence-core"),
+        ]
+    );
+
+    // resolved values never show up in debug or serialized output
+    assert!(!format!("{headers:?}").contains("s3cr3t-key"));
+    assert!(
+        !serde_json::to_string(&headers)
+            .unwrap()
+            .contains("s3cr3t-key")
+    );
+
+    // only the references are stored
+    let on_disk = fs::read_to_string(td.path().join(&app.filename))
+        .await
+        .unwrap();
+    assert!(on_disk.contains("secrets_file: API_KEY"));
+    assert!(!on_disk.contains("s3cr3t-key"));
+    assert_eq!(svc.get(app.id).await.unwrap().envs[0].auth, env.auth);
+}
+
+#[tokio::test]
+async fn missing_secret_is_reported_with_its_reference() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = app_with_env(&svc).await;
+
+    let mut env = app.envs[0].clone();
+    env.auth = Some(EnvAuth::Basic {
+        username: "admin".to_string(),
+        password: SecretRef::SecretsFile("ADMIN_PASSWORD".to_string()),
+    });
+    let app = svc
+        .update_env(app.id.clone(), app.etag.clone(), env.clone())
+        .await
+        .unwrap();
+
+    let err = svc
+        .resolve_env_headers(app.id, env.id.clone())
+        .await
+        .unwrap_err();
+    match err {
+        AppError::UnresolvedSecret { env_id, secret } => {
+            assert_eq!(env_id, env.id);
+            assert_eq!(secret, SecretRef::SecretsFile("ADMIN_PASSWORD".to_string()));
+        }
+        other => panic!("expected unresolved secret, got {other:?}"),
+    }
+}
@@ -0,0 +1,152 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{AppError, AppService};
+use evalessence_core::app_core::FileAppService;
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::fs;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+#[tokio::test]
+async fn deleted_app_is_restored_with_its_history() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.create("Trashed".to_string()).await.unwrap();
+    let mut renamed = app.clone();
+    renamed.name = "Renamed".to_string();
+    let app = svc.update(renamed).await.unwrap();
+
+    svc.delete(app.id.clone()).await.unwrap();
+    assert!(!td.path().join(&app.filename).exists());
+    assert!(svc.list().await.unwrap().is_empty());
+
+    let trash = svc.list_trash().await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].id, app.id);
+    assert_eq!(trash[0].name.as_deref(), Some("Renamed"));
+    assert_eq!(trash[0].filename, app.filename);
+
+    let restored = svc.restore(trash[0].trash_id.clone()).await.unwrap();
+    assert_eq!(restored.etag, app.etag);
+    assert_eq!(svc.get(app.id.clone()).await.unwrap().name, "Renamed");
+    assert_eq!(svc.list_revisions(app.id).await.unwrap().len(), 2);
+    assert!(svc.list_trash().await.unwrap().is_empty());
+
+    // restored once, the trash id is gone
+    let err = svc.restore(trash[0].trash_id.clone()).await.unwrap_err();
+    assert!(matches!(err, AppError::TrashNotFound { .. }));
+}
+
+#[tokio::test]
+async fn restore_fails_if_the_file_is_used_again() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.create("Reused".to_string()).await.unwrap();
+    let content = fs::read(td.path().join(&app.filename)).await.unwrap();
+
+    svc.delete(app.id.clone()).await.unwrap();
+    // the file is written again outside of the service
+    fs::write(td.path().join(&app.filename), &content)
+        .await
+        .unwrap();
+
+    let trash_id = svc.list_trash().await.unwrap()[0].trash_id.clone();
+    match svc.restore(trash_id).await.unwrap_err() {
+        AppError::AlreadyExists { id, filename } => {
+            assert_eq!(id, app.id);
+            assert_eq!(filename, app.filename);
+        }
+        other => panic!("expected already exists, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn purge_removes_the_app_and_its_history() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let kept = svc.create("Kept".to_string()).await.unwrap();
+    let purged = svc.create("Purged".to_string()).await.unwrap();
+    svc.update(purged.clone()).await.unwrap();
+
+    svc.delete(kept.id.clone()).await.unwrap();
+    // deletion times are in milliseconds
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    svc.delete(purged.id.clone()).await.unwrap();
+    let trash = svc.list_trash().await.unwrap();
+    // most recently deleted first
+    assert_eq!(trash[0].id, purged.id);
+
+    svc.purge(trash[0].trash_id.clone()).await.unwrap();
+    let remaining: Vec<_> = svc
+        .list_trash()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|t| t.id)
+        .collect();
+    assert_eq!(remaining, vec![kept.id]);
+    assert!(!td.path().join(".history").join(purged.id.as_str()).exists());
+
+    // trash ids can't point outside of the trash
+    let err = svc
+        .purge(format!("0-../{}", kept.filename))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::TrashNotFound { .. }));
+}
+
+#[tokio::test]
+async fn expired_apps_are_purged_after_the_retention_period() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path()).with_trash_retention(Duration::from_secs(1));
+    let old = svc.create("Old".to_string()).await.unwrap();
+    svc.delete(old.id).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let recent = svc.create("Recent".to_string()).await.unwrap();
+    svc.delete(recent.id.clone()).await.unwrap();
+
+    let trash = svc.list_trash().await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].id, recent.id);
+}
+
+#[tokio::test]
+async fn files_deleted_at_the_same_time_are_all_kept() {
+    let td = tempdir().unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.create("Twice".to_string()).await.unwrap();
+    let content = fs::read(td.path().join(&app.filename)).await.unwrap();
+
+    // the same file is deleted twice, without waiting for the next millisecond
+    svc.delete(app.id.clone()).await.unwrap();
+    fs::write(td.path().join(&app.filename), &content)
+        .await
+        .unwrap();
+    svc.delete(app.id.clone()).await.unwrap();
+
+    let trash = svc.list_trash().await.unwrap();
+    assert_eq!(trash.len(), 2);
+    assert_ne!(trash[0].trash_id, trash[1].trash_id);
+    assert!(trash.iter().all(|t| t.filename == app.filename));
+
+    // files trashed before trash ids had a random suffix are still listed
+    fs::write(
+        td.path().join(".trash").join(format!("1-{}", app.filename)),
+        &content,
+    )
+    .await
+    .unwrap();
+    let trash = svc.list_trash().await.unwrap();
+    assert_eq!(trash.len(), 3);
+    svc.restore(format!("1-{}", app.filename)).await.unwrap();
+    assert_eq!(svc.get(app.id).await.unwrap().name, "Twice");
+}
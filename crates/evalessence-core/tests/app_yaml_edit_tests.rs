@@ -0,0 +1,112 @@
+// remove lints that do not make sense in tests
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::indexing_slicing
+)]
+
+use evalessence_api::app::{AppId, AppService};
+use evalessence_core::app_core::FileAppService;
+use tempfile::tempdir;
+use tokio::fs;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+const ANNOTATED: &str = "\
+# Chat assistant, owned by the evals team
+schema_version: 1
+id: chat-abcd
+name: 'Chat'
+envs:
+  # the dev server, started with `make run`
+  - id: local
+    name: Local
+    url: http://localhost:8000 # default port
+
+  # shared with the QA team, don't use for load tests
+  - id: staging
+    name: Staging
+    url: https://staging.example.com
+datasets: []
+pipelines: []
+";
+
+#[tokio::test]
+async fn updates_keep_comments_order_and_quotes() {
+    let td = tempdir().unwrap();
+    let path = td.path().join("app-chat-abcd.yaml");
+    fs::write(&path, ANNOTATED).await.unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.get(AppId::new("chat-abcd").unwrap()).await.unwrap();
+
+    let mut updated = app.clone();
+    updated.name = "Chat v2".to_string();
+    updated.envs[0].url = "http://localhost:9000".to_string();
+    let app = svc.update(updated).await.unwrap();
+    let app = svc
+        .add_dataset(app.id.clone(), app.etag, "golden".to_string())
+        .await
+        .unwrap();
+
+    let expected = ANNOTATED
+        .replace("'Chat'", "'Chat v2'")
+        .replace("localhost:8000", "localhost:9000")
+        .replace(
+            "datasets: []",
+            &format!(
+                "datasets:\n  - id: {}\n    name: golden",
+                app.datasets[0].id
+            ),
+        );
+    assert_eq!(fs::read_to_string(&path).await.unwrap(), expected);
+    assert_eq!(svc.get(app.id).await.unwrap().name, "Chat v2");
+}
+
+#[tokio::test]
+async fn single_quoted_values_stay_single_quoted() {
+    let td = tempdir().unwrap();
+    let path = td.path().join("app-chat-abcd.yaml");
+    fs::write(&path, ANNOTATED).await.unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.get(AppId::new("chat-abcd").unwrap()).await.unwrap();
+
+    let mut updated = app.clone();
+    updated.name = "Bob's \"chat\"".to_string();
+    let app = svc.update(updated).await.unwrap();
+
+    // single quotes are doubled in single quoted scalars, double quotes are written as is
+    let expected = ANNOTATED.replace("'Chat'", "'Bob''s \"chat\"'");
+    assert_eq!(fs::read_to_string(&path).await.unwrap(), expected);
+    assert_eq!(
+        svc.get(app.id.clone()).await.unwrap().name,
+        "Bob's \"chat\""
+    );
+
+    // line breaks can't be escaped in single quoted scalars
+    let mut updated = app;
+    updated.name = "Bob's\nchat".to_string();
+    let app = svc.update(updated).await.unwrap();
+    let expected = ANNOTATED.replace("'Chat'", "\"Bob's\\nchat\"");
+    assert_eq!(fs::read_to_string(&path).await.unwrap(), expected);
+    assert_eq!(svc.get(app.id).await.unwrap().name, "Bob's\nchat");
+}
+
+#[tokio::test]
+async fn removed_entities_take_their_comments_with_them() {
+    let td = tempdir().unwrap();
+    let path = td.path().join("app-chat-abcd.yaml");
+    fs::write(&path, ANNOTATED).await.unwrap();
+    let svc = FileAppService::new(td.path());
+    let app = svc.get(AppId::new("chat-abcd").unwrap()).await.unwrap();
+
+    let staging = app.envs[1].id.clone();
+    svc.remove_env(app.id, app.etag, staging).await.unwrap();
+
+    let expected = ANNOTATED.replace(
+        "\n  # shared with the QA team, don't use for load tests\n  - id: staging\n    name: Staging\n    url: https://staging.example.com\n",
+        "\n",
+    );
+    assert_eq!(fs::read_to_string(&path).await.unwrap(), expected);
+}
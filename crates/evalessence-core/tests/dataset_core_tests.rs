@@ -0,0 +1,306 @@
+// remove lints that do not make sense in tests
+#![allow(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use arrow::array::{Array, RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatchIterator;
+use evalessence_api::app::DatasetId;
+use evalessence_api::dataset::{
+    CompareOp, DatasetService, Delete, Filter, FilterField, OrderDirection, SamplePage,
+    SendableRecordBatchReader,
+};
+use evalessence_core::datatset_core::DuckDbDatasetService;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+fn samples(rows: &[(&str, &str)]) -> SendableRecordBatchReader {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("input", DataType::Utf8, true),
+    ]));
+    let ids: Vec<&str> = rows.iter().map(|(id, _)| *id).collect();
+    let inputs: Vec<&str> = rows.iter().map(|(_, input)| *input).collect();
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(StringArray::from(ids)),
+            Arc::new(StringArray::from(inputs)),
+        ],
+    )
+    .unwrap();
+    Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))
+}
+
+fn select_all(svc: &DuckDbDatasetService, dataset_id: &DatasetId) -> Vec<(String, String)> {
+    let reader = svc
+        .select(
+            dataset_id.clone(),
+            None,
+            Some(vec![("id".to_string(), OrderDirection::Asc)]),
+            None,
+            None,
+        )
+        .unwrap();
+    let mut rows = vec![];
+    for batch in reader {
+        let batch = batch.unwrap();
+        let ids = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let inputs = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        for i in 0..ids.len() {
+            rows.push((ids.value(i).to_string(), inputs.value(i).to_string()));
+        }
+    }
+    rows
+}
+
+#[test]
+fn first_upsert_creates_dataset_and_next_ones_replace_by_id() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    assert!(!svc.exists(golden.clone()).unwrap());
+
+    svc.update(
+        golden.clone(),
+        Some(samples(&[("a", "1"), ("b", "2")])),
+        None,
+    )
+    .unwrap();
+    assert!(svc.exists(golden.clone()).unwrap());
+
+    svc.update(
+        golden.clone(),
+        Some(samples(&[("b", "20"), ("c", "3")])),
+        Some(Delete::ByIds(StringArray::from(vec!["a"]))),
+    )
+    .unwrap();
+
+    assert_eq!(
+        select_all(&svc, &golden),
+        vec![
+            ("b".to_string(), "20".to_string()),
+            ("c".to_string(), "3".to_string()),
+        ]
+    );
+
+    // data is persisted across service instances
+    let reopened = DuckDbDatasetService::new(td.path()).unwrap();
+    assert_eq!(select_all(&reopened, &golden).len(), 2);
+}
+
+#[test]
+fn ids_to_delete_are_compared_as_values() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    let injected = "x') OR TRUE OR id IN ('x";
+    svc.update(
+        golden.clone(),
+        Some(samples(&[("it's", "1"), (injected, "2"), ("kept", "3")])),
+        None,
+    )
+    .unwrap();
+
+    svc.update(
+        golden.clone(),
+        None,
+        Some(Delete::ByIds(StringArray::from(vec!["it's", injected]))),
+    )
+    .unwrap();
+
+    assert_eq!(
+        select_all(&svc, &golden),
+        vec![("kept".to_string(), "3".to_string())]
+    );
+}
+
+#[test]
+fn loaded_tables_are_reloaded_only_when_their_file_changes() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let other_process = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+
+    svc.update(golden.clone(), Some(samples(&[("a", "1")])), None)
+        .unwrap();
+    assert_eq!(select_all(&other_process, &golden).len(), 1);
+
+    // an update removing nothing doesn't save a version
+    let unchanged = svc
+        .update(
+            golden.clone(),
+            None,
+            Some(Delete::ByIds(StringArray::from(vec!["missing"]))),
+        )
+        .unwrap();
+    assert_eq!(unchanged, None);
+    assert_eq!(svc.list_versions(golden.clone()).unwrap().len(), 1);
+
+    // the version saved by another service is loaded
+    svc.update(
+        golden.clone(),
+        Some(samples(&[("b", "2"), ("c", "3")])),
+        None,
+    )
+    .unwrap();
+    assert_eq!(select_all(&other_process, &golden).len(), 3);
+
+    // its update starts from the reloaded rows
+    other_process
+        .update(
+            golden.clone(),
+            None,
+            Some(Delete::ByIds(StringArray::from(vec!["a"]))),
+        )
+        .unwrap();
+    assert_eq!(
+        select_all(&svc, &golden),
+        vec![
+            ("b".to_string(), "2".to_string()),
+            ("c".to_string(), "3".to_string()),
+        ]
+    );
+}
+
+fn page_ids(page: SamplePage) -> Vec<String> {
+    let mut ids = vec![];
+    for batch in page.items {
+        let batch = batch.unwrap();
+        let column = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        for i in 0..column.len() {
+            ids.push(column.value(i).to_string());
+        }
+    }
+    ids
+}
+
+#[test]
+fn pages_continue_after_the_last_row_and_count_every_matching_row() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    svc.update(
+        golden.clone(),
+        Some(samples(&[
+            ("a", "2"),
+            ("b", "1"),
+            ("c", "2"),
+            ("d", "3"),
+            ("e", "2"),
+            ("f", "0"),
+        ])),
+        None,
+    )
+    .unwrap();
+    // the input of "d" is replaced by null
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("input", DataType::Utf8, true),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(StringArray::from(vec!["d"])),
+            Arc::new(StringArray::from(vec![None::<&str>])),
+        ],
+    )
+    .unwrap();
+    svc.update(
+        golden.clone(),
+        Some(Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))),
+        None,
+    )
+    .unwrap();
+
+    let not_f = Filter::Compare {
+        field: FilterField::column("id"),
+        op: CompareOp::Ne,
+        value: "f".into(),
+    };
+    let order_by = Some(("input".to_string(), OrderDirection::Desc));
+    let mut page = svc
+        .select_page(golden.clone(), Some(not_f), order_by, Some(2))
+        .unwrap();
+    let mut pages = vec![];
+    loop {
+        assert_eq!(page.total_count, 5);
+        let cursor = page.cursor.take();
+        pages.push(page_ids(page));
+        let Some(cursor) = cursor else { break };
+        page = svc.select_next(golden.clone(), cursor, Some(2)).unwrap();
+    }
+    // ties are sorted by id in the same direction, null values come last
+    assert_eq!(pages, vec![vec!["e", "c"], vec!["a", "b"], vec!["d"]]);
+
+    let all = svc.select_page(golden, None, None, None).unwrap();
+    assert!(all.cursor.is_none());
+    assert_eq!(page_ids(all), vec!["a", "b", "c", "d", "e", "f"]);
+}
+
+#[test]
+fn next_pages_are_not_shifted_by_concurrent_upserts_and_deletes() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    svc.update(
+        golden.clone(),
+        Some(samples(&[("b", "1"), ("d", "2"), ("f", "3"), ("h", "4")])),
+        None,
+    )
+    .unwrap();
+
+    let first = svc
+        .select_page(golden.clone(), None, None, Some(2))
+        .unwrap();
+    let cursor = first.cursor.clone().unwrap();
+    assert_eq!(page_ids(first), vec!["b", "d"]);
+
+    // rows added before the end of the page, or removed from it, don't move the next page
+    svc.update(
+        golden.clone(),
+        Some(samples(&[("a", "0"), ("c", "0"), ("g", "0")])),
+        Some(Delete::ByIds(StringArray::from(vec!["b"]))),
+    )
+    .unwrap();
+    let next = svc.select_next(golden, cursor, Some(2)).unwrap();
+    assert_eq!(next.total_count, 6);
+    assert_eq!(next.cursor.as_ref().map(|c| c.last_id.as_str()), Some("g"));
+    assert_eq!(page_ids(next), vec!["f", "g"]);
+}
+
+#[test]
+fn pages_can_not_use_raw_sql_filters_their_cursor_could_not_keep() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    svc.update(
+        golden.clone(),
+        Some(samples(&[("a", "1"), ("b", "2")])),
+        None,
+    )
+    .unwrap();
+
+    let raw = Filter::Not(Box::new(Filter::RawSql("id = 'b'".to_string())));
+    assert!(
+        svc.select_page(golden.clone(), Some(raw.clone()), None, Some(1))
+            .is_err()
+    );
+    // selects have no cursor, they accept them
+    let rows = svc.select(golden, Some(raw), None, None, None).unwrap();
+    assert_eq!(rows.map(|b| b.unwrap().num_rows()).sum::<usize>(), 1);
+}
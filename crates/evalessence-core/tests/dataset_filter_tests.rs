@@ -0,0 +1,220 @@
+// remove lints that do not make sense in tests
+#![allow(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use arrow::array::{Array, Int64Array, RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatchIterator;
+use evalessence_api::app::DatasetId;
+use evalessence_api::dataset::{
+    CompareOp, DatasetService, Delete, Filter, FilterField, FilterValue, OrderDirection,
+};
+use evalessence_core::datatset_core::DuckDbDatasetService;
+use std::sync::Arc;
+use tempfile::{TempDir, tempdir};
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+// A dataset of samples with a score, and metadata as JSON
+fn samples_dataset() -> (TempDir, DuckDbDatasetService, DatasetId) {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let dataset_id = DatasetId::new("samples").unwrap();
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("score", DataType::Int64, true),
+        Field::new("metadata", DataType::Utf8, true),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(StringArray::from(vec!["a", "b", "c", "d"])),
+            Arc::new(Int64Array::from(vec![Some(1), Some(5), None, Some(9)])),
+            Arc::new(StringArray::from(vec![
+                Some(r#"{"lang": "en", "tokens": 12, "a/b": true}"#),
+                Some(r#"{"lang": "fr", "tokens": 250}"#),
+                Some(r#"{"lang": "en"}"#),
+                None,
+            ])),
+        ],
+    )
+    .unwrap();
+    svc.update(
+        dataset_id.clone(),
+        Some(Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))),
+        None,
+    )
+    .unwrap();
+    (td, svc, dataset_id)
+}
+
+fn selected_ids(svc: &DuckDbDatasetService, dataset_id: &DatasetId, filter: Filter) -> Vec<String> {
+    let reader = svc
+        .select(
+            dataset_id.clone(),
+            Some(filter),
+            Some(vec![("id".to_string(), OrderDirection::Asc)]),
+            None,
+            None,
+        )
+        .unwrap();
+    let mut ids = vec![];
+    for batch in reader {
+        let batch = batch.unwrap();
+        let column = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        for i in 0..column.len() {
+            ids.push(column.value(i).to_string());
+        }
+    }
+    ids
+}
+
+fn compare(field: FilterField, op: CompareOp, value: impl Into<FilterValue>) -> Filter {
+    Filter::Compare {
+        field,
+        op,
+        value: value.into(),
+    }
+}
+
+#[test]
+fn filters_combine_comparisons_lists_ranges_and_null_checks() {
+    let (_td, svc, dataset_id) = samples_dataset();
+    let score = || FilterField::column("score");
+
+    assert_eq!(
+        selected_ids(&svc, &dataset_id, compare(score(), CompareOp::Ge, 5_i64)),
+        vec!["b", "d"]
+    );
+    assert_eq!(
+        selected_ids(
+            &svc,
+            &dataset_id,
+            Filter::In {
+                field: FilterField::column("id"),
+                values: vec!["a".into(), "c".into(), "z".into()],
+            }
+        ),
+        vec!["a", "c"]
+    );
+    assert_eq!(
+        selected_ids(
+            &svc,
+            &dataset_id,
+            Filter::Between {
+                field: score(),
+                low: 1_i64.into(),
+                high: 5_i64.into(),
+            }
+        ),
+        vec!["a", "b"]
+    );
+    assert_eq!(
+        selected_ids(&svc, &dataset_id, Filter::IsNull(score())),
+        vec!["c"]
+    );
+
+    let either = Filter::Or(vec![
+        Filter::IsNull(score()),
+        Filter::And(vec![
+            compare(score(), CompareOp::Gt, 1_i64),
+            Filter::Not(Box::new(compare(score(), CompareOp::Eq, 9_i64))),
+        ]),
+    ]);
+    assert_eq!(selected_ids(&svc, &dataset_id, either), vec!["b", "c"]);
+    // empty lists match nothing, empty conjunctions everything
+    assert!(selected_ids(&svc, &dataset_id, Filter::Or(vec![])).is_empty());
+    assert_eq!(
+        selected_ids(&svc, &dataset_id, Filter::And(vec![])).len(),
+        4
+    );
+}
+
+#[test]
+fn json_values_are_compared_with_the_type_of_the_filter_value() {
+    let (_td, svc, dataset_id) = samples_dataset();
+    let metadata = |path: &[&str]| FilterField::json("metadata", path.iter().copied());
+
+    assert_eq!(
+        selected_ids(
+            &svc,
+            &dataset_id,
+            compare(metadata(&["lang"]), CompareOp::Eq, "en")
+        ),
+        vec!["a", "c"]
+    );
+    // compared as numbers: "250" < "30" as strings
+    assert_eq!(
+        selected_ids(
+            &svc,
+            &dataset_id,
+            compare(metadata(&["tokens"]), CompareOp::Gt, 30_i64)
+        ),
+        vec!["b"]
+    );
+    assert_eq!(
+        selected_ids(&svc, &dataset_id, Filter::IsNull(metadata(&["tokens"]))),
+        vec!["c", "d"]
+    );
+    // keys can contain the separator of the path
+    assert_eq!(
+        selected_ids(
+            &svc,
+            &dataset_id,
+            compare(metadata(&["a/b"]), CompareOp::Eq, true)
+        ),
+        vec!["a"]
+    );
+}
+
+#[test]
+fn values_and_columns_cannot_inject_sql_but_raw_sql_can_be_opted_in() {
+    let (_td, svc, dataset_id) = samples_dataset();
+
+    let injected = compare(FilterField::column("id"), CompareOp::Eq, "a' OR '1'='1");
+    assert!(selected_ids(&svc, &dataset_id, injected).is_empty());
+    let unknown_column = svc.select(
+        dataset_id.clone(),
+        Some(Filter::IsNull(FilterField::column("id\" IS NULL OR \"id"))),
+        None,
+        None,
+        None,
+    );
+    assert!(unknown_column.is_err());
+
+    // the filters of deletes are parameterized too
+    svc.update(
+        dataset_id.clone(),
+        None,
+        Some(Delete::Where(compare(
+            FilterField::column("id"),
+            CompareOp::Eq,
+            "b' OR 'x'='x",
+        ))),
+    )
+    .unwrap();
+    assert_eq!(
+        selected_ids(&svc, &dataset_id, Filter::And(vec![])),
+        vec!["a", "b", "c", "d"]
+    );
+
+    svc.update(
+        dataset_id.clone(),
+        None,
+        Some(Delete::Where(Filter::RawSql("score > 4".to_string()))),
+    )
+    .unwrap();
+    assert_eq!(
+        selected_ids(&svc, &dataset_id, Filter::RawSql("TRUE".to_string())),
+        vec!["a", "c"]
+    );
+
+    // raw SQL can't be received from a client
+    let from_client: Result<Filter, _> = serde_json::from_str(r#"{"raw_sql": "TRUE"}"#);
+    assert!(from_client.is_err());
+}
@@ -0,0 +1,171 @@
+// remove lints that do not make sense in tests
+#![allow(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use arrow::array::{Array, Int64Array, RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatchIterator;
+use evalessence_api::app::DatasetId;
+use evalessence_api::dataset::{DatasetService, Filter, OrderDirection, SendableRecordBatchReader};
+use evalessence_core::datatset_core::DuckDbDatasetService;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+// `count` rows with ids "00000", "00001"..., in batches small enough for DuckDB's arrow scan
+fn numbered(count: i64) -> SendableRecordBatchReader {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("n", DataType::Int64, false),
+    ]));
+    let batches: Vec<_> = (0..count)
+        .step_by(2048)
+        .map(|start| {
+            let numbers: Vec<i64> = (start..count.min(start + 2048)).collect();
+            let ids: Vec<String> = numbers.iter().map(|n| format!("{n:05}")).collect();
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(StringArray::from(ids)),
+                    Arc::new(Int64Array::from(numbers)),
+                ],
+            )
+        })
+        .collect();
+    Box::new(RecordBatchIterator::new(batches, schema))
+}
+
+fn select_all(svc: &DuckDbDatasetService, dataset_id: &DatasetId) -> SendableRecordBatchReader {
+    svc.select(
+        dataset_id.clone(),
+        None,
+        Some(vec![("id".to_string(), OrderDirection::Asc)]),
+        None,
+        None,
+    )
+    .unwrap()
+}
+
+fn sum(reader: SendableRecordBatchReader) -> (usize, i64) {
+    let mut rows = 0;
+    let mut total = 0;
+    for batch in reader {
+        let batch = batch.unwrap();
+        let n = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        rows += n.len();
+        total += n.iter().flatten().sum::<i64>();
+    }
+    (rows, total)
+}
+
+#[test]
+fn open_readers_do_not_block_other_datasets() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let large = DatasetId::new("large").unwrap();
+    let small = DatasetId::new("small").unwrap();
+    // several DuckDB vectors, so the rows can't all come in the first batch
+    svc.update(large.clone(), Some(numbered(10_000)), None)
+        .unwrap();
+    svc.update(small.clone(), Some(numbered(3)), None).unwrap();
+
+    let mut reader = select_all(&svc, &large);
+    let first = reader.next().unwrap().unwrap();
+    assert!(first.num_rows() < 10_000);
+
+    // the reader is in the middle of its rows, the service is still usable
+    svc.update(small.clone(), Some(numbered(5)), None).unwrap();
+    assert_eq!(sum(select_all(&svc, &small)), (5, 10));
+
+    let (rows, _) = sum(reader);
+    assert_eq!(first.num_rows() + rows, 10_000);
+}
+
+#[test]
+fn readers_keep_the_rows_of_the_dataset_when_they_started() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let dataset_id = DatasetId::new("numbers").unwrap();
+    svc.update(dataset_id.clone(), Some(numbered(10_000)), None)
+        .unwrap();
+
+    let before = select_all(&svc, &dataset_id);
+    svc.update(dataset_id.clone(), Some(numbered(20_000)), None)
+        .unwrap();
+    let after = select_all(&svc, &dataset_id);
+
+    assert_eq!(sum(before), (10_000, 49_995_000));
+    assert_eq!(sum(after), (20_000, 199_990_000));
+}
+
+#[test]
+fn readers_can_be_dropped_before_their_end() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let dataset_id = DatasetId::new("numbers").unwrap();
+    svc.update(dataset_id.clone(), Some(numbered(10_000)), None)
+        .unwrap();
+
+    for _ in 0..20 {
+        let mut reader = select_all(&svc, &dataset_id);
+        assert!(reader.next().is_some());
+    }
+    svc.update(dataset_id.clone(), Some(numbered(5)), None)
+        .unwrap();
+    assert_eq!(sum(select_all(&svc, &dataset_id)).0, 10_000);
+}
+
+#[test]
+fn large_selects_do_not_block_the_dataset_while_they_are_read() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let dataset_id = DatasetId::new("numbers").unwrap();
+    svc.update(dataset_id.clone(), Some(numbered(200_000)), None)
+        .unwrap();
+
+    let mut reader = select_all(&svc, &dataset_id);
+    let first = reader.next().unwrap().unwrap();
+
+    // the dataset can be read and updated while the rows of the first select are read
+    let mut second = select_all(&svc, &dataset_id);
+    assert!(second.next().unwrap().unwrap().num_rows() > 0);
+    svc.update(dataset_id.clone(), Some(numbered(5)), None)
+        .unwrap();
+    let page = svc
+        .select_page(dataset_id.clone(), None, None, Some(10))
+        .unwrap();
+    assert_eq!(page.total_count, 200_000);
+
+    let (rows, _) = sum(reader);
+    assert_eq!(first.num_rows() + rows, 200_000);
+}
+
+#[test]
+fn queries_failing_after_their_first_rows_end_with_an_error() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let dataset_id = DatasetId::new("numbers").unwrap();
+    svc.update(dataset_id.clone(), Some(numbered(200_000)), None)
+        .unwrap();
+
+    // the cast only fails on the last rows, after the first batches of a streamed result
+    let failing =
+        Filter::RawSql("CAST(CASE WHEN n < 190000 THEN '1' ELSE 'x' END AS INTEGER) = 1".into());
+    let reader = svc
+        .select(dataset_id.clone(), Some(failing), None, None, None)
+        .unwrap();
+
+    // the rows read before the error are not returned as if they were the whole result
+    let batches: Vec<_> = reader.collect();
+    assert!(batches.len() > 1);
+    let Some(Err(e)) = batches.last() else {
+        panic!("the query has failed");
+    };
+    assert!(e.to_string().contains("the query failed"), "{e}");
+    assert!(batches[..batches.len() - 1].iter().all(Result::is_ok));
+}
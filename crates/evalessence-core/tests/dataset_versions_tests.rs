@@ -0,0 +1,297 @@
+// remove lints that do not make sense in tests
+#![allow(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+use arrow::array::{Array, RecordBatch, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatchIterator;
+use evalessence_api::app::DatasetId;
+use evalessence_api::dataset::{
+    DatasetError, DatasetOperation, DatasetService, Delete, SendableRecordBatchReader,
+    VersionRetention,
+};
+use evalessence_core::datatset_core::DuckDbDatasetService;
+use std::sync::Arc;
+use tempfile::tempdir;
+
+// use pretty_assertions for better test failure diffs
+use pretty_assertions::assert_eq;
+
+fn samples(ids: &[&str]) -> SendableRecordBatchReader {
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Utf8, false)]));
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![Arc::new(StringArray::from(ids.to_vec()))],
+    )
+    .unwrap();
+    Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema))
+}
+
+fn ids(reader: SendableRecordBatchReader) -> Vec<String> {
+    let mut ids = vec![];
+    for batch in reader {
+        let batch = batch.unwrap();
+        let column = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        for i in 0..column.len() {
+            ids.push(column.value(i).to_string());
+        }
+    }
+    ids.sort();
+    ids
+}
+
+fn version_ids(svc: &DuckDbDatasetService, dataset_id: &DatasetId, version: u64) -> Vec<String> {
+    ids(svc
+        .select_version(dataset_id.clone(), version, None, None, None, None)
+        .unwrap())
+}
+
+fn version_numbers(svc: &DuckDbDatasetService, dataset_id: &DatasetId) -> Vec<u64> {
+    svc.list_versions(dataset_id.clone())
+        .unwrap()
+        .iter()
+        .map(|v| v.version)
+        .collect()
+}
+
+#[test]
+fn every_update_creates_a_version_that_can_still_be_selected() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    assert!(svc.list_versions(golden.clone()).unwrap().is_empty());
+
+    let created = svc
+        .update(golden.clone(), Some(samples(&["a", "b"])), None)
+        .unwrap()
+        .unwrap();
+    let updated = svc
+        .update(
+            golden.clone(),
+            Some(samples(&["b", "c"])),
+            Some(Delete::ByIds(StringArray::from(vec!["a"]))),
+        )
+        .unwrap()
+        .unwrap();
+    // nothing to delete, no version
+    let unchanged = svc
+        .update(
+            golden.clone(),
+            None,
+            Some(Delete::ByIds(StringArray::from(vec!["a"]))),
+        )
+        .unwrap();
+    assert_eq!(unchanged, None);
+
+    assert_eq!(
+        (
+            created.version,
+            created.operation,
+            created.upserted_rows,
+            created.deleted_rows,
+            created.row_count
+        ),
+        (1, DatasetOperation::Create, 2, 0, 2)
+    );
+    assert_eq!(
+        (
+            updated.version,
+            updated.operation,
+            updated.upserted_rows,
+            updated.deleted_rows,
+            updated.row_count
+        ),
+        (2, DatasetOperation::Update, 2, 1, 2)
+    );
+    assert!(updated.created_at >= created.created_at);
+    assert_eq!(
+        svc.list_versions(golden.clone()).unwrap(),
+        vec![updated, created]
+    );
+
+    assert_eq!(version_ids(&svc, &golden, 1), vec!["a", "b"]);
+    assert_eq!(version_ids(&svc, &golden, 2), vec!["b", "c"]);
+    assert!(matches!(
+        svc.select_version(golden.clone(), 3, None, None, None, None),
+        Err(DatasetError::VersionNotFound { version: 3, .. })
+    ));
+
+    // versions are kept across service instances
+    let reopened = DuckDbDatasetService::new(td.path()).unwrap();
+    assert_eq!(version_ids(&reopened, &golden, 1), vec!["a", "b"]);
+}
+
+#[test]
+fn compact_removes_the_versions_not_kept_by_the_retention() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    for id in ["a", "b", "c", "d", "e"] {
+        svc.update(golden.clone(), Some(samples(&[id])), None)
+            .unwrap();
+    }
+
+    let removed = svc
+        .compact(
+            golden.clone(),
+            VersionRetention {
+                keep_last: 2,
+                keep_since: None,
+                keep_versions: vec![1],
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        removed.iter().map(|v| v.version).collect::<Vec<_>>(),
+        vec![3, 2]
+    );
+    assert_eq!(version_numbers(&svc, &golden), vec![5, 4, 1]);
+    assert_eq!(version_ids(&svc, &golden, 1), vec!["a"]);
+    assert!(matches!(
+        svc.select_version(golden.clone(), 2, None, None, None, None),
+        Err(DatasetError::VersionNotFound { version: 2, .. })
+    ));
+
+    // the latest version is always kept, and the next one follows it
+    svc.compact(golden.clone(), VersionRetention::default())
+        .unwrap();
+    assert_eq!(version_numbers(&svc, &golden), vec![5]);
+    svc.update(golden.clone(), Some(samples(&["f"])), None)
+        .unwrap();
+    assert_eq!(version_numbers(&svc, &golden), vec![6, 5]);
+    assert_eq!(
+        ids(svc.select(golden.clone(), None, None, None, None).unwrap()),
+        vec!["a", "b", "c", "d", "e", "f"]
+    );
+}
+
+#[test]
+fn datasets_saved_before_versions_become_their_first_version() {
+    let td = tempdir().unwrap();
+    let path = td.path().join("golden.parquet");
+    duckdb::Connection::open_in_memory()
+        .unwrap()
+        .execute_batch(&format!(
+            "COPY (SELECT 'a' AS id UNION ALL SELECT 'b') TO '{}' (FORMAT PARQUET)",
+            path.display()
+        ))
+        .unwrap();
+
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    assert!(svc.exists(golden.clone()).unwrap());
+
+    let versions = svc.list_versions(golden.clone()).unwrap();
+    assert_eq!(
+        versions
+            .iter()
+            .map(|v| (v.version, v.operation, v.row_count))
+            .collect::<Vec<_>>(),
+        vec![(1, DatasetOperation::Create, 2)]
+    );
+    assert!(!path.exists());
+    assert_eq!(version_ids(&svc, &golden, 1), vec!["a", "b"]);
+}
+
+fn parquet_ids(path: &std::path::Path) -> Vec<String> {
+    let conn = duckdb::Connection::open_in_memory().unwrap();
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT id FROM read_parquet('{}') ORDER BY id",
+            path.display()
+        ))
+        .unwrap();
+    stmt.query_map([], |row| row.get(0))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+}
+
+#[test]
+fn updates_only_save_the_rows_they_change() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    svc.update(golden.clone(), Some(samples(&["a", "b", "c", "d"])), None)
+        .unwrap();
+    svc.update(
+        golden.clone(),
+        Some(samples(&["e"])),
+        Some(Delete::ByIds(StringArray::from(vec!["a"]))),
+    )
+    .unwrap();
+
+    let dir = td.path().join("golden");
+    assert_eq!(
+        parquet_ids(&dir.join("1.parquet")),
+        vec!["a", "b", "c", "d"]
+    );
+    assert!(!dir.join("2.parquet").exists());
+    assert_eq!(parquet_ids(&dir.join("2.delta.parquet")), vec!["e"]);
+    assert_eq!(parquet_ids(&dir.join("2.deleted.parquet")), vec!["a"]);
+
+    // every version is rebuilt from the snapshot and the deltas, a snapshot is saved once
+    // there are too many deltas to read
+    let mut expected = vec!["b", "c", "d", "e"];
+    let updates = [
+        "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s", "t", "u",
+    ];
+    for (i, id) in updates.iter().enumerate() {
+        let delete = (i % 2 == 0).then(|| Delete::ByIds(StringArray::from(vec![expected[0]])));
+        svc.update(golden.clone(), Some(samples(&[id])), delete)
+            .unwrap();
+        if i % 2 == 0 {
+            expected.remove(0);
+        }
+        expected.push(id);
+    }
+    assert!(dir.join("18.parquet").exists());
+    assert!(!dir.join("17.parquet").exists());
+    assert_eq!(version_ids(&svc, &golden, 2), vec!["b", "c", "d", "e"]);
+    assert_eq!(version_ids(&svc, &golden, 3), vec!["c", "d", "e", "f"]);
+    assert_eq!(version_ids(&svc, &golden, 18), expected);
+
+    let reopened = DuckDbDatasetService::new(td.path()).unwrap();
+    assert_eq!(
+        ids(reopened.select(golden, None, None, None, None).unwrap()),
+        expected
+    );
+}
+
+#[test]
+fn compact_saves_the_kept_deltas_it_breaks_as_snapshots() {
+    let td = tempdir().unwrap();
+    let svc = DuckDbDatasetService::new(td.path()).unwrap();
+    let golden = DatasetId::new("golden").unwrap();
+    for id in ["a", "b", "c", "d"] {
+        svc.update(golden.clone(), Some(samples(&[id])), None)
+            .unwrap();
+    }
+
+    svc.compact(
+        golden.clone(),
+        VersionRetention {
+            keep_last: 1,
+            keep_since: None,
+            keep_versions: vec![3],
+        },
+    )
+    .unwrap();
+    assert_eq!(version_numbers(&svc, &golden), vec![4, 3]);
+    let dir = td.path().join("golden");
+    assert!(dir.join("3.parquet").exists());
+    assert!(!dir.join("3.delta.parquet").exists());
+    assert!(dir.join("4.delta.parquet").exists());
+    assert!(!dir.join("1.parquet").exists());
+    assert_eq!(version_ids(&svc, &golden, 3), vec!["a", "b", "c"]);
+    assert_eq!(version_ids(&svc, &golden, 4), vec!["a", "b", "c", "d"]);
+
+    // the latest version is saved as a snapshot once the versions before it are removed
+    svc.compact(golden.clone(), VersionRetention::default())
+        .unwrap();
+    assert!(dir.join("4.parquet").exists());
+    assert_eq!(version_ids(&svc, &golden, 4), vec!["a", "b", "c", "d"]);
+}